@@ -0,0 +1,375 @@
+use crate::{
+    comparison::{Comparison, ComparisonSet},
+    filters::Filter,
+    gradient::{self, HeatmapGradient},
+    heatmap::{HeatmapType, KillDeathMetric, KillDeathOptions, OccupancyOptions, KILL_DEATH_RATIO_RANGE},
+    heatmap_analyser::{Class, Team},
+    path::{PathColoring, PathOptions},
+    query,
+};
+use ab_glyph::{point, Font, FontRef, PxScale, ScaleFont};
+use image::{Rgb, RgbImage};
+use serde::{Deserialize, Serialize};
+
+const TEXT_FONT: &[u8] = include_bytes!("../fonts/Lato-Regular.ttf");
+const TEXT_COLOR: [f32; 3] = [1.0, 1.0, 1.0];
+const PANEL_COLOR: [f32; 3] = [0.0, 0.0, 0.0];
+const PANEL_ALPHA: f32 = 0.6;
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Annotations {
+    pub legend: bool,
+    pub scale_bar: bool,
+    pub title: bool,
+}
+
+impl Annotations {
+    pub fn any(&self) -> bool {
+        self.legend || self.scale_bar || self.title
+    }
+}
+
+// What the annotations describe, everything is already known once the heatmap is generated
+pub struct AnnotationInfo<'a> {
+    pub map: &'a str,
+    pub demo_count: usize,
+    pub death_count: usize,
+    pub filters: &'a [Filter],
+    pub heatmap_type: HeatmapType,
+    pub gradient: &'a HeatmapGradient,
+    pub comparison: Option<&'a Comparison>,
+    pub kill_death: Option<&'a KillDeathOptions>,
+    pub occupancy: Option<&'a OccupancyOptions>,
+    // In seconds, how much time the positions counted by the occupancy heatmap add up to
+    pub player_time: f32,
+    pub path: Option<&'a PathOptions>,
+    pub path_player: &'a str,
+    // Only counted for player paths, the deaths are in death_count
+    pub kill_count: usize,
+    pub gradient_max: Option<f32>,
+    pub units_per_pixel: f32,
+}
+
+pub fn annotate(image: &mut RgbImage, annotations: Annotations, info: &AnnotationInfo) {
+    if !annotations.any() {
+        return;
+    }
+    // sized relative to the image so that the annotations look the same at any resolution
+    let text_size = (image.height() as f32 / 40.0).max(12.0);
+    let mut painter = Painter {
+        image,
+        font: FontRef::try_from_slice(TEXT_FONT).expect("Invalid embedded font"),
+        text_size,
+    };
+    if annotations.title {
+        painter.draw_title(info);
+    }
+    if annotations.scale_bar {
+        painter.draw_scale_bar(info.units_per_pixel);
+    }
+    if annotations.legend {
+        painter.draw_legend(info);
+    }
+}
+
+struct Painter<'a> {
+    image: &'a mut RgbImage,
+    font: FontRef<'static>,
+    text_size: f32,
+}
+
+impl<'a> Painter<'a> {
+    fn margin(&self) -> f32 {
+        self.text_size * 0.75
+    }
+
+    fn padding(&self) -> f32 {
+        self.text_size * 0.5
+    }
+
+    fn line_height(&self, size: f32) -> f32 {
+        size * 1.3
+    }
+
+    fn draw_title(&mut self, info: &AnnotationInfo) {
+        let title_size = self.text_size * 1.4;
+        let max_width = self.image.width() as f32 * 0.45;
+        let mut lines = Vec::new();
+        if !info.map.is_empty() {
+            lines.push((info.map.to_string(), title_size));
+        }
+        let deaths = format!("{} death{}", info.death_count, if info.death_count == 1 { "" } else { "s" });
+        let counts = match (info.occupancy, info.path) {
+            (Some(_), _) => format!("{} of player time", format_duration(info.player_time)),
+            (None, Some(_)) => format!("{} kill{}, {}", info.kill_count, if info.kill_count == 1 { "" } else { "s" }, deaths),
+            (None, None) => deaths,
+        };
+        lines.push((
+            format!("{}, {} demo{}, {}", info.heatmap_type, info.demo_count, if info.demo_count == 1 { "" } else { "s" }, counts),
+            self.text_size,
+        ));
+        let mut descriptions = Vec::new();
+        match (info.occupancy, info.path) {
+            (Some(occupancy), _) => descriptions.push(describe_occupancy(occupancy)),
+            (None, Some(path)) => descriptions.push(format!("{}, {}, colored by {}", info.path_player, path.range, path.coloring.to_string().to_lowercase())),
+            (None, None) if info.filters.is_empty() => descriptions.push("No filters".to_string()),
+            (None, None) => descriptions.push(format!("Filters: {}", query::format_filters(info.filters))),
+        }
+        if let Some(comparison) = info.comparison {
+            descriptions.push(format!("A: {}", describe_set(&comparison.set_a)));
+            descriptions.push(format!("B: {}", describe_set(&comparison.set_b)));
+        }
+        if let Some(kill_death) = info.kill_death {
+            descriptions.push(format!(
+                "{} of {}, positions with fewer than {} kills + deaths left out",
+                kill_death.metric, kill_death.subject, kill_death.min_samples
+            ));
+        }
+        for description in descriptions {
+            for line in self.wrap(&description, self.text_size, max_width) {
+                lines.push((line, self.text_size));
+            }
+        }
+
+        let width = lines.iter().map(|(line, size)| self.text_width(line, *size)).fold(0.0, f32::max);
+        let height: f32 = lines.iter().map(|(_, size)| self.line_height(*size)).sum();
+        let (x, y) = (self.margin(), self.margin());
+        self.fill_rect(x, y, width + self.padding() * 2.0, height + self.padding() * 2.0, PANEL_COLOR, PANEL_ALPHA);
+        let mut line_y = y + self.padding();
+        for (line, size) in &lines {
+            self.draw_text(x + self.padding(), line_y, *size, line);
+            line_y += self.line_height(*size);
+        }
+    }
+
+    fn draw_scale_bar(&mut self, units_per_pixel: f32) {
+        if !units_per_pixel.is_finite() || units_per_pixel <= 0.0 {
+            return;
+        }
+        // the longest round length that fits in a fifth of the image
+        let target = units_per_pixel * self.image.width() as f32 * 0.2;
+        let magnitude = 10_f32.powf(target.log10().floor());
+        let units = [5.0, 2.0, 1.0].iter().map(|step| step * magnitude).find(|units| *units <= target).unwrap_or(magnitude);
+        let bar_width = units / units_per_pixel;
+        let bar_height = (self.text_size * 0.3).max(2.0);
+        let label = format!("{} Hammer units", units);
+
+        let width = bar_width.max(self.text_width(&label, self.text_size));
+        let height = self.line_height(self.text_size) + bar_height;
+        let x = self.margin();
+        let y = self.image.height() as f32 - self.margin() - height - self.padding() * 2.0;
+        self.fill_rect(x, y, width + self.padding() * 2.0, height + self.padding() * 2.0, PANEL_COLOR, PANEL_ALPHA);
+        self.draw_text(x + self.padding(), y + self.padding(), self.text_size, &label);
+        self.fill_rect(
+            x + self.padding(),
+            y + self.padding() + self.line_height(self.text_size),
+            bar_width,
+            bar_height,
+            TEXT_COLOR,
+            1.0,
+        );
+    }
+
+    fn draw_legend(&mut self, info: &AnnotationInfo) {
+        let (caption, labels, gradient) = if info.heatmap_type == HeatmapType::Lines {
+            (
+                "Killer to victim".to_string(),
+                ["Killer".to_string(), String::new(), "Victim".to_string()],
+                info.gradient.line_gradient(),
+            )
+        } else if let Some(path) = info.path {
+            let labels = match path.coloring {
+                PathColoring::Time => ["Start".to_string(), String::new(), "End".to_string()],
+                PathColoring::Health => ["0%".to_string(), "50%".to_string(), "100%".to_string()],
+            };
+            (path.coloring.to_string(), labels, info.gradient.line_gradient())
+        } else if let Some(kill_death) = info.kill_death {
+            let gradient_max = match info.gradient_max {
+                Some(gradient_max) => gradient_max,
+                None => return,
+            };
+            let labels = match kill_death.metric {
+                KillDeathMetric::Ratio => [format!("1/{}", KILL_DEATH_RATIO_RANGE), "1".to_string(), format!("{}+", KILL_DEATH_RATIO_RANGE)],
+                KillDeathMetric::Net => [format!("-{}", format_count(gradient_max)), "0".to_string(), format!("+{}", format_count(gradient_max))],
+            };
+            (format!("{} of {}", kill_death.metric, kill_death.subject), labels, gradient::diverging_gradient())
+        } else if let Some(comparison) = info.comparison {
+            let gradient_max = match info.gradient_max {
+                Some(gradient_max) => gradient_max,
+                None => return,
+            };
+            let caption = format!("Deaths, A - B ({})", comparison.normalization.to_string().to_lowercase());
+            let labels = [format!("-{}", format_count(gradient_max)), "0".to_string(), format!("+{}", format_count(gradient_max))];
+            (caption, labels, gradient::diverging_gradient())
+        } else {
+            let gradient_max = match info.gradient_max {
+                Some(gradient_max) => gradient_max,
+                None => return,
+            };
+            let labels = ["0".to_string(), format_count(gradient_max / 2.0), format!("{}+", format_count(gradient_max))];
+            let caption = if info.occupancy.is_some() { "Seconds spent" } else { "Deaths" };
+            (caption.to_string(), labels, info.gradient.heatmap_gradient())
+        };
+
+        let bar_width = (self.image.width() as f32 * 0.25).round();
+        let bar_height = self.text_size;
+        let width = bar_width.max(self.text_width(&caption, self.text_size));
+        let height = self.line_height(self.text_size) * 2.0 + bar_height;
+        let x = self.image.width() as f32 - self.margin() - width - self.padding() * 2.0;
+        let y = self.image.height() as f32 - self.margin() - height - self.padding() * 2.0;
+        self.fill_rect(x, y, width + self.padding() * 2.0, height + self.padding() * 2.0, PANEL_COLOR, PANEL_ALPHA);
+        self.draw_text(x + self.padding(), y + self.padding(), self.text_size, &caption);
+
+        let bar_x = x + self.padding();
+        let bar_y = y + self.padding() + self.line_height(self.text_size);
+        for column in 0..bar_width as u32 {
+            let color = gradient.get(column as f32 / (bar_width - 1.0).max(1.0));
+            self.fill_rect(bar_x + column as f32, bar_y, 1.0, bar_height, [color.red, color.green, color.blue], color.alpha);
+        }
+
+        let labels_y = bar_y + bar_height + (self.line_height(self.text_size) - self.text_size) / 2.0;
+        for (index, label) in labels.iter().enumerate() {
+            let label_width = self.text_width(label, self.text_size);
+            let label_x = (bar_x + bar_width * index as f32 / 2.0 - label_width / 2.0).min(bar_x + bar_width - label_width).max(bar_x);
+            self.draw_text(label_x, labels_y, self.text_size, label);
+        }
+    }
+
+    fn wrap(&self, text: &str, size: f32, max_width: f32) -> Vec<String> {
+        let mut lines = Vec::new();
+        let mut line = String::new();
+        for word in text.split(' ') {
+            let candidate = if line.is_empty() { word.to_string() } else { format!("{} {}", line, word) };
+            if !line.is_empty() && self.text_width(&candidate, size) > max_width {
+                lines.push(std::mem::replace(&mut line, word.to_string()));
+            } else {
+                line = candidate;
+            }
+        }
+        lines.push(line);
+        lines
+    }
+
+    fn text_width(&self, text: &str, size: f32) -> f32 {
+        let font = self.font.as_scaled(PxScale::from(size));
+        let mut width = 0.0;
+        let mut previous = None;
+        for c in text.chars() {
+            let glyph_id = font.glyph_id(c);
+            if let Some(previous) = previous {
+                width += font.kern(previous, glyph_id);
+            }
+            width += font.h_advance(glyph_id);
+            previous = Some(glyph_id);
+        }
+        width
+    }
+
+    fn draw_text(&mut self, x: f32, y: f32, size: f32, text: &str) {
+        let scale = PxScale::from(size);
+        let baseline = y + self.font.as_scaled(scale).ascent();
+        let mut caret = x;
+        let mut previous = None;
+        for c in text.chars() {
+            let scaled_font = self.font.as_scaled(scale);
+            let glyph_id = scaled_font.glyph_id(c);
+            if let Some(previous) = previous {
+                caret += scaled_font.kern(previous, glyph_id);
+            }
+            let glyph = glyph_id.with_scale_and_position(scale, point(caret, baseline));
+            caret += scaled_font.h_advance(glyph_id);
+            previous = Some(glyph_id);
+            if let Some(outlined_glyph) = self.font.outline_glyph(glyph) {
+                let bounds = outlined_glyph.px_bounds();
+                let image = &mut *self.image;
+                outlined_glyph.draw(|glyph_x, glyph_y, coverage| {
+                    blend_pixel(image, bounds.min.x as i32 + glyph_x as i32, bounds.min.y as i32 + glyph_y as i32, TEXT_COLOR, coverage);
+                });
+            }
+        }
+    }
+
+    fn fill_rect(&mut self, x: f32, y: f32, width: f32, height: f32, color: [f32; 3], alpha: f32) {
+        let (x, y) = (x.round() as i32, y.round() as i32);
+        for pixel_y in y..y + height.round() as i32 {
+            for pixel_x in x..x + width.round() as i32 {
+                blend_pixel(self.image, pixel_x, pixel_y, color, alpha);
+            }
+        }
+    }
+}
+
+fn blend_pixel(image: &mut RgbImage, x: i32, y: i32, color: [f32; 3], alpha: f32) {
+    if x < 0 || y < 0 || x >= image.width() as i32 || y >= image.height() as i32 {
+        return;
+    }
+    let alpha = alpha.clamp(0.0, 1.0);
+    let Rgb(channels) = image.get_pixel_mut(x as u32, y as u32);
+    for (channel, color) in channels.iter_mut().zip(color) {
+        *channel = ((alpha * color + (1.0 - alpha) * (*channel as f32 / 255.0)) * 255.0) as u8;
+    }
+}
+
+fn describe_set(set: &ComparisonSet) -> String {
+    let filters = if set.filters.is_empty() {
+        "all deaths".to_string()
+    } else {
+        query::format_filters(&set.filters)
+    };
+    if set.demos.is_empty() {
+        filters
+    } else {
+        format!("{} in {}", filters, set.demos.join(", "))
+    }
+}
+
+fn describe_occupancy(occupancy: &OccupancyOptions) -> String {
+    let team = match occupancy.team {
+        Some(Team::Red) => "RED",
+        Some(Team::Blu) => "BLU",
+        _ => "All",
+    };
+    let classes: Vec<_> = CLASSES.iter().filter(|(class, _)| occupancy.classes[*class as usize]).map(|(_, name)| *name).collect();
+    let classes = if classes.len() == CLASSES.len() { "players".to_string() } else { classes.join(", ") };
+    let mut description = format!("{} {}", team, classes);
+    if !occupancy.players.is_empty() {
+        description.push_str(&format!(" among {}", occupancy.players.join(", ")));
+    }
+    if occupancy.alive_only {
+        description.push_str(", alive only");
+    }
+    description
+}
+
+const CLASSES: [(Class, &str); 9] = [
+    (Class::Scout, "scouts"),
+    (Class::Soldier, "soldiers"),
+    (Class::Pyro, "pyros"),
+    (Class::Demoman, "demomen"),
+    (Class::Heavy, "heavies"),
+    (Class::Engineer, "engineers"),
+    (Class::Medic, "medics"),
+    (Class::Sniper, "snipers"),
+    (Class::Spy, "spies"),
+];
+
+fn format_duration(seconds: f32) -> String {
+    let minutes = (seconds / 60.0).round() as u32;
+    if minutes >= 60 {
+        format!("{}h{:02}", minutes / 60, minutes % 60)
+    } else {
+        format!("{} min", minutes)
+    }
+}
+
+// per demo and per round rates are usually below 1
+fn format_count(count: f32) -> String {
+    if count >= 10.0 {
+        format!("{:.0}", count)
+    } else if count >= 1.0 {
+        format!("{:.1}", count)
+    } else {
+        format!("{:.2}", count)
+    }
+}
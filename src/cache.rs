@@ -0,0 +1,88 @@
+use crate::{error::DemoError, heatmap_analyser::HeatmapAnalysis};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    fs, io,
+    path::PathBuf,
+    process,
+    sync::atomic::{AtomicU64, Ordering},
+};
+use tf_demo_parser::demo::header::Header;
+
+// Bump this whenever the output of HeatmapAnalyser changes, so the analyses cached by older versions get parsed again
+pub const CACHE_FORMAT_VERSION: u32 = 8;
+
+const COLDMAPS_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+// Tells apart the temporary files of the entries being written at the same time, e.g. for two copies of a demo
+static TEMP_FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedAnalysis {
+    pub header: Header,
+    // Corrupted demos are cached too, with what could be analysed
+    pub error: Option<DemoError>,
+    pub heatmap_analysis: HeatmapAnalysis,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheEntry<A> {
+    format_version: u32,
+    coldmaps_version: String,
+    analysis: A,
+}
+
+#[derive(Debug, Clone)]
+pub struct AnalysisCache {
+    dir: PathBuf,
+}
+
+impl AnalysisCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn default_location() -> Option<Self> {
+        dirs::cache_dir().map(|dir| Self::new(dir.join("coldmaps").join("analyses")))
+    }
+
+    pub fn hash_demo(file: &[u8]) -> String {
+        format!("{:x}", Sha256::digest(file))
+    }
+
+    pub fn get(&self, hash: &str) -> Option<CachedAnalysis> {
+        let file = fs::read(self.entry_path(hash)).ok()?;
+        let entry: CacheEntry<CachedAnalysis> = serde_json::from_slice(&file).ok()?;
+        if entry.format_version != CACHE_FORMAT_VERSION || entry.coldmaps_version != COLDMAPS_VERSION {
+            return None;
+        }
+        Some(entry.analysis)
+    }
+
+    pub fn insert(&self, hash: &str, analysis: &CachedAnalysis) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let entry = CacheEntry {
+            format_version: CACHE_FORMAT_VERSION,
+            coldmaps_version: COLDMAPS_VERSION.into(),
+            analysis,
+        };
+        let serialized = serde_json::to_vec(&entry)?;
+        // Write to a temporary file first so an interrupted write can't leave a truncated entry behind
+        let temp_path = self
+            .dir
+            .join(format!("{}.{}-{}.tmp", hash, process::id(), TEMP_FILE_COUNTER.fetch_add(1, Ordering::Relaxed)));
+        fs::write(&temp_path, serialized)?;
+        fs::rename(temp_path, self.entry_path(hash))
+    }
+
+    pub fn clear(&self) -> io::Result<()> {
+        match fs::remove_dir_all(&self.dir) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+
+    fn entry_path(&self, hash: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", hash))
+    }
+}
@@ -0,0 +1,135 @@
+use crate::{heatmap::LEVELOVERVIEW_SCALE_MULTIPLIER, heatmap_analyser::HeatmapAnalysis};
+use image::{Rgb, RgbImage};
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+use tf_demo_parser::demo::vector::Vector;
+
+// Size of the generated backgrounds when there is no level overview to draw on
+pub const BACKGROUND_WIDTH: u32 = 1920;
+pub const BACKGROUND_HEIGHT: u32 = 1080;
+pub const BACKGROUND_COLOR: [u8; 3] = [43, 45, 49];
+// Fraction of the data's size left around it when fitting the camera to the data
+const DATA_MARGIN: f32 = 0.05;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum CameraFit {
+    // Use the position and scale of the job
+    Manual,
+    // The m_WorldMins / m_WorldMaxs of the map, the whole playable space
+    WorldBounds,
+    // The bounding box of the deaths and of the players' positions
+    Data,
+}
+
+impl CameraFit {
+    pub const ALL: [CameraFit; 3] = [CameraFit::Manual, CameraFit::WorldBounds, CameraFit::Data];
+}
+
+impl Default for CameraFit {
+    fn default() -> Self {
+        Self::Manual
+    }
+}
+
+impl Display for CameraFit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CameraFit::Manual => write!(f, "Manual"),
+            CameraFit::WorldBounds => write!(f, "Fit to world bounds"),
+            CameraFit::Data => write!(f, "Fit to data"),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Background {
+    LevelOverview,
+    Blank,
+    // Blank with lines every power of two Hammer units
+    Grid,
+}
+
+impl Background {
+    pub const ALL: [Background; 3] = [Background::LevelOverview, Background::Blank, Background::Grid];
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Self::LevelOverview
+    }
+}
+
+impl Display for Background {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Background::LevelOverview => write!(f, "Level overview"),
+            Background::Blank => write!(f, "Blank"),
+            Background::Grid => write!(f, "Grid"),
+        }
+    }
+}
+
+pub fn blank_image(width: u32, height: u32) -> RgbImage {
+    RgbImage::from_pixel(width, height, Rgb(BACKGROUND_COLOR))
+}
+
+// cl_showpos camera (x, y, scale) that shows the whole area on an image of that size, None when there is nothing to fit
+pub fn fit_camera(fit: CameraFit, analyses: &[&HeatmapAnalysis], width: u32, height: u32) -> Option<(f32, f32, f32)> {
+    let (min, max) = match fit {
+        CameraFit::Manual => return None,
+        CameraFit::WorldBounds => bounds(
+            analyses
+                .iter()
+                .filter_map(|heatmap_analysis| heatmap_analysis.world.as_ref())
+                .flat_map(|world| [world.boundary_min, world.boundary_max]),
+        )?,
+        CameraFit::Data => {
+            let (min, max) = bounds(analyses.iter().flat_map(|heatmap_analysis| {
+                let deaths = heatmap_analysis.deaths.iter().flat_map(|death| {
+                    [&death.victim_entity_state, &death.killer_entity_state, &death.assister_entity_state]
+                        .into_iter()
+                        .filter_map(|entity| entity.as_ref().map(|entity| entity.position))
+                        .chain(death.sentry_position)
+                });
+                deaths.chain(heatmap_analysis.position_samples.iter().map(|sample| sample.position))
+            }))?;
+            let margin = (max.x - min.x).max(max.y - min.y) * DATA_MARGIN;
+            (
+                Vector {
+                    x: min.x - margin,
+                    y: min.y - margin,
+                    z: min.z,
+                },
+                Vector {
+                    x: max.x + margin,
+                    y: max.y + margin,
+                    z: max.z,
+                },
+            )
+        }
+    };
+    let aspect_ratio = width as f32 / height as f32;
+    let half_height = ((max.y - min.y) / 2.0).max((max.x - min.x) / 2.0 / aspect_ratio);
+    if !half_height.is_normal() {
+        return None;
+    }
+    Some(((min.x + max.x) / 2.0, (min.y + max.y) / 2.0, half_height / LEVELOVERVIEW_SCALE_MULTIPLIER))
+}
+
+fn bounds(positions: impl Iterator<Item = Vector>) -> Option<(Vector, Vector)> {
+    positions.fold(None, |bounds, position| match bounds {
+        None => Some((position, position)),
+        Some((min, max)) => Some((
+            Vector {
+                x: min.x.min(position.x),
+                y: min.y.min(position.y),
+                z: min.z.min(position.z),
+            },
+            Vector {
+                x: max.x.max(position.x),
+                y: max.y.max(position.y),
+                z: max.z.max(position.z),
+            },
+        )),
+    })
+}
@@ -0,0 +1,566 @@
+use coldmaps::{
+    annotations::Annotations,
+    cache::AnalysisCache,
+    camera::{Background, CameraFit},
+    comparison::{Comparison, Normalization},
+    export::{self, DeathRecord, ExportFormat},
+    filters::FilterTrait,
+    gradient::{self, GradientPreset, HeatmapGradient},
+    heatmap::{CoordsType, HeatmapType, Kernel, KillDeathMetric, KillDeathSubject},
+    heatmap_analyser::{Team, DEFAULT_SAMPLE_INTERVAL},
+    job::HeatmapJob,
+    maps::{base_name, MapDatabase, MapEntry},
+    path::{PathColoring, PathRange},
+    query,
+};
+use image::io::Reader;
+use std::{error::Error, fs, path::PathBuf, time::Instant};
+
+const USAGE: &str = "Usage: coldmaps --cli [OPTIONS] <DEMOS>...
+
+Renders a heatmap without opening a window. DEMOS can be paths or glob patterns (e.g. \"demos/*.dem\").
+
+Options:
+  -j, --job <PATH>           Job file saved from the GUI, the other options override its settings
+  -i, --image <PATH>         Level overview screenshot (default: a grid background without a job file)
+  -o, --output <PATH>        Output image (default: heatmap.png), a .svg output draws the lines or the
+                             victim / killer positions as vectors with a tooltip for each death,
+                             a .html output is an interactive report of the deaths that works offline
+  -x <X>                     Camera x position (required without a job file or --fit)
+  -y <Y>                     Camera y position (required without a job file or --fit)
+  -s, --scale <SCALE>        cl_leveloverview scale (required without a job file or --fit)
+  --coords <TYPE>            Coordinates origin: showpos or console (default: showpos)
+  --map <NAME>               Take the camera and the level overview from the map database, another
+                             version of the map is used when there's no entry for that exact one.
+                             Only the demos on that map (any version of it) are used, required when they are on several maps
+  --save-map <NAME>          Save the camera and the level overview to the map database under that name
+  --map-db <DIR>             Map database directory (default: coldmaps/maps in your user data directory)
+  --fit <MODE>               Compute the camera instead: world (the map's world bounds) or data (the deaths
+                             and player positions), for maps without a known camera
+  --background <BACKGROUND>  What to draw on: overview, blank or grid (a line every power of two Hammer
+                             units), blank and grid don't need an image (default: overview)
+  --type <TYPE>              Heatmap type: victim, killer, assister, lines, kd (kills vs deaths), occupancy or path
+                             (default: victim)
+  --kd-subject <SUBJECT>     Whose kills and deaths the kd type compares: red, blu or a SteamID (default: red)
+  --kd-metric <METRIC>       What the kd type shows: ratio or net (kills - deaths) (default: ratio)
+  --min-samples <COUNT>      Leave out the positions with fewer kills + deaths with the kd type (default: 3)
+  --type occupancy           Where the players spend their time rather than where they die, the filters
+                             don't apply to it, these options select the players instead:
+  --occupancy-team <TEAM>    Only count the players of a team: red or blu (default: both)
+  --occupancy-class <CLASS>  Only count some classes, e.g. soldier,demoman (default: all)
+  --occupancy-player <ID>    Only count a player (SteamID), can be repeated (default: everyone)
+  --include-dead             Also count the positions of the dead players
+  --sample-interval <SECS>   How often the positions of the players are recorded (default: 0.5)
+  --type path                Where one player went, with their spawns, kills and deaths, the filters don't
+                             apply to it either:
+  --path-player <ID>         The player (SteamID), required with the path type
+  --path-life <N>            Only draw their nth life (default: the whole demo)
+  --path-ticks <START-END>   Only draw the ticks between START and END (default: the whole demo)
+  --path-color <COLORING>    What the path's colors show: time or health (default: time)
+  --radius <RADIUS>          Heatmap radius in screen pixels, 1 to 100 (default: 50)
+  --kernel <KERNEL>          Density estimator: gaussian, epanechnikov, uniform or hex (default: gaussian)
+  --bandwidth <UNITS>        Kernel bandwidth in Hammer units, replaces the radius so the heatmap
+                             looks the same whatever the resolution of the screenshot
+  --intensity <INTENSITY>    Heatmap intensity, 1 to 100, or auto (default: auto)
+  --desaturate <PERCENT>     Desaturate the level overview, 0 to 100 (default: 0)
+  --no-sentry-position       Don't use the sentry position for sentry kills
+  --legend                   Draw a color legend on the image
+  --scale-bar                Draw a scale bar in Hammer units on the image
+  --title                    Draw the map, demo and death counts and the filters on the image
+  --gradient <GRADIENT>      Color gradient: classic, viridis, magma, colorblind, monochrome or the path
+                             of a file with one \"<position> <#rrggbb[aa]>\" stop per line (default: classic)
+  -f, --filter <FILTER>      Only keep the deaths matching the filter, can be repeated
+                             (added to the filters of the job file)
+  --compare-a <FILTER>       Render set A minus set B instead, set A keeps the deaths matching these
+  --compare-b <FILTER>       filters (on top of the other ones), can be repeated
+  --demos-a <PATTERN>        Only take set A / set B from the demos whose file name matches the pattern,
+  --demos-b <PATTERN>        can be repeated (default: every demo)
+  --normalize <MODE>         How the sets are compared: raw, demo (deaths per demo) or round (deaths per round)
+                             (default: raw)
+  --export-deaths <PATH>     Also write the matching deaths to a .csv or newline-delimited .json file
+  --no-cache                 Parse every demo again instead of using the cached analyses
+  -h, --help                 Print this message
+
+Filters:
+  Filters are queries combining conditions with and, or, not and parentheses, e.g.
+    -f \"killer.class in (scout, soldier) and distance2d < 512 and not suicide and round >= 2\"
+  killer.team, victim.team, assister.team = red|blu
+  killer.class, victim.class, assister.class = scout|soldier|pyro|demoman|heavy|engineer|medic|sniper|spy
+  weapon = rocket_launchers|melee|hitscan|projectile|sentry|<kill icon>
+    quote a kill icon named like a group: weapon = \"sentry\"
+  killer, victim, assister = <SteamID> ([U:1:123], STEAM_0:1:61 or 76561197960265851)
+    these also accept != and in (<value>, ...)
+  killer.z, victim.z, distance2d, distance3d, round, match_time, round_time, time_alive <op> <number>
+    where <op> is one of = != > < >= <=, times are in seconds
+  suicide, posthumous, during_round, sentry";
+
+struct CliOptions {
+    demos: Vec<PathBuf>,
+    use_cache: bool,
+    sample_interval: f32,
+    export_deaths: Option<PathBuf>,
+    output: PathBuf,
+    map: Option<String>,
+    job: HeatmapJob,
+}
+
+pub(crate) fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let options = match parse_args(args)? {
+        Some(options) => options,
+        None => {
+            println!("{}", USAGE);
+            return Ok(());
+        }
+    };
+
+    let demo_count = options.demos.len();
+    eprintln!("Processing {} demo{}...", demo_count, if demo_count > 1 { "s" } else { "" });
+    let chrono = Instant::now();
+    let cache = AnalysisCache::default_location().filter(|_| options.use_cache);
+    let outputs = coldmaps::process_demos_with_interval(options.demos, cache.as_ref(), options.sample_interval);
+    let mut analyses = Vec::with_capacity(outputs.len());
+    for demo in outputs {
+        for problem in demo.problems() {
+            eprintln!("{}: {}", demo.path.to_string_lossy(), problem);
+        }
+        if let Some(heatmap_analysis) = demo.heatmap_analysis {
+            let file_name = demo.path.file_name().unwrap_or_default().to_string_lossy().to_string();
+            analyses.push((file_name, demo.map, heatmap_analysis));
+        }
+    }
+    let mut maps: Vec<_> = analyses.iter().map(|(_, map, _)| map.clone()).collect();
+    maps.sort_unstable();
+    maps.dedup();
+    // the coordinates of different maps don't match, they can't be drawn on the same image
+    if maps.len() > 1 && options.map.is_none() {
+        return Err(format!("The demos are on different maps ({}), choose one with --map <NAME>", maps.join(", ")).into());
+    }
+    if let Some(map) = &options.map {
+        // any version of the map will do, like for the map database entry
+        let same_map = |demo_map: &str| base_name(demo_map).eq_ignore_ascii_case(base_name(map));
+        let (kept, left_out): (Vec<_>, Vec<_>) = maps.iter().cloned().partition(|demo_map| same_map(demo_map));
+        if kept.is_empty() {
+            return Err(format!("None of the demos are on {} (they are on {})", map, maps.join(", ")).into());
+        }
+        if !left_out.is_empty() {
+            analyses.retain(|(_, demo_map, _)| same_map(demo_map));
+            eprintln!("Only using the demos on {}, the other ones are on {}", kept.join(", "), left_out.join(", "));
+        }
+    }
+    let death_count: usize = analyses.iter().map(|(_, _, heatmap_analysis)| heatmap_analysis.deaths.len()).sum();
+    eprintln!(
+        "Loaded {} death{} from {} demo{} in {:.2}s",
+        death_count,
+        if death_count > 1 { "s" } else { "" },
+        analyses.len(),
+        if analyses.len() > 1 { "s" } else { "" },
+        chrono.elapsed().as_secs_f32()
+    );
+
+    if let Some(path) = &options.export_deaths {
+        let format = ExportFormat::from_path(path).ok_or("Unsupported file extension for the deaths export, use .csv or .json")?;
+        let records = analyses.iter().flat_map(|(file_name, map, heatmap_analysis)| {
+            heatmap_analysis
+                .deaths
+                .iter()
+                .filter(|death| options.job.filters.iter().all(|filter| filter.apply(death)))
+                .map(move |death| DeathRecord::new(file_name, map, heatmap_analysis, death))
+        });
+        export::export_deaths(path, format, records)?;
+        eprintln!("Deaths exported: {}", path.to_string_lossy());
+    }
+
+    let demos: Vec<_> = analyses
+        .iter()
+        .map(|(file_name, map, heatmap_analysis)| (file_name.as_str(), map.as_str(), heatmap_analysis))
+        .collect();
+    let extension = options.output.extension().unwrap_or_default().to_string_lossy().to_lowercase();
+    let kind = match extension.as_str() {
+        "svg" => {
+            fs::write(&options.output, options.job.render_svg(&demos)?)?;
+            "SVG"
+        }
+        "html" | "htm" => {
+            fs::write(&options.output, options.job.render_report(&demos)?)?;
+            "Report"
+        }
+        _ => {
+            options.job.render(&demos)?.save(&options.output)?;
+            "Image"
+        }
+    };
+    eprintln!("{} saved: {}", kind, options.output.to_string_lossy());
+    Ok(())
+}
+
+fn parse_args(args: &[String]) -> Result<Option<CliOptions>, Box<dyn Error>> {
+    let mut demo_patterns = Vec::new();
+    let mut job_path = None;
+    let mut image = None;
+    let mut output = PathBuf::from("heatmap.png");
+    let mut pos_x = None;
+    let mut pos_y = None;
+    let mut scale = None;
+    let mut coords_type = None;
+    let mut map_name = None;
+    let mut save_map = None;
+    let mut map_database = MapDatabase::default_location();
+    let mut camera_fit = None;
+    let mut background = None;
+    let mut heatmap_type = None;
+    let mut radius = None;
+    let mut kernel = None;
+    let mut bandwidth = None;
+    let mut intensity = None;
+    let mut desaturate = None;
+    let mut use_sentry_position = None;
+    let mut gradient = None;
+    let mut legend = false;
+    let mut scale_bar = false;
+    let mut title = false;
+    let mut comparison: Option<Comparison> = None;
+    let mut kill_death_subject = None;
+    let mut kill_death_metric = None;
+    let mut min_samples = None;
+    let mut occupancy_team = None;
+    let mut occupancy_classes = None;
+    let mut occupancy_players = Vec::new();
+    let mut include_dead = false;
+    let mut sample_interval = DEFAULT_SAMPLE_INTERVAL;
+    let mut path_player = None;
+    let mut path_range = None;
+    let mut path_coloring = None;
+    let mut normalization = None;
+    let mut filters = Vec::new();
+    let mut use_cache = true;
+    let mut export_deaths = None;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| args.next().ok_or_else(|| format!("Missing value for {}", name));
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(None),
+            "-j" | "--job" => job_path = Some(PathBuf::from(value(arg)?)),
+            "-i" | "--image" => image = Some(PathBuf::from(value(arg)?)),
+            "-o" | "--output" => output = PathBuf::from(value(arg)?),
+            "-x" => pos_x = Some(parse_number(arg, value(arg)?)?),
+            "-y" => pos_y = Some(parse_number(arg, value(arg)?)?),
+            "-s" | "--scale" => scale = Some(parse_number(arg, value(arg)?)?),
+            "--coords" => {
+                coords_type = Some(match value(arg)?.to_lowercase().as_str() {
+                    "showpos" | "cl_showpos" => CoordsType::ShowPos,
+                    "console" => CoordsType::Console,
+                    other => return Err(format!("Unknown coordinates origin \"{}\"", other).into()),
+                })
+            }
+            "--map" => map_name = Some(value(arg)?.clone()),
+            "--save-map" => save_map = Some(value(arg)?.clone()),
+            "--map-db" => map_database = Some(MapDatabase::new(value(arg)?)),
+            "--fit" => {
+                camera_fit = Some(match value(arg)?.to_lowercase().as_str() {
+                    "world" => CameraFit::WorldBounds,
+                    "data" => CameraFit::Data,
+                    "manual" => CameraFit::Manual,
+                    other => return Err(format!("Unknown camera fit \"{}\"", other).into()),
+                })
+            }
+            "--background" => {
+                background = Some(match value(arg)?.to_lowercase().as_str() {
+                    "overview" => Background::LevelOverview,
+                    "blank" => Background::Blank,
+                    "grid" => Background::Grid,
+                    other => return Err(format!("Unknown background \"{}\"", other).into()),
+                })
+            }
+            "--type" => {
+                heatmap_type = Some(match value(arg)?.to_lowercase().as_str() {
+                    "victim" => HeatmapType::VictimPosition,
+                    "killer" => HeatmapType::KillerPosition,
+                    "assister" => HeatmapType::AssisterPosition,
+                    "lines" => HeatmapType::Lines,
+                    "kd" => HeatmapType::KillDeath,
+                    "occupancy" => HeatmapType::Occupancy,
+                    "path" => HeatmapType::Path,
+                    other => return Err(format!("Unknown heatmap type \"{}\"", other).into()),
+                })
+            }
+            "--kd-subject" => {
+                let input = value(arg)?;
+                kill_death_subject = Some(match input.to_lowercase().as_str() {
+                    "red" => KillDeathSubject::Team(Team::Red),
+                    "blu" | "blue" => KillDeathSubject::Team(Team::Blu),
+                    _ => KillDeathSubject::Player(query::normalize_steam_id(input).ok_or_else(|| format!("Invalid value for {}: \"{}\"", arg, input))?),
+                })
+            }
+            "--kd-metric" => {
+                kill_death_metric = Some(match value(arg)?.to_lowercase().as_str() {
+                    "ratio" => KillDeathMetric::Ratio,
+                    "net" => KillDeathMetric::Net,
+                    other => return Err(format!("Unknown kd metric \"{}\"", other).into()),
+                })
+            }
+            "--min-samples" => min_samples = Some(parse_number(arg, value(arg)?)?),
+            "--occupancy-team" => {
+                occupancy_team = Some(match value(arg)?.to_lowercase().as_str() {
+                    "red" => Team::Red,
+                    "blu" | "blue" => Team::Blu,
+                    other => return Err(format!("Unknown team \"{}\"", other).into()),
+                })
+            }
+            "--occupancy-class" => {
+                let mut classes = [false; 10];
+                for name in value(arg)?.split(',') {
+                    classes[query::class_index(name.trim()).ok_or_else(|| format!("Unknown class \"{}\"", name))?] = true;
+                }
+                occupancy_classes = Some(classes);
+            }
+            "--occupancy-player" => {
+                let input = value(arg)?;
+                occupancy_players.push(query::normalize_steam_id(input).ok_or_else(|| format!("Invalid value for {}: \"{}\"", arg, input))?);
+            }
+            "--include-dead" => include_dead = true,
+            "--sample-interval" => sample_interval = parse_number(arg, value(arg)?)?,
+            "--path-player" => {
+                let input = value(arg)?;
+                path_player = Some(query::normalize_steam_id(input).ok_or_else(|| format!("Invalid value for {}: \"{}\"", arg, input))?);
+            }
+            "--path-life" => path_range = Some(PathRange::Life(parse_number(arg, value(arg)?)?)),
+            "--path-ticks" => {
+                let input = value(arg)?;
+                let (start, end) = input.split_once('-').ok_or_else(|| format!("Invalid value for {}: \"{}\"", arg, input))?;
+                path_range = Some(PathRange::Ticks {
+                    start: parse_number(arg, start.trim())?,
+                    end: parse_number(arg, end.trim())?,
+                });
+            }
+            "--path-color" => {
+                path_coloring = Some(match value(arg)?.to_lowercase().as_str() {
+                    "time" => PathColoring::Time,
+                    "health" => PathColoring::Health,
+                    other => return Err(format!("Unknown path coloring \"{}\"", other).into()),
+                })
+            }
+            "--radius" => radius = Some(parse_positive(arg, value(arg)?)?),
+            "--kernel" => {
+                kernel = Some(match value(arg)?.to_lowercase().as_str() {
+                    "gaussian" => Kernel::Gaussian,
+                    "epanechnikov" => Kernel::Epanechnikov,
+                    "uniform" | "disc" => Kernel::UniformDisc,
+                    "hex" | "hexagonal" => Kernel::HexagonalBinning,
+                    other => return Err(format!("Unknown kernel \"{}\"", other).into()),
+                })
+            }
+            "--bandwidth" => bandwidth = Some(parse_number(arg, value(arg)?)?),
+            "--intensity" => {
+                let input = value(arg)?;
+                intensity = Some(if input.eq_ignore_ascii_case("auto") { None } else { Some(parse_number(arg, input)?) });
+            }
+            "--desaturate" => desaturate = Some(parse_number(arg, value(arg)?)?),
+            "--no-sentry-position" => use_sentry_position = Some(false),
+            "--legend" => legend = true,
+            "--scale-bar" => scale_bar = true,
+            "--title" => title = true,
+            "--gradient" => {
+                let input = value(arg)?;
+                gradient = Some(match GradientPreset::from_keyword(input) {
+                    Some(preset) => HeatmapGradient::Preset(preset),
+                    None => gradient::load_gradient(input).map_err(|err| format!("Can't load the gradient \"{}\": {}", input, err))?,
+                });
+            }
+            "-f" | "--filter" => {
+                let input = value(arg)?;
+                filters.extend(query::parse_filters(input).map_err(|err| format!("Can't parse filter: {}", err.pretty(input)))?);
+            }
+            "--compare-a" | "--compare-b" => {
+                let input = value(arg)?;
+                let parsed = query::parse_filters(input).map_err(|err| format!("Can't parse filter: {}", err.pretty(input)))?;
+                let comparison = comparison.get_or_insert_with(Default::default);
+                let set = if arg == "--compare-a" { &mut comparison.set_a } else { &mut comparison.set_b };
+                set.filters.extend(parsed);
+            }
+            "--demos-a" | "--demos-b" => {
+                let pattern = value(arg)?;
+                glob::Pattern::new(pattern).map_err(|err| format!("Invalid pattern for {}: {}", arg, err))?;
+                let comparison = comparison.get_or_insert_with(Default::default);
+                let set = if arg == "--demos-a" { &mut comparison.set_a } else { &mut comparison.set_b };
+                set.demos.push(pattern.clone());
+            }
+            "--normalize" => {
+                normalization = Some(match value(arg)?.to_lowercase().as_str() {
+                    "raw" => Normalization::Raw,
+                    "demo" => Normalization::PerDemo,
+                    "round" => Normalization::PerRound,
+                    other => return Err(format!("Unknown normalization \"{}\"", other).into()),
+                })
+            }
+            "--export-deaths" => export_deaths = Some(PathBuf::from(value(arg)?)),
+            "--no-cache" => use_cache = false,
+            other if other.starts_with('-') && other.len() > 1 => return Err(format!("Unknown option {}\n\n{}", other, USAGE).into()),
+            other => demo_patterns.push(other),
+        }
+    }
+
+    if demo_patterns.is_empty() {
+        return Err(format!("No demo specified\n\n{}", USAGE).into());
+    }
+    let mut demos = Vec::new();
+    for pattern in demo_patterns {
+        let mut matched = false;
+        for path in glob::glob(pattern)? {
+            demos.push(path?);
+            matched = true;
+        }
+        if !matched {
+            return Err(format!("No demo found matching \"{}\"", pattern).into());
+        }
+    }
+
+    // The explicit camera and image take precedence over the map's
+    if let Some(map_name) = &map_name {
+        let found = map_database
+            .as_ref()
+            .and_then(|map_database| map_database.get(map_name))
+            .ok_or_else(|| format!("No entry for {} in the map database", map_name))?;
+        if &found.name != map_name {
+            eprintln!("Using the map database entry of {} for {}", found.name, map_name);
+        }
+        image = image.or(found.image_path);
+        pos_x = pos_x.or(Some(found.entry.pos_x));
+        pos_y = pos_y.or(Some(found.entry.pos_y));
+        scale = scale.or(Some(found.entry.scale));
+        coords_type = coords_type.or(Some(found.entry.coords_type));
+    }
+
+    // The options given on the command line take precedence over the ones from the job file
+    let mut job = match job_path {
+        Some(job_path) => {
+            let mut job = HeatmapJob::load(job_path)?;
+            job.image_path = image.or(job.image_path);
+            job.pos_x = pos_x.unwrap_or(job.pos_x);
+            job.pos_y = pos_y.unwrap_or(job.pos_y);
+            job.scale = scale.unwrap_or(job.scale);
+            job.coords_type = coords_type.unwrap_or(job.coords_type);
+            job.camera_fit = camera_fit.unwrap_or(job.camera_fit);
+            job.background = background.unwrap_or(job.background);
+            job.heatmap_type = heatmap_type.unwrap_or(job.heatmap_type);
+            job.radius = radius.unwrap_or(job.radius);
+            job.kernel = kernel.unwrap_or(job.kernel);
+            job.bandwidth = bandwidth.or(job.bandwidth);
+            job.intensity = intensity.unwrap_or(job.intensity);
+            job.desaturate = desaturate.unwrap_or(job.desaturate);
+            job.use_sentry_position = use_sentry_position.unwrap_or(job.use_sentry_position);
+            job.gradient = gradient.unwrap_or(job.gradient);
+            job.annotations.legend |= legend;
+            job.annotations.scale_bar |= scale_bar;
+            job.annotations.title |= title;
+            if let Some(comparison) = comparison {
+                job.comparison = Some(match job.comparison {
+                    Some(mut job_comparison) => {
+                        job_comparison.set_a.filters.extend(comparison.set_a.filters);
+                        job_comparison.set_a.demos.extend(comparison.set_a.demos);
+                        job_comparison.set_b.filters.extend(comparison.set_b.filters);
+                        job_comparison.set_b.demos.extend(comparison.set_b.demos);
+                        job_comparison
+                    }
+                    None => comparison,
+                });
+            }
+            if let (Some(job_comparison), Some(normalization)) = (&mut job.comparison, normalization) {
+                job_comparison.normalization = normalization;
+            }
+            job.filters.extend(filters);
+            job
+        }
+        None => {
+            let camera_fit = camera_fit.unwrap_or_default();
+            // the fitted camera doesn't need any position
+            let fitted = camera_fit != CameraFit::Manual;
+            let background = background.unwrap_or(if image.is_some() { Background::LevelOverview } else { Background::Grid });
+            HeatmapJob {
+                image_path: image,
+                pos_x: pos_x.or(Some(0.0).filter(|_| fitted)).ok_or("Missing the camera x position (-x)")?,
+                pos_y: pos_y.or(Some(0.0).filter(|_| fitted)).ok_or("Missing the camera y position (-y)")?,
+                scale: scale.or(Some(1.0).filter(|_| fitted)).ok_or("Missing the camera scale (--scale)")?,
+                coords_type: coords_type.unwrap_or_default(),
+                camera_fit,
+                background,
+                heatmap_type: heatmap_type.unwrap_or_default(),
+                radius: radius.unwrap_or(50.0),
+                kernel: kernel.unwrap_or_default(),
+                bandwidth,
+                intensity: intensity.unwrap_or_default(),
+                desaturate: desaturate.unwrap_or_default(),
+                use_sentry_position: use_sentry_position.unwrap_or(true),
+                gradient: gradient.unwrap_or_default(),
+                annotations: Annotations { legend, scale_bar, title },
+                kill_death: Default::default(),
+                occupancy: Default::default(),
+                path: Default::default(),
+                comparison: comparison.map(|comparison| Comparison {
+                    normalization: normalization.unwrap_or_default(),
+                    ..comparison
+                }),
+                filters,
+            }
+        }
+    };
+    if job.background == Background::LevelOverview && job.image_path.is_none() {
+        return Err("Missing the level overview image (--image), or use --background grid".into());
+    }
+    if let Some(save_map) = save_map {
+        if job.camera_fit != CameraFit::Manual {
+            return Err("A fitted camera can't be saved to the map database".into());
+        }
+        let map_database = map_database.ok_or("Couldn't find where to store the map database, use --map-db")?;
+        let entry = MapEntry {
+            pos_x: job.pos_x,
+            pos_y: job.pos_y,
+            scale: job.scale,
+            coords_type: job.coords_type,
+        };
+        // without the desaturation
+        let image = match (job.background, &job.image_path) {
+            (Background::LevelOverview, Some(image_path)) => Some(Reader::open(image_path)?.with_guessed_format()?.decode()?.into_rgb8()),
+            _ => None,
+        };
+        map_database.insert(&save_map, &entry, image.as_ref())?;
+        eprintln!("Map saved: {} in {}", save_map, map_database.dir().to_string_lossy());
+    }
+    job.kill_death.subject = kill_death_subject.unwrap_or(job.kill_death.subject);
+    job.kill_death.metric = kill_death_metric.unwrap_or(job.kill_death.metric);
+    job.kill_death.min_samples = min_samples.unwrap_or(job.kill_death.min_samples);
+    job.occupancy.team = occupancy_team.or(job.occupancy.team);
+    job.occupancy.classes = occupancy_classes.unwrap_or(job.occupancy.classes);
+    job.occupancy.players.extend(occupancy_players);
+    job.occupancy.alive_only &= !include_dead;
+    job.path.player = path_player.unwrap_or(job.path.player);
+    job.path.range = path_range.unwrap_or(job.path.range);
+    job.path.coloring = path_coloring.unwrap_or(job.path.coloring);
+    if job.heatmap_type == HeatmapType::Path && job.path.player.is_empty() {
+        return Err("Missing the player of the path (--path-player)".into());
+    }
+
+    Ok(Some(CliOptions {
+        demos,
+        use_cache,
+        sample_interval,
+        export_deaths,
+        output,
+        map: map_name,
+        job,
+    }))
+}
+
+fn parse_number<T: std::str::FromStr>(name: &str, input: &str) -> Result<T, String> {
+    input.parse().map_err(|_| format!("Invalid value for {}: \"{}\"", name, input))
+}
+
+// Same as the GUI inputs, 0, negative, infinite and NaN values are rejected
+fn parse_positive(name: &str, input: &str) -> Result<f32, String> {
+    let number: f32 = parse_number(name, input)?;
+    if number.is_finite() && number > 0.0 {
+        Ok(number)
+    } else {
+        Err(format!("Invalid value for {}: \"{}\", it has to be greater than 0", name, input))
+    }
+}
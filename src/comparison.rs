@@ -0,0 +1,80 @@
+use crate::{
+    filters::{Filter, FilterTrait},
+    heatmap_analyser::{Death, HeatmapAnalysis},
+};
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Normalization {
+    Raw,
+    PerDemo,
+    PerRound,
+}
+
+impl Normalization {
+    pub const ALL: [Normalization; 3] = [Normalization::Raw, Normalization::PerDemo, Normalization::PerRound];
+}
+
+impl Default for Normalization {
+    fn default() -> Self {
+        Self::Raw
+    }
+}
+
+impl Display for Normalization {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Normalization::Raw => write!(f, "Raw counts"),
+            Normalization::PerDemo => write!(f, "Per demo"),
+            Normalization::PerRound => write!(f, "Per round"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ComparisonSet {
+    // Applied on top of the filters of the job
+    #[serde(default)]
+    pub filters: Vec<Filter>,
+    // Glob patterns matched against the demo file names, empty = every demo
+    #[serde(default)]
+    pub demos: Vec<String>,
+}
+
+impl ComparisonSet {
+    pub fn contains_demo(&self, file_name: &str) -> bool {
+        self.demos.is_empty()
+            || self
+                .demos
+                .iter()
+                .any(|pattern| glob::Pattern::new(pattern).is_ok_and(|pattern| pattern.matches(file_name)))
+    }
+}
+
+// Set A minus set B
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Comparison {
+    pub set_a: ComparisonSet,
+    pub set_b: ComparisonSet,
+    #[serde(default)]
+    pub normalization: Normalization,
+}
+
+impl Comparison {
+    // The deaths of the set, and how much each of them weighs according to the normalization
+    pub fn select<'a>(&self, set: &ComparisonSet, demos: &[(&str, &'a HeatmapAnalysis)], filters: &[Filter]) -> (Vec<&'a Death>, f32) {
+        let demos: Vec<_> = demos.iter().filter(|(file_name, _)| set.contains_demo(file_name)).collect();
+        let deaths = demos
+            .iter()
+            .flat_map(|(_, heatmap_analysis)| heatmap_analysis.deaths.iter())
+            .filter(|death| filters.iter().chain(&set.filters).all(|filter| filter.apply(death)))
+            .collect();
+        let weight = match self.normalization {
+            Normalization::Raw => 1.0,
+            Normalization::PerDemo => 1.0 / demos.len().max(1) as f32,
+            Normalization::PerRound => 1.0 / demos.iter().map(|(_, heatmap_analysis)| heatmap_analysis.rounds.len()).sum::<usize>().max(1) as f32,
+        };
+        (deaths, weight)
+    }
+}
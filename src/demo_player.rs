@@ -1,5 +1,3 @@
-mod weapons;
-
 use fnv::FnvHashMap;
 use io::{BufRead, BufReader, BufWriter, LineWriter, StdoutLock};
 use std::{
@@ -14,6 +12,7 @@ use std::{
 use std::{num::NonZeroU32, str::FromStr};
 
 use coldmaps::heatmap_analyser::{handle_to_entity_index, Class, HeatmapAnalyser, HeatmapAnalysis, PlayerState, Spawn, Team, UserId, UserInfo};
+use coldmaps::weapons::{self, Weapon};
 use serde::Serialize;
 use std::borrow::Borrow;
 use tf_demo_parser::{
@@ -34,7 +33,6 @@ use tf_demo_parser::{
     demo::{packet::datatable::ServerClass, sendprop::SendPropName},
     Demo, DemoParser, MessageType, ParseError, ParserState, ReadResult, Stream,
 };
-use weapons::Weapon;
 
 const SECTION_SIZE: usize = 1024;
 const SHOW_UNKNOWN_ENTITIES: bool = true;
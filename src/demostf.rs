@@ -1,4 +1,4 @@
-use coldmaps::heatmap::LEVELOVERVIEW_SCALE_MULTIPLIER;
+use coldmaps::maps::MapEntry;
 use image::io::Reader;
 use image::{ImageFormat, RgbImage};
 use reqwest::StatusCode;
@@ -6,17 +6,14 @@ use serde::Deserialize;
 use std::collections::HashMap;
 use std::io::Cursor;
 
-pub async fn get_boundary(map: &str) -> Result<Option<(f32, f32, f32)>, reqwest::Error> {
+pub async fn get_boundary(map: &str) -> Result<Option<MapEntry>, reqwest::Error> {
     let cache: HashMap<String, Boundary> = reqwest::get("https://github.com/demostf/demos.tf/raw/master/src/Analyse/mapboundries.json")
         .await?
         .json()
         .await?;
-    Ok(cache.get(map).map(|boundary| {
-        let x = (boundary.max.x + boundary.min.x) / 2.0;
-        let y = (boundary.max.y + boundary.min.y) / 2.0;
-        let scale = (boundary.max.y - boundary.min.y) / LEVELOVERVIEW_SCALE_MULTIPLIER / 2.0;
-        (x, y, scale)
-    }))
+    Ok(cache
+        .get(map)
+        .map(|boundary| MapEntry::from_boundary(boundary.min.x, boundary.min.y, boundary.max.x, boundary.max.y)))
 }
 
 pub async fn get_image(map: &str) -> Result<Option<RgbImage>, reqwest::Error> {
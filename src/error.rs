@@ -0,0 +1,45 @@
+use serde::{Deserialize, Serialize};
+use std::{error::Error, fmt::Display};
+
+// Problems found while processing a demo, the ones with a tick don't stop the analysis and keep what was analysed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum DemoError {
+    // The file couldn't be read
+    Io(String),
+    // Not a demo, or one with a malformed header
+    Header(String),
+    // The rest of the demo can't be parsed, the analysis stops at that tick
+    Corrupted { tick: u32, message: String },
+    // An event that doesn't match what the analyser knows, e.g. a kill from a user that never joined, it's skipped or partially kept
+    InconsistentState { tick: u32, message: String },
+    // The analysis is fine but couldn't be stored in the cache, the demo gets parsed again next time
+    CacheWrite(String),
+}
+
+impl DemoError {
+    pub fn tick(&self) -> Option<u32> {
+        match self {
+            DemoError::Io(_) | DemoError::Header(_) | DemoError::CacheWrite(_) => None,
+            DemoError::Corrupted { tick, .. } | DemoError::InconsistentState { tick, .. } => Some(*tick),
+        }
+    }
+
+    // Whether there is no analysis at all
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, DemoError::Io(_) | DemoError::Header(_))
+    }
+}
+
+impl Display for DemoError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DemoError::Io(message) => write!(f, "Couldn't read the demo: {}", message),
+            DemoError::Header(message) => write!(f, "Couldn't parse the demo header: {}", message),
+            DemoError::Corrupted { tick, message } => write!(f, "Demo is corrupted, could only analyse up to tick {} ({})", tick, message),
+            DemoError::InconsistentState { tick, message } => write!(f, "Tick {}: {}", tick, message),
+            DemoError::CacheWrite(message) => write!(f, "Couldn't cache the analysis: {}", message),
+        }
+    }
+}
+
+impl Error for DemoError {}
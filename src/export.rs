@@ -0,0 +1,145 @@
+use crate::heatmap_analyser::{Class, Death, HeatmapAnalysis, PlayerEntity, PlayerState, Team, UserId};
+use serde::Serialize;
+use std::{
+    error::Error,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    NdJson,
+}
+
+impl ExportFormat {
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_lowercase().as_str() {
+            "csv" => Some(ExportFormat::Csv),
+            "json" | "ndjson" | "jsonl" => Some(ExportFormat::NdJson),
+            _ => None,
+        }
+    }
+}
+
+// One row per death, flattened so it can be loaded as a table
+#[derive(Debug, Clone, Serialize)]
+pub struct DeathRecord {
+    pub demo: String,
+    pub map: String,
+    pub tick: u32,
+    pub demo_tick: u32,
+    pub demo_time: f32,
+    pub round: u32,
+    pub during_round: bool,
+    pub match_time: Option<f32>,
+    pub round_time: Option<f32>,
+    pub time_alive: Option<f32>,
+    pub weapon: String,
+    pub suicide: bool,
+    pub posthumous: bool,
+    pub killer_name: String,
+    pub killer_steamid: String,
+    pub killer_team: Option<Team>,
+    pub killer_class: Option<Class>,
+    pub killer_x: Option<f32>,
+    pub killer_y: Option<f32>,
+    pub killer_z: Option<f32>,
+    pub victim_name: String,
+    pub victim_steamid: String,
+    pub victim_team: Option<Team>,
+    pub victim_class: Option<Class>,
+    pub victim_x: Option<f32>,
+    pub victim_y: Option<f32>,
+    pub victim_z: Option<f32>,
+    pub assister_name: Option<String>,
+    pub assister_steamid: Option<String>,
+    pub assister_team: Option<Team>,
+    pub assister_class: Option<Class>,
+    pub assister_x: Option<f32>,
+    pub assister_y: Option<f32>,
+    pub assister_z: Option<f32>,
+    pub sentry_x: Option<f32>,
+    pub sentry_y: Option<f32>,
+    pub sentry_z: Option<f32>,
+    pub distance_2d: Option<f32>,
+    pub distance_3d: Option<f32>,
+}
+
+impl DeathRecord {
+    pub fn new(demo: &str, map: &str, heatmap_analysis: &HeatmapAnalysis, death: &Death) -> Self {
+        let user_name = |user_id: &UserId| heatmap_analysis.users.get(user_id).map(|user| user.name.clone()).unwrap_or_default();
+        let demo_tick = death.tick.saturating_sub(heatmap_analysis.tick_offset);
+        let killer = death.killer_entity_state.as_ref();
+        let victim = death.victim_entity_state.as_ref();
+        let assister = death.assister_entity_state.as_ref();
+        DeathRecord {
+            demo: demo.into(),
+            map: map.into(),
+            tick: death.tick,
+            demo_tick,
+            demo_time: demo_tick as f32 * heatmap_analysis.interval_per_tick,
+            round: death.round,
+            during_round: death.during_round,
+            match_time: death.match_time,
+            round_time: death.round_time,
+            time_alive: death.time_alive,
+            weapon: death.weapon.clone(),
+            suicide: death.killer == death.victim,
+            posthumous: !matches!(killer, Some(PlayerEntity { state: PlayerState::Alive, .. })),
+            killer_name: user_name(&death.killer),
+            killer_steamid: death.killer_steamid.clone(),
+            killer_team: killer.map(|entity| entity.team),
+            killer_class: killer.map(|entity| entity.class),
+            killer_x: killer.map(|entity| entity.position.x),
+            killer_y: killer.map(|entity| entity.position.y),
+            killer_z: killer.map(|entity| entity.position.z),
+            victim_name: user_name(&death.victim),
+            victim_steamid: death.victim_steamid.clone(),
+            victim_team: victim.map(|entity| entity.team),
+            victim_class: victim.map(|entity| entity.class),
+            victim_x: victim.map(|entity| entity.position.x),
+            victim_y: victim.map(|entity| entity.position.y),
+            victim_z: victim.map(|entity| entity.position.z),
+            assister_name: death.assister.as_ref().map(user_name),
+            assister_steamid: death.assister_steamid.clone(),
+            assister_team: assister.map(|entity| entity.team),
+            assister_class: assister.map(|entity| entity.class),
+            assister_x: assister.map(|entity| entity.position.x),
+            assister_y: assister.map(|entity| entity.position.y),
+            assister_z: assister.map(|entity| entity.position.z),
+            sentry_x: death.sentry_position.map(|position| position.x),
+            sentry_y: death.sentry_position.map(|position| position.y),
+            sentry_z: death.sentry_position.map(|position| position.z),
+            distance_2d: death.distance_2d(),
+            distance_3d: death.distance_3d(),
+        }
+    }
+}
+
+pub fn write_csv<W: Write>(writer: W, records: impl IntoIterator<Item = DeathRecord>) -> Result<(), csv::Error> {
+    let mut writer = csv::Writer::from_writer(writer);
+    for record in records {
+        writer.serialize(record)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn write_ndjson<W: Write>(mut writer: W, records: impl IntoIterator<Item = DeathRecord>) -> io::Result<()> {
+    for record in records {
+        serde_json::to_writer(&mut writer, &record)?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()
+}
+
+pub fn export_deaths(path: &Path, format: ExportFormat, records: impl IntoIterator<Item = DeathRecord>) -> Result<(), Box<dyn Error>> {
+    let writer = BufWriter::new(File::create(path)?);
+    match format {
+        ExportFormat::Csv => write_csv(writer, records)?,
+        ExportFormat::NdJson => write_ndjson(writer, records)?,
+    }
+    Ok(())
+}
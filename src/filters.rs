@@ -1,9 +1,13 @@
-use crate::heatmap_analyser::{Death, PlayerEntity, PlayerState, Team};
+use crate::{
+    heatmap_analyser::{Death, PlayerEntity, PlayerState, Team},
+    weapons::WeaponGroup,
+};
 use enum_dispatch::enum_dispatch;
+use serde::{Deserialize, Serialize};
 use std::fmt::Display;
 use tf_demo_parser::demo::vector::Vector;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum OrderedOperator {
     Equal,
     NotEqual,
@@ -43,7 +47,7 @@ impl Display for OrderedOperator {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum PropertyOperator {
     IsPresent,
     IsNotPresent,
@@ -68,7 +72,7 @@ impl Display for PropertyOperator {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Property {
     Suicide,
     Posthumous,
@@ -98,18 +102,30 @@ impl Display for Property {
 }
 
 #[enum_dispatch]
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Filter {
     KillerTeamFilter,
     VictimTeamFilter,
     KillerClassFilter,
     VictimClassFilter,
+    AssisterTeamFilter,
+    AssisterClassFilter,
     KillerElevationFilter,
     VictimElevationFilter,
     Distance2DFilter,
     Distance3DFilter,
     RoundFilter,
     PropertyFilter,
+    WeaponFilter,
+    KillerPlayerFilter,
+    VictimPlayerFilter,
+    AssisterPlayerFilter,
+    MatchTimeFilter,
+    RoundTimeFilter,
+    TimeAliveFilter,
+    AndFilter,
+    OrFilter,
+    NotFilter,
 }
 
 #[enum_dispatch(Filter)]
@@ -117,7 +133,7 @@ pub trait FilterTrait {
     fn apply(&self, death: &Death) -> bool;
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct KillerTeamFilter {
     pub team: Team,
 }
@@ -131,7 +147,7 @@ impl FilterTrait for KillerTeamFilter {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VictimTeamFilter {
     pub team: Team,
 }
@@ -145,7 +161,7 @@ impl FilterTrait for VictimTeamFilter {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct KillerClassFilter {
     pub classes: [bool; 10],
 }
@@ -159,7 +175,7 @@ impl FilterTrait for KillerClassFilter {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VictimClassFilter {
     pub classes: [bool; 10],
 }
@@ -173,7 +189,36 @@ impl FilterTrait for VictimClassFilter {
     }
 }
 
-#[derive(Debug)]
+// The deaths without an assist never match
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AssisterTeamFilter {
+    pub team: Team,
+}
+
+impl FilterTrait for AssisterTeamFilter {
+    fn apply(&self, death: &Death) -> bool {
+        match &death.assister_entity_state {
+            Some(PlayerEntity { team, .. }) => *team == self.team,
+            None => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AssisterClassFilter {
+    pub classes: [bool; 10],
+}
+
+impl FilterTrait for AssisterClassFilter {
+    fn apply(&self, death: &Death) -> bool {
+        match &death.assister_entity_state {
+            Some(PlayerEntity { class, .. }) => self.classes[*class as usize],
+            None => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct KillerElevationFilter {
     pub op: OrderedOperator,
     pub z: f32,
@@ -193,7 +238,7 @@ impl FilterTrait for KillerElevationFilter {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VictimElevationFilter {
     pub op: OrderedOperator,
     pub z: f32,
@@ -213,7 +258,7 @@ impl FilterTrait for VictimElevationFilter {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Distance2DFilter {
     pub op: OrderedOperator,
     pub distance: f32,
@@ -221,10 +266,7 @@ pub struct Distance2DFilter {
 
 impl FilterTrait for Distance2DFilter {
     fn apply(&self, death: &Death) -> bool {
-        if let (Some(killer_entity), Some(victim_entity)) = (&death.killer_entity_state, &death.victim_entity_state) {
-            let distance_x = killer_entity.position.x - victim_entity.position.x;
-            let distance_y = killer_entity.position.y - victim_entity.position.y;
-            let distance = (distance_x * distance_x + distance_y * distance_y).sqrt();
+        if let Some(distance) = death.distance_2d() {
             match self.op {
                 OrderedOperator::Equal => distance == self.distance,
                 OrderedOperator::NotEqual => distance != self.distance,
@@ -239,7 +281,7 @@ impl FilterTrait for Distance2DFilter {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Distance3DFilter {
     pub op: OrderedOperator,
     pub distance: f32,
@@ -247,11 +289,7 @@ pub struct Distance3DFilter {
 
 impl FilterTrait for Distance3DFilter {
     fn apply(&self, death: &Death) -> bool {
-        if let (Some(killer_entity), Some(victim_entity)) = (&death.killer_entity_state, &death.victim_entity_state) {
-            let distance_x = killer_entity.position.x - victim_entity.position.x;
-            let distance_y = killer_entity.position.y - victim_entity.position.y;
-            let distance_z = killer_entity.position.z - victim_entity.position.z;
-            let distance = (distance_x * distance_x + distance_y * distance_y + distance_z * distance_z).sqrt();
+        if let Some(distance) = death.distance_3d() {
             match self.op {
                 OrderedOperator::Equal => distance == self.distance,
                 OrderedOperator::NotEqual => distance != self.distance,
@@ -266,7 +304,7 @@ impl FilterTrait for Distance3DFilter {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RoundFilter {
     pub op: OrderedOperator,
     pub round: u32,
@@ -285,7 +323,7 @@ impl FilterTrait for RoundFilter {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PropertyFilter {
     pub op: PropertyOperator,
     pub property: Property,
@@ -308,3 +346,158 @@ impl FilterTrait for PropertyFilter {
         }
     }
 }
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WeaponSelection {
+    KillIcons(Vec<String>),
+    Group(WeaponGroup),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WeaponFilter {
+    pub weapon: WeaponSelection,
+}
+
+impl FilterTrait for WeaponFilter {
+    fn apply(&self, death: &Death) -> bool {
+        match &self.weapon {
+            WeaponSelection::KillIcons(kill_icons) => kill_icons.contains(&death.weapon),
+            WeaponSelection::Group(group) => group.contains(&death.weapon),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KillerPlayerFilter {
+    pub steam_id: String,
+}
+
+impl FilterTrait for KillerPlayerFilter {
+    fn apply(&self, death: &Death) -> bool {
+        death.killer_steamid == self.steam_id
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VictimPlayerFilter {
+    pub steam_id: String,
+}
+
+impl FilterTrait for VictimPlayerFilter {
+    fn apply(&self, death: &Death) -> bool {
+        death.victim_steamid == self.steam_id
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AssisterPlayerFilter {
+    pub steam_id: String,
+}
+
+impl FilterTrait for AssisterPlayerFilter {
+    fn apply(&self, death: &Death) -> bool {
+        death.assister_steamid.as_deref() == Some(self.steam_id.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MatchTimeFilter {
+    pub op: OrderedOperator,
+    pub seconds: f32,
+}
+
+impl FilterTrait for MatchTimeFilter {
+    fn apply(&self, death: &Death) -> bool {
+        if let Some(seconds) = death.match_time {
+            match self.op {
+                OrderedOperator::Equal => seconds == self.seconds,
+                OrderedOperator::NotEqual => seconds != self.seconds,
+                OrderedOperator::Greater => seconds > self.seconds,
+                OrderedOperator::Smaller => seconds < self.seconds,
+                OrderedOperator::GreaterOrEqual => seconds >= self.seconds,
+                OrderedOperator::SmallerOrEqual => seconds <= self.seconds,
+            }
+        } else {
+            false
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoundTimeFilter {
+    pub op: OrderedOperator,
+    pub seconds: f32,
+}
+
+impl FilterTrait for RoundTimeFilter {
+    fn apply(&self, death: &Death) -> bool {
+        if let Some(seconds) = death.round_time {
+            match self.op {
+                OrderedOperator::Equal => seconds == self.seconds,
+                OrderedOperator::NotEqual => seconds != self.seconds,
+                OrderedOperator::Greater => seconds > self.seconds,
+                OrderedOperator::Smaller => seconds < self.seconds,
+                OrderedOperator::GreaterOrEqual => seconds >= self.seconds,
+                OrderedOperator::SmallerOrEqual => seconds <= self.seconds,
+            }
+        } else {
+            false
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimeAliveFilter {
+    pub op: OrderedOperator,
+    pub seconds: f32,
+}
+
+impl FilterTrait for TimeAliveFilter {
+    fn apply(&self, death: &Death) -> bool {
+        if let Some(seconds) = death.time_alive {
+            match self.op {
+                OrderedOperator::Equal => seconds == self.seconds,
+                OrderedOperator::NotEqual => seconds != self.seconds,
+                OrderedOperator::Greater => seconds > self.seconds,
+                OrderedOperator::Smaller => seconds < self.seconds,
+                OrderedOperator::GreaterOrEqual => seconds >= self.seconds,
+                OrderedOperator::SmallerOrEqual => seconds <= self.seconds,
+            }
+        } else {
+            false
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AndFilter {
+    pub filters: Vec<Filter>,
+}
+
+impl FilterTrait for AndFilter {
+    fn apply(&self, death: &Death) -> bool {
+        self.filters.iter().all(|filter| filter.apply(death))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrFilter {
+    pub filters: Vec<Filter>,
+}
+
+impl FilterTrait for OrFilter {
+    fn apply(&self, death: &Death) -> bool {
+        self.filters.iter().any(|filter| filter.apply(death))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NotFilter {
+    pub filter: Box<Filter>,
+}
+
+impl FilterTrait for NotFilter {
+    fn apply(&self, death: &Death) -> bool {
+        !self.filter.apply(death)
+    }
+}
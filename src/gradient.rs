@@ -0,0 +1,191 @@
+use palette::{Gradient, LinSrgba};
+use serde::{Deserialize, Serialize};
+use std::{error::Error, fmt::Display, fs, path::Path};
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GradientStop {
+    pub position: f32,
+    pub color: [f32; 4],
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum GradientPreset {
+    Classic,
+    Viridis,
+    Magma,
+    Colorblind,
+    Monochrome,
+}
+
+impl GradientPreset {
+    pub const ALL: [GradientPreset; 5] = [
+        GradientPreset::Classic,
+        GradientPreset::Viridis,
+        GradientPreset::Magma,
+        GradientPreset::Colorblind,
+        GradientPreset::Monochrome,
+    ];
+
+    fn colors(self) -> &'static [u32] {
+        match self {
+            GradientPreset::Classic => &[0x0000ff, 0x00ffff, 0x00ff00, 0xffff00, 0xff0000],
+            GradientPreset::Viridis => &[0x440154, 0x482878, 0x3e4989, 0x31688e, 0x26828e, 0x1f9e89, 0x35b779, 0x6ece58, 0xb5de2b, 0xfde725],
+            GradientPreset::Magma => &[0x000004, 0x180f3d, 0x440f76, 0x721f81, 0x9e2f7f, 0xcd4071, 0xf1605d, 0xfd9668, 0xfeca8d, 0xfcfdbf],
+            // cividis, readable with every kind of color vision deficiency
+            GradientPreset::Colorblind => &[0x00204d, 0x00336f, 0x39486b, 0x575d6d, 0x707173, 0x8a8779, 0xa69d75, 0xc4b56c, 0xe4cf5b, 0xffea46],
+            GradientPreset::Monochrome => &[0x202020, 0xffffff],
+        }
+    }
+
+    fn keyword(self) -> &'static str {
+        match self {
+            GradientPreset::Classic => "classic",
+            GradientPreset::Viridis => "viridis",
+            GradientPreset::Magma => "magma",
+            GradientPreset::Colorblind => "colorblind",
+            GradientPreset::Monochrome => "monochrome",
+        }
+    }
+
+    pub fn from_keyword(keyword: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|preset| preset.keyword().eq_ignore_ascii_case(keyword))
+    }
+}
+
+impl Display for GradientPreset {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GradientPreset::Classic => write!(f, "Classic"),
+            GradientPreset::Viridis => write!(f, "Viridis"),
+            GradientPreset::Magma => write!(f, "Magma"),
+            GradientPreset::Colorblind => write!(f, "Colorblind safe"),
+            GradientPreset::Monochrome => write!(f, "Monochrome"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum HeatmapGradient {
+    Preset(GradientPreset),
+    Custom(Vec<GradientStop>),
+}
+
+impl Default for HeatmapGradient {
+    fn default() -> Self {
+        Self::Preset(GradientPreset::Classic)
+    }
+}
+
+impl HeatmapGradient {
+    // The alpha of the heatmap colors rises with the intensity so that the level overview stays visible under the cold areas
+    pub fn heatmap_gradient(&self) -> Gradient<LinSrgba> {
+        match self {
+            HeatmapGradient::Preset(preset) => {
+                let colors = preset.colors();
+                let last = (colors.len() - 1) as f32;
+                Gradient::with_domain(
+                    colors
+                        .iter()
+                        .enumerate()
+                        .map(|(index, color)| {
+                            let position = index as f32 / last;
+                            (position, rgb_to_color(*color, position))
+                        })
+                        .collect(),
+                )
+            }
+            HeatmapGradient::Custom(stops) => Gradient::with_domain(
+                stops
+                    .iter()
+                    .map(|stop| (stop.position, LinSrgba::new(stop.color[0], stop.color[1], stop.color[2], stop.color[3])))
+                    .collect(),
+            ),
+        }
+    }
+
+    // Lines are drawn opaque, their alpha comes from the antialiasing
+    pub fn line_gradient(&self) -> Gradient<LinSrgba> {
+        match self {
+            HeatmapGradient::Preset(GradientPreset::Classic) => Gradient::new(vec![LinSrgba::new(0.0, 0.0, 1.0, 1.0), LinSrgba::new(1.0, 1.0, 0.0, 1.0)]),
+            HeatmapGradient::Preset(preset) => Gradient::new(preset.colors().iter().map(|color| rgb_to_color(*color, 1.0)).collect::<Vec<_>>()),
+            HeatmapGradient::Custom(stops) => Gradient::with_domain(
+                stops
+                    .iter()
+                    .map(|stop| (stop.position, LinSrgba::new(stop.color[0], stop.color[1], stop.color[2], 1.0)))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+impl Display for HeatmapGradient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HeatmapGradient::Preset(preset) => write!(f, "{}", preset),
+            HeatmapGradient::Custom(_) => write!(f, "Custom"),
+        }
+    }
+}
+
+// Differences between two sets: blue where set B is ahead, red where set A is, transparent where they're even
+pub fn diverging_gradient() -> Gradient<LinSrgba> {
+    Gradient::with_domain(vec![
+        (0.0, rgb_to_color(0x2166ac, 1.0)),
+        (0.25, rgb_to_color(0x67a9cf, 0.5)),
+        (0.5, rgb_to_color(0xf7f7f7, 0.0)),
+        (0.75, rgb_to_color(0xef8a62, 0.5)),
+        (1.0, rgb_to_color(0xb2182b, 1.0)),
+    ])
+}
+
+fn rgb_to_color(rgb: u32, alpha: f32) -> LinSrgba {
+    LinSrgba::new(((rgb >> 16) & 0xff) as f32 / 255.0, ((rgb >> 8) & 0xff) as f32 / 255.0, (rgb & 0xff) as f32 / 255.0, alpha)
+}
+
+// One stop per line: a position between 0 and 1 followed by a #rrggbb or #rrggbbaa color,
+// without an explicit alpha the stop is as opaque as its position, like the presets.
+// Empty lines and lines starting with ; are ignored.
+pub fn parse_gradient_stops(input: &str) -> Result<Vec<GradientStop>, String> {
+    let mut stops: Vec<GradientStop> = Vec::new();
+    for (line_number, line) in input.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(';') {
+            continue;
+        }
+        let error = |message: &str| format!("Line {}: {}", line_number + 1, message);
+        let mut parts = line.split_whitespace();
+        let (position, color) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(position), Some(color), None) => (position, color),
+            _ => return Err(error("expected a position and a color")),
+        };
+        let position: f32 = position.parse().map_err(|_| error(&format!("invalid position \"{}\"", position)))?;
+        if !(0.0..=1.0).contains(&position) {
+            return Err(error("the position must be between 0 and 1"));
+        }
+        if let Some(previous) = stops.last() {
+            if position < previous.position {
+                return Err(error("the stops must be sorted by position"));
+            }
+        }
+        let hex = color.strip_prefix('#').unwrap_or(color);
+        let value = u32::from_str_radix(hex, 16).map_err(|_| error(&format!("invalid color \"{}\"", color)))?;
+        let color = match hex.len() {
+            6 => rgb_to_color(value, position),
+            8 => rgb_to_color(value >> 8, (value & 0xff) as f32 / 255.0),
+            _ => return Err(error(&format!("invalid color \"{}\", expected #rrggbb or #rrggbbaa", color))),
+        };
+        stops.push(GradientStop {
+            position,
+            color: [color.red, color.green, color.blue, color.alpha],
+        });
+    }
+    if stops.len() < 2 {
+        return Err("A gradient needs at least two stops".into());
+    }
+    Ok(stops)
+}
+
+pub fn load_gradient(path: impl AsRef<Path>) -> Result<HeatmapGradient, Box<dyn Error>> {
+    let stops = parse_gradient_stops(&fs::read_to_string(path)?)?;
+    Ok(HeatmapGradient::Custom(stops))
+}
@@ -1,10 +1,13 @@
 use crate::{delete_icon, style, Message};
 use coldmaps::{
     filters::{
-        Distance2DFilter, Distance3DFilter, Filter, KillerClassFilter, KillerElevationFilter, KillerTeamFilter, OrderedOperator, Property, PropertyFilter, PropertyOperator,
-        RoundFilter, VictimClassFilter, VictimElevationFilter, VictimTeamFilter,
+        AndFilter, AssisterClassFilter, AssisterPlayerFilter, AssisterTeamFilter, Distance2DFilter, Distance3DFilter, Filter, KillerClassFilter, KillerElevationFilter,
+        KillerPlayerFilter, KillerTeamFilter, MatchTimeFilter, NotFilter, OrFilter, OrderedOperator, Property, PropertyFilter, PropertyOperator, RoundFilter, RoundTimeFilter,
+        TimeAliveFilter, VictimClassFilter, VictimElevationFilter, VictimPlayerFilter, VictimTeamFilter, WeaponFilter, WeaponSelection,
     },
     heatmap_analyser::Team,
+    query,
+    weapons::WeaponGroup,
 };
 use iced::{alignment, button, pick_list, scrollable, text_input, Button, Column, Container, Element, Font, Length, PickList, Row, Scrollable, Text, TextInput};
 use std::fmt::Display;
@@ -24,7 +27,7 @@ fn icon(unicode: char) -> Text {
 }
 
 const CLASS_ICONS_CHARS: [char; 10] = [
-    '?',    // Other
+    '?',  // Other
     '🐇', // Scout
     '🎷', // Sniper
     '💥', // Soldier
@@ -42,27 +45,55 @@ pub enum FilterType {
     VictimTeamFilter,
     KillerClassFilter,
     VictimClassFilter,
+    AssisterTeamFilter,
+    AssisterClassFilter,
     KillerElevationFilter,
     VictimElevationFilter,
     Distance2DFilter,
     Distance3DFilter,
     RoundFilter,
     PropertyFilter,
+    WeaponFilter,
+    KillerPlayerFilter,
+    VictimPlayerFilter,
+    AssisterPlayerFilter,
+    MatchTimeFilter,
+    RoundTimeFilter,
+    TimeAliveFilter,
+    AllOfGroup,
+    AnyOfGroup,
+    NoneOfGroup,
 }
 
 impl FilterType {
-    const ALL: [FilterType; 10] = [
+    const ALL: [FilterType; 22] = [
         FilterType::KillerTeamFilter,
         FilterType::VictimTeamFilter,
         FilterType::KillerClassFilter,
         FilterType::VictimClassFilter,
+        FilterType::AssisterTeamFilter,
+        FilterType::AssisterClassFilter,
         FilterType::KillerElevationFilter,
         FilterType::VictimElevationFilter,
         FilterType::Distance2DFilter,
         FilterType::Distance3DFilter,
         FilterType::RoundFilter,
         FilterType::PropertyFilter,
+        FilterType::WeaponFilter,
+        FilterType::KillerPlayerFilter,
+        FilterType::VictimPlayerFilter,
+        FilterType::AssisterPlayerFilter,
+        FilterType::MatchTimeFilter,
+        FilterType::RoundTimeFilter,
+        FilterType::TimeAliveFilter,
+        FilterType::AllOfGroup,
+        FilterType::AnyOfGroup,
+        FilterType::NoneOfGroup,
     ];
+
+    fn is_group(&self) -> bool {
+        matches!(self, FilterType::AllOfGroup | FilterType::AnyOfGroup | FilterType::NoneOfGroup)
+    }
 }
 
 impl Default for FilterType {
@@ -78,12 +109,72 @@ impl Display for FilterType {
             FilterType::VictimTeamFilter => write!(f, "Victim's team"),
             FilterType::KillerClassFilter => write!(f, "Killer's class"),
             FilterType::VictimClassFilter => write!(f, "Victim's class"),
+            FilterType::AssisterTeamFilter => write!(f, "Assister's team"),
+            FilterType::AssisterClassFilter => write!(f, "Assister's class"),
             FilterType::KillerElevationFilter => write!(f, "Killer's elevation"),
             FilterType::VictimElevationFilter => write!(f, "Victim's elevation"),
             FilterType::Distance2DFilter => write!(f, "2D distance"),
             FilterType::Distance3DFilter => write!(f, "3D distance"),
             FilterType::RoundFilter => write!(f, "Round #"),
             FilterType::PropertyFilter => write!(f, "Death property"),
+            FilterType::WeaponFilter => write!(f, "Weapon"),
+            FilterType::KillerPlayerFilter => write!(f, "Killer"),
+            FilterType::VictimPlayerFilter => write!(f, "Victim"),
+            FilterType::AssisterPlayerFilter => write!(f, "Assister"),
+            FilterType::MatchTimeFilter => write!(f, "Seconds into match"),
+            FilterType::RoundTimeFilter => write!(f, "Seconds into round"),
+            FilterType::TimeAliveFilter => write!(f, "Seconds alive"),
+            FilterType::AllOfGroup => write!(f, "All of (AND)"),
+            FilterType::AnyOfGroup => write!(f, "Any of (OR)"),
+            FilterType::NoneOfGroup => write!(f, "None of (NOT)"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WeaponChoice {
+    Group(WeaponGroup),
+    KillIcons,
+}
+
+impl WeaponChoice {
+    const ALL: [WeaponChoice; 6] = [
+        WeaponChoice::Group(WeaponGroup::RocketLaunchers),
+        WeaponChoice::Group(WeaponGroup::Melee),
+        WeaponChoice::Group(WeaponGroup::Hitscan),
+        WeaponChoice::Group(WeaponGroup::Projectile),
+        WeaponChoice::Group(WeaponGroup::Sentry),
+        WeaponChoice::KillIcons,
+    ];
+}
+
+impl Default for WeaponChoice {
+    fn default() -> Self {
+        WeaponChoice::Group(WeaponGroup::RocketLaunchers)
+    }
+}
+
+impl Display for WeaponChoice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WeaponChoice::Group(group) => group.fmt(f),
+            WeaponChoice::KillIcons => write!(f, "Kill icons"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PlayerChoice {
+    pub steam_id: String,
+    pub name: String,
+}
+
+impl Display for PlayerChoice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.name.is_empty() {
+            write!(f, "{}", self.steam_id)
+        } else {
+            write!(f, "{} ({})", self.name, self.steam_id)
         }
     }
 }
@@ -92,6 +183,12 @@ pub struct FiltersPane {
     pub theme: style::Theme,
     pub busy: bool,
     pub filters: Vec<FilterRow>,
+    // Union of the players of every loaded demo
+    pub players: Vec<PlayerChoice>,
+    // Textual version of the filters, kept in sync with the rows
+    pub query: String,
+    pub query_error: Option<String>,
+    query_input_state: text_input::State,
     scroll_state: scrollable::State,
     add_filter_button_state: button::State,
 }
@@ -112,13 +209,19 @@ impl Default for FiltersPane {
             ..Default::default()
         };
         only_in_round_kills_by_default.filter = only_in_round_kills_by_default.try_generate_filter();
-        Self {
+        let mut filters_pane = Self {
             filters: vec![no_suicides_by_default, only_in_round_kills_by_default],
+            players: Default::default(),
+            query: Default::default(),
+            query_error: Default::default(),
+            query_input_state: Default::default(),
             theme: Default::default(),
             busy: Default::default(),
             scroll_state: Default::default(),
             add_filter_button_state: Default::default(),
-        }
+        };
+        filters_pane.update_query();
+        filters_pane
     }
 }
 
@@ -139,24 +242,105 @@ impl FiltersPane {
             } else {
                 style::ResultContainer::Error
             };
+            let players = &self.players;
             let col = self
                 .filters
                 .iter_mut()
                 .enumerate()
-                .fold(Column::new(), |col, (index, filter_row)| col.push(filter_row.view(index, theme)));
+                .fold(Column::new(), |col, (index, filter_row)| col.push(filter_row.view(vec![index], theme, players)));
             (col.into(), style)
         };
 
         let add_filter_button = Button::new(&mut self.add_filter_button_state, Text::new("Add filter"))
             .style(self.theme)
             .on_press(Message::AddFilter);
-        let header = Row::new().push(add_filter_button);
+        let query_input = TextInput::new(
+            &mut self.query_input_state,
+            "killer.class in (scout, soldier) and not suicide",
+            &self.query,
+            Message::FilterQueryChanged,
+        )
+        .on_submit(Message::FilterQuerySubmitted)
+        .padding(5)
+        .style(theme);
+        let header = Row::new().push(add_filter_button).push(query_input);
+        let mut view = Column::new().push(header);
+        if let Some(query_error) = &self.query_error {
+            view = view.push(Text::new(query_error).size(16).color([0.8, 0.1, 0.1]));
+        }
         let filters_scroll = Scrollable::new(&mut self.scroll_state).push(filters).width(Length::Fill).height(Length::Fill);
-        let view = Column::new().push(header).push(filters_scroll);
+        let view = view.push(filters_scroll);
         let result_container = Container::new(view).width(Length::Fill).height(Length::Fill).center_x().center_y().padding(10).style(style);
 
         Container::new(result_container).padding(4).width(Length::Fill).height(Length::Fill).into()
     }
+
+    // Regenerates the filter of the updated row and of every group containing it
+    pub fn update_row(&mut self, path: &[usize], update: impl FnOnce(&mut FilterRow)) {
+        update_row(&mut self.filters, path, update);
+        self.update_query();
+    }
+
+    pub fn remove_row(&mut self, path: &[usize]) {
+        match path.split_last() {
+            Some((&index, [])) => {
+                self.filters.remove(index);
+                self.update_query();
+            }
+            Some((&index, parent_path)) => self.update_row(parent_path, |parent| {
+                parent.children.remove(index);
+            }),
+            None => {}
+        }
+    }
+
+    pub fn set_filters(&mut self, filters: Vec<Filter>) {
+        self.filters = filters.into_iter().map(FilterRow::from_filter).collect();
+        self.update_query();
+    }
+
+    // Invalid rows are left out of the query, just like they are ignored when generating the heatmap
+    fn update_query(&mut self) {
+        let filters: Vec<_> = self.filters.iter().filter_map(|filter_row| filter_row.filter.clone()).collect();
+        self.query = query::format_filters(&filters);
+        self.query_error = None;
+    }
+
+    pub fn set_query(&mut self, query: String) {
+        self.query_error = query::parse_filters(&query).err().map(|err| err.describe(&query));
+        self.query = query;
+    }
+
+    // Replaces the rows with the query if it's valid
+    pub fn submit_query(&mut self) -> bool {
+        match query::parse_filters(&self.query) {
+            Ok(filters) => {
+                self.set_filters(filters);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+}
+
+fn update_row(rows: &mut [FilterRow], path: &[usize], update: impl FnOnce(&mut FilterRow)) {
+    if let Some((&index, rest)) = path.split_first() {
+        let row = &mut rows[index];
+        if rest.is_empty() {
+            update(row);
+        } else {
+            update_row(&mut row.children, rest, update);
+        }
+        row.filter = row.try_generate_filter();
+    }
+}
+
+// Indices of a row and of the groups containing it, starting from the top level
+pub type FilterPath = Vec<usize>;
+
+fn with_path<T>(path: &FilterPath, message: fn(FilterPath, T) -> Message) -> impl Fn(T) -> Message {
+    let path = path.clone();
+    move |value| message(path.clone(), value)
 }
 
 #[derive(Default, Debug)]
@@ -186,38 +370,52 @@ pub struct FilterRow {
     pub selected_property_operator: PropertyOperator,
     pub property_pick_list: pick_list::State<Property>,
     pub selected_property: Property,
+    pub weapon_choice_pick_list: pick_list::State<WeaponChoice>,
+    pub selected_weapon_choice: WeaponChoice,
+    pub player_pick_list: pick_list::State<PlayerChoice>,
+    // SteamID of the selected player
+    pub selected_player: Option<String>,
+    pub add_child_filter_button: button::State,
+    pub children: Vec<FilterRow>,
 }
 
 impl FilterRow {
-    fn view(&mut self, index: usize, theme: style::Theme) -> Element<Message> {
-        let pick_list = PickList::new(&mut self.filter_pick_list, &FilterType::ALL[..], Some(self.selected_filter), move |selected| {
-            Message::FilterSelected(index, selected)
-        });
+    fn view<'a>(&'a mut self, path: FilterPath, theme: style::Theme, players: &'a [PlayerChoice]) -> Element<'a, Message> {
+        let pick_list = PickList::new(
+            &mut self.filter_pick_list,
+            &FilterType::ALL[..],
+            Some(self.selected_filter),
+            with_path(&path, Message::FilterSelected),
+        );
 
         let filter_options = match self.selected_filter {
-            FilterType::KillerTeamFilter | FilterType::VictimTeamFilter => {
+            FilterType::KillerTeamFilter | FilterType::VictimTeamFilter | FilterType::AssisterTeamFilter => {
                 let mut row = Row::new();
-                row = row.push(Button::new(&mut self.team_button_blu, Text::new("BLU")).on_press(Message::BluTeamClicked(index)).style(
-                    if self.team_button_selected == Team::Blu {
-                        ActiveButtonHighlight::Highlighted
-                    } else {
-                        ActiveButtonHighlight::NotHighlighted
-                    },
-                ));
-                row = row.push(Button::new(&mut self.team_button_red, Text::new("RED")).on_press(Message::RedTeamClicked(index)).style(
-                    if self.team_button_selected == Team::Red {
-                        ActiveButtonHighlight::Highlighted
-                    } else {
-                        ActiveButtonHighlight::NotHighlighted
-                    },
-                ));
+                row = row.push(
+                    Button::new(&mut self.team_button_blu, Text::new("BLU"))
+                        .on_press(Message::BluTeamClicked(path.clone()))
+                        .style(if self.team_button_selected == Team::Blu {
+                            ActiveButtonHighlight::Highlighted
+                        } else {
+                            ActiveButtonHighlight::NotHighlighted
+                        }),
+                );
+                row = row.push(
+                    Button::new(&mut self.team_button_red, Text::new("RED"))
+                        .on_press(Message::RedTeamClicked(path.clone()))
+                        .style(if self.team_button_selected == Team::Red {
+                            ActiveButtonHighlight::Highlighted
+                        } else {
+                            ActiveButtonHighlight::NotHighlighted
+                        }),
+                );
                 row
             }
-            FilterType::KillerClassFilter | FilterType::VictimClassFilter => {
+            FilterType::KillerClassFilter | FilterType::VictimClassFilter | FilterType::AssisterClassFilter => {
                 let mut row = Row::new();
                 row = row.push(
                     Button::new(&mut self.class_button_state_scout, icon(CLASS_ICONS_CHARS[1]))
-                        .on_press(Message::ClassIconClicked(index, 1))
+                        .on_press(Message::ClassIconClicked(path.clone(), 1))
                         .style(if self.class_buttons_selected[1] {
                             ActiveButtonHighlight::Highlighted
                         } else {
@@ -226,7 +424,7 @@ impl FilterRow {
                 );
                 row = row.push(
                     Button::new(&mut self.class_button_state_soldier, icon(CLASS_ICONS_CHARS[3]))
-                        .on_press(Message::ClassIconClicked(index, 3))
+                        .on_press(Message::ClassIconClicked(path.clone(), 3))
                         .style(if self.class_buttons_selected[3] {
                             ActiveButtonHighlight::Highlighted
                         } else {
@@ -235,7 +433,7 @@ impl FilterRow {
                 );
                 row = row.push(
                     Button::new(&mut self.class_button_state_pyro, icon(CLASS_ICONS_CHARS[7]))
-                        .on_press(Message::ClassIconClicked(index, 7))
+                        .on_press(Message::ClassIconClicked(path.clone(), 7))
                         .style(if self.class_buttons_selected[7] {
                             ActiveButtonHighlight::Highlighted
                         } else {
@@ -244,7 +442,7 @@ impl FilterRow {
                 );
                 row = row.push(
                     Button::new(&mut self.class_button_state_demoman, icon(CLASS_ICONS_CHARS[4]))
-                        .on_press(Message::ClassIconClicked(index, 4))
+                        .on_press(Message::ClassIconClicked(path.clone(), 4))
                         .style(if self.class_buttons_selected[4] {
                             ActiveButtonHighlight::Highlighted
                         } else {
@@ -253,7 +451,7 @@ impl FilterRow {
                 );
                 row = row.push(
                     Button::new(&mut self.class_button_state_heavy, icon(CLASS_ICONS_CHARS[6]))
-                        .on_press(Message::ClassIconClicked(index, 6))
+                        .on_press(Message::ClassIconClicked(path.clone(), 6))
                         .style(if self.class_buttons_selected[6] {
                             ActiveButtonHighlight::Highlighted
                         } else {
@@ -262,7 +460,7 @@ impl FilterRow {
                 );
                 row = row.push(
                     Button::new(&mut self.class_button_state_engineer, icon(CLASS_ICONS_CHARS[9]))
-                        .on_press(Message::ClassIconClicked(index, 9))
+                        .on_press(Message::ClassIconClicked(path.clone(), 9))
                         .style(if self.class_buttons_selected[9] {
                             ActiveButtonHighlight::Highlighted
                         } else {
@@ -271,7 +469,7 @@ impl FilterRow {
                 );
                 row = row.push(
                     Button::new(&mut self.class_button_state_medic, icon(CLASS_ICONS_CHARS[5]))
-                        .on_press(Message::ClassIconClicked(index, 5))
+                        .on_press(Message::ClassIconClicked(path.clone(), 5))
                         .style(if self.class_buttons_selected[5] {
                             ActiveButtonHighlight::Highlighted
                         } else {
@@ -280,7 +478,7 @@ impl FilterRow {
                 );
                 row = row.push(
                     Button::new(&mut self.class_button_state_sniper, icon(CLASS_ICONS_CHARS[2]))
-                        .on_press(Message::ClassIconClicked(index, 2))
+                        .on_press(Message::ClassIconClicked(path.clone(), 2))
                         .style(if self.class_buttons_selected[2] {
                             ActiveButtonHighlight::Highlighted
                         } else {
@@ -289,7 +487,7 @@ impl FilterRow {
                 );
                 row = row.push(
                     Button::new(&mut self.class_button_state_spy, icon(CLASS_ICONS_CHARS[8]))
-                        .on_press(Message::ClassIconClicked(index, 8))
+                        .on_press(Message::ClassIconClicked(path.clone(), 8))
                         .style(if self.class_buttons_selected[8] {
                             ActiveButtonHighlight::Highlighted
                         } else {
@@ -298,18 +496,23 @@ impl FilterRow {
                 );
                 row
             }
-            FilterType::KillerElevationFilter | FilterType::VictimElevationFilter | FilterType::Distance2DFilter | FilterType::Distance3DFilter | FilterType::RoundFilter => {
+            FilterType::KillerElevationFilter
+            | FilterType::VictimElevationFilter
+            | FilterType::Distance2DFilter
+            | FilterType::Distance3DFilter
+            | FilterType::RoundFilter
+            | FilterType::MatchTimeFilter
+            | FilterType::RoundTimeFilter
+            | FilterType::TimeAliveFilter => {
                 let pick_list = PickList::new(
                     &mut self.ordered_operator_pick_list,
                     &OrderedOperator::ALL[..],
                     Some(self.selected_ordered_operator),
-                    move |selected| Message::OrderedOperatorSelected(index, selected),
+                    with_path(&path, Message::OrderedOperatorSelected),
                 );
-                let text_input = TextInput::new(&mut self.text_input_state, "value", &self.text_input, move |selected| {
-                    Message::FilterTextInputChanged(index, selected)
-                })
-                .size(30)
-                .style(theme);
+                let text_input = TextInput::new(&mut self.text_input_state, "value", &self.text_input, with_path(&path, Message::FilterTextInputChanged))
+                    .size(30)
+                    .style(theme);
                 Row::new().push(pick_list).push(text_input)
             }
             FilterType::PropertyFilter => {
@@ -317,26 +520,193 @@ impl FilterRow {
                     &mut self.property_operator_pick_list,
                     &PropertyOperator::ALL[..],
                     Some(self.selected_property_operator),
-                    move |selected| Message::PropertyOperatorSelected(index, selected),
+                    with_path(&path, Message::PropertyOperatorSelected),
+                );
+                let property_pick_list = PickList::new(
+                    &mut self.property_pick_list,
+                    &Property::ALL[..],
+                    Some(self.selected_property),
+                    with_path(&path, Message::PropertySelected),
                 );
-                let property_pick_list = PickList::new(&mut self.property_pick_list, &Property::ALL[..], Some(self.selected_property), move |selected| {
-                    Message::PropertySelected(index, selected)
-                });
                 Row::new().push(property_pick_list).push(property_operator_pick_list)
             }
+            FilterType::WeaponFilter => {
+                let weapon_choice_pick_list = PickList::new(
+                    &mut self.weapon_choice_pick_list,
+                    &WeaponChoice::ALL[..],
+                    Some(self.selected_weapon_choice),
+                    with_path(&path, Message::WeaponChoiceSelected),
+                );
+                let mut row = Row::new().push(weapon_choice_pick_list);
+                if self.selected_weapon_choice == WeaponChoice::KillIcons {
+                    let text_input = TextInput::new(
+                        &mut self.text_input_state,
+                        "tf_projectile_rocket, shovel, ...",
+                        &self.text_input,
+                        with_path(&path, Message::FilterTextInputChanged),
+                    )
+                    .size(30)
+                    .style(theme);
+                    row = row.push(text_input);
+                }
+                row
+            }
+            FilterType::KillerPlayerFilter | FilterType::VictimPlayerFilter | FilterType::AssisterPlayerFilter => {
+                // The selected player may not be in any of the loaded demos when the filter comes from a job file
+                let selected_player = self.selected_player.as_ref().map(|steam_id| {
+                    players.iter().find(|player| player.steam_id == *steam_id).cloned().unwrap_or_else(|| PlayerChoice {
+                        steam_id: steam_id.clone(),
+                        name: String::new(),
+                    })
+                });
+                let player_pick_list = PickList::new(&mut self.player_pick_list, players, selected_player, with_path(&path, Message::PlayerSelected));
+                Row::new().push(player_pick_list)
+            }
+            FilterType::AllOfGroup | FilterType::AnyOfGroup | FilterType::NoneOfGroup => {
+                let add_filter_button = Button::new(&mut self.add_child_filter_button, Text::new("Add filter"))
+                    .style(theme)
+                    .on_press(Message::AddChildFilter(path.clone()));
+                Row::new().push(add_filter_button)
+            }
         };
 
-        let delete_button = Button::new(&mut self.delete_button, delete_icon()).style(theme).on_press(Message::FilterRemoved(index));
+        let delete_button = Button::new(&mut self.delete_button, delete_icon())
+            .style(theme)
+            .on_press(Message::FilterRemoved(path.clone()));
         let row = Row::new().push(delete_button).push(pick_list).push(filter_options);
+        let mut content = Column::new().push(row);
+        if self.selected_filter.is_group() {
+            let children = self.children.iter_mut().enumerate().fold(Column::new(), |col, (index, child)| {
+                let mut child_path = path.clone();
+                child_path.push(index);
+                col.push(child.view(child_path, theme, players))
+            });
+            content = content.push(Container::new(children).padding([0, 0, 0, 20]));
+        }
         let container_style = if self.filter.is_some() {
             style::ResultContainer::Ok
         } else {
             style::ResultContainer::Error
         };
-        let result_container = Container::new(row).width(Length::Fill).center_y().padding(4).style(container_style).into();
+        let result_container = Container::new(content).width(Length::Fill).center_y().padding(4).style(container_style).into();
         result_container
     }
 
+    pub fn from_filter(filter: Filter) -> Self {
+        let mut filter_row = FilterRow::default();
+        match &filter {
+            Filter::KillerTeamFilter(KillerTeamFilter { team }) => {
+                filter_row.selected_filter = FilterType::KillerTeamFilter;
+                filter_row.team_button_selected = *team;
+            }
+            Filter::VictimTeamFilter(VictimTeamFilter { team }) => {
+                filter_row.selected_filter = FilterType::VictimTeamFilter;
+                filter_row.team_button_selected = *team;
+            }
+            Filter::KillerClassFilter(KillerClassFilter { classes }) => {
+                filter_row.selected_filter = FilterType::KillerClassFilter;
+                filter_row.class_buttons_selected = *classes;
+            }
+            Filter::VictimClassFilter(VictimClassFilter { classes }) => {
+                filter_row.selected_filter = FilterType::VictimClassFilter;
+                filter_row.class_buttons_selected = *classes;
+            }
+            Filter::AssisterTeamFilter(AssisterTeamFilter { team }) => {
+                filter_row.selected_filter = FilterType::AssisterTeamFilter;
+                filter_row.team_button_selected = *team;
+            }
+            Filter::AssisterClassFilter(AssisterClassFilter { classes }) => {
+                filter_row.selected_filter = FilterType::AssisterClassFilter;
+                filter_row.class_buttons_selected = *classes;
+            }
+            Filter::KillerElevationFilter(KillerElevationFilter { op, z }) => {
+                filter_row.selected_filter = FilterType::KillerElevationFilter;
+                filter_row.selected_ordered_operator = *op;
+                filter_row.text_input = z.to_string();
+            }
+            Filter::VictimElevationFilter(VictimElevationFilter { op, z }) => {
+                filter_row.selected_filter = FilterType::VictimElevationFilter;
+                filter_row.selected_ordered_operator = *op;
+                filter_row.text_input = z.to_string();
+            }
+            Filter::Distance2DFilter(Distance2DFilter { op, distance }) => {
+                filter_row.selected_filter = FilterType::Distance2DFilter;
+                filter_row.selected_ordered_operator = *op;
+                filter_row.text_input = distance.to_string();
+            }
+            Filter::Distance3DFilter(Distance3DFilter { op, distance }) => {
+                filter_row.selected_filter = FilterType::Distance3DFilter;
+                filter_row.selected_ordered_operator = *op;
+                filter_row.text_input = distance.to_string();
+            }
+            Filter::RoundFilter(RoundFilter { op, round }) => {
+                filter_row.selected_filter = FilterType::RoundFilter;
+                filter_row.selected_ordered_operator = *op;
+                filter_row.text_input = round.to_string();
+            }
+            Filter::PropertyFilter(PropertyFilter { op, property }) => {
+                filter_row.selected_filter = FilterType::PropertyFilter;
+                filter_row.selected_property_operator = *op;
+                filter_row.selected_property = *property;
+            }
+            Filter::WeaponFilter(WeaponFilter { weapon }) => {
+                filter_row.selected_filter = FilterType::WeaponFilter;
+                match weapon {
+                    WeaponSelection::Group(group) => filter_row.selected_weapon_choice = WeaponChoice::Group(*group),
+                    WeaponSelection::KillIcons(kill_icons) => {
+                        filter_row.selected_weapon_choice = WeaponChoice::KillIcons;
+                        filter_row.text_input = kill_icons.join(", ");
+                    }
+                }
+            }
+            Filter::KillerPlayerFilter(KillerPlayerFilter { steam_id }) => {
+                filter_row.selected_filter = FilterType::KillerPlayerFilter;
+                filter_row.selected_player = Some(steam_id.clone());
+            }
+            Filter::VictimPlayerFilter(VictimPlayerFilter { steam_id }) => {
+                filter_row.selected_filter = FilterType::VictimPlayerFilter;
+                filter_row.selected_player = Some(steam_id.clone());
+            }
+            Filter::AssisterPlayerFilter(AssisterPlayerFilter { steam_id }) => {
+                filter_row.selected_filter = FilterType::AssisterPlayerFilter;
+                filter_row.selected_player = Some(steam_id.clone());
+            }
+            Filter::MatchTimeFilter(MatchTimeFilter { op, seconds }) => {
+                filter_row.selected_filter = FilterType::MatchTimeFilter;
+                filter_row.selected_ordered_operator = *op;
+                filter_row.text_input = seconds.to_string();
+            }
+            Filter::RoundTimeFilter(RoundTimeFilter { op, seconds }) => {
+                filter_row.selected_filter = FilterType::RoundTimeFilter;
+                filter_row.selected_ordered_operator = *op;
+                filter_row.text_input = seconds.to_string();
+            }
+            Filter::TimeAliveFilter(TimeAliveFilter { op, seconds }) => {
+                filter_row.selected_filter = FilterType::TimeAliveFilter;
+                filter_row.selected_ordered_operator = *op;
+                filter_row.text_input = seconds.to_string();
+            }
+            Filter::AndFilter(AndFilter { filters }) => {
+                filter_row.selected_filter = FilterType::AllOfGroup;
+                filter_row.children = filters.iter().cloned().map(FilterRow::from_filter).collect();
+            }
+            Filter::OrFilter(OrFilter { filters }) => {
+                filter_row.selected_filter = FilterType::AnyOfGroup;
+                filter_row.children = filters.iter().cloned().map(FilterRow::from_filter).collect();
+            }
+            Filter::NotFilter(NotFilter { filter }) => {
+                filter_row.selected_filter = FilterType::NoneOfGroup;
+                // "None of" rows generate NOT (a OR b ...)
+                filter_row.children = match filter.as_ref() {
+                    Filter::OrFilter(OrFilter { filters }) => filters.iter().cloned().map(FilterRow::from_filter).collect(),
+                    filter => vec![FilterRow::from_filter(filter.clone())],
+                };
+            }
+        }
+        filter_row.filter = Some(filter);
+        filter_row
+    }
+
     pub fn try_generate_filter(&mut self) -> Option<Filter> {
         match self.selected_filter {
             FilterType::KillerTeamFilter => Some(
@@ -381,6 +751,27 @@ impl FilterRow {
                 }
                 .into(),
             ),
+            FilterType::AssisterTeamFilter => Some(
+                AssisterTeamFilter {
+                    team: match self.team_button_selected {
+                        Team::Red => Team::Red,
+                        Team::Blu => Team::Blu,
+                        _ => return None,
+                    },
+                }
+                .into(),
+            ),
+            FilterType::AssisterClassFilter => Some(
+                AssisterClassFilter {
+                    classes: if self.class_buttons_selected.iter().any(|&b| b) {
+                        self.class_buttons_selected
+                    } else {
+                        [false, true, true, true, true, true, true, true, true, true]
+                        // none selected = all selected
+                    },
+                }
+                .into(),
+            ),
             FilterType::KillerElevationFilter => Some(
                 KillerElevationFilter {
                     op: self.selected_ordered_operator,
@@ -438,6 +829,90 @@ impl FilterRow {
                 }
                 .into(),
             ),
+            FilterType::WeaponFilter => Some(
+                WeaponFilter {
+                    weapon: match self.selected_weapon_choice {
+                        WeaponChoice::Group(group) => WeaponSelection::Group(group),
+                        WeaponChoice::KillIcons => {
+                            let kill_icons: Vec<String> = self
+                                .text_input
+                                .split(',')
+                                .map(str::trim)
+                                .filter(|kill_icon| !kill_icon.is_empty())
+                                .map(String::from)
+                                .collect();
+                            if kill_icons.is_empty() {
+                                return None;
+                            }
+                            WeaponSelection::KillIcons(kill_icons)
+                        }
+                    },
+                }
+                .into(),
+            ),
+            FilterType::KillerPlayerFilter => Some(
+                KillerPlayerFilter {
+                    steam_id: self.selected_player.clone()?,
+                }
+                .into(),
+            ),
+            FilterType::VictimPlayerFilter => Some(
+                VictimPlayerFilter {
+                    steam_id: self.selected_player.clone()?,
+                }
+                .into(),
+            ),
+            FilterType::AssisterPlayerFilter => Some(
+                AssisterPlayerFilter {
+                    steam_id: self.selected_player.clone()?,
+                }
+                .into(),
+            ),
+            FilterType::MatchTimeFilter => Some(
+                MatchTimeFilter {
+                    op: self.selected_ordered_operator,
+                    seconds: match self.text_input.parse() {
+                        Ok(value) => value,
+                        Err(_) => return None,
+                    },
+                }
+                .into(),
+            ),
+            FilterType::RoundTimeFilter => Some(
+                RoundTimeFilter {
+                    op: self.selected_ordered_operator,
+                    seconds: match self.text_input.parse() {
+                        Ok(value) => value,
+                        Err(_) => return None,
+                    },
+                }
+                .into(),
+            ),
+            FilterType::TimeAliveFilter => Some(
+                TimeAliveFilter {
+                    op: self.selected_ordered_operator,
+                    seconds: match self.text_input.parse() {
+                        Ok(value) => value,
+                        Err(_) => return None,
+                    },
+                }
+                .into(),
+            ),
+            FilterType::AllOfGroup => Some(AndFilter { filters: self.child_filters()? }.into()),
+            FilterType::AnyOfGroup => Some(OrFilter { filters: self.child_filters()? }.into()),
+            FilterType::NoneOfGroup => Some(
+                NotFilter {
+                    filter: Box::new(OrFilter { filters: self.child_filters()? }.into()),
+                }
+                .into(),
+            ),
+        }
+    }
+    // A group is only valid if it isn't empty and all of its filters are valid
+    fn child_filters(&self) -> Option<Vec<Filter>> {
+        if self.children.is_empty() {
+            return None;
         }
+        self.children.iter().map(|child| child.filter.clone()).collect()
     }
 }
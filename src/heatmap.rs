@@ -1,11 +1,19 @@
-use crate::heatmap_analyser::Death;
+use crate::{
+    gradient::{self, HeatmapGradient},
+    heatmap_analyser::{Death, PlayerEntity, PlayerState, PositionSample, Team, UserId, UserInfo},
+    path::{PathColoring, PlayerPath},
+};
 use image::{ImageBuffer, Pixel, Rgb};
 use palette::{Gradient, LinSrgba};
-use std::fmt::Display;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+};
 
 pub const LEVELOVERVIEW_SCALE_MULTIPLIER: f32 = 512.0;
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub enum CoordsType {
     ShowPos,
     Console,
@@ -26,11 +34,15 @@ impl Display for CoordsType {
     }
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub enum HeatmapType {
     VictimPosition,
     KillerPosition,
+    AssisterPosition,
     Lines,
+    KillDeath,
+    Occupancy,
+    Path,
 }
 
 impl Default for HeatmapType {
@@ -44,11 +56,199 @@ impl Display for HeatmapType {
         match self {
             HeatmapType::VictimPosition => write!(f, "Victim position"),
             HeatmapType::KillerPosition => write!(f, "Killer position"),
+            HeatmapType::AssisterPosition => write!(f, "Assister position"),
             HeatmapType::Lines => write!(f, "Killer -> victim lines"),
+            HeatmapType::KillDeath => write!(f, "Kills vs deaths"),
+            HeatmapType::Occupancy => write!(f, "Time spent"),
+            HeatmapType::Path => write!(f, "Player path"),
         }
     }
 }
 
+// Whose kills and deaths the KillDeath heatmap compares
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum KillDeathSubject {
+    Team(Team),
+    // SteamID
+    Player(String),
+}
+
+impl KillDeathSubject {
+    fn is_killer(&self, death: &Death) -> bool {
+        match self {
+            KillDeathSubject::Team(team) => matches!(&death.killer_entity_state, Some(PlayerEntity { team: killer_team, .. }) if killer_team == team),
+            KillDeathSubject::Player(steam_id) => death.killer_steamid == *steam_id,
+        }
+    }
+
+    fn is_victim(&self, death: &Death) -> bool {
+        match self {
+            KillDeathSubject::Team(team) => matches!(&death.victim_entity_state, Some(PlayerEntity { team: victim_team, .. }) if victim_team == team),
+            KillDeathSubject::Player(steam_id) => death.victim_steamid == *steam_id,
+        }
+    }
+}
+
+impl Default for KillDeathSubject {
+    fn default() -> Self {
+        Self::Team(Team::Red)
+    }
+}
+
+impl Display for KillDeathSubject {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KillDeathSubject::Team(Team::Red) => write!(f, "RED team"),
+            KillDeathSubject::Team(Team::Blu) => write!(f, "BLU team"),
+            KillDeathSubject::Team(team) => write!(f, "{:?} team", team),
+            KillDeathSubject::Player(steam_id) => write!(f, "{}", steam_id),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum KillDeathMetric {
+    Ratio,
+    Net,
+}
+
+impl KillDeathMetric {
+    pub const ALL: [KillDeathMetric; 2] = [KillDeathMetric::Ratio, KillDeathMetric::Net];
+}
+
+impl Default for KillDeathMetric {
+    fn default() -> Self {
+        Self::Ratio
+    }
+}
+
+impl Display for KillDeathMetric {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            KillDeathMetric::Ratio => write!(f, "K/D ratio"),
+            KillDeathMetric::Net => write!(f, "Kills - deaths"),
+        }
+    }
+}
+
+// The ratio is shown on a log scale, from 1 kill for KILL_DEATH_RATIO_RANGE deaths to the opposite
+pub const KILL_DEATH_RATIO_RANGE: f32 = 4.0;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct KillDeathOptions {
+    pub subject: KillDeathSubject,
+    pub metric: KillDeathMetric,
+    // Positions with fewer kills + deaths (weighted by the kernel) are left out
+    pub min_samples: f32,
+}
+
+impl Default for KillDeathOptions {
+    fn default() -> Self {
+        Self {
+            subject: Default::default(),
+            metric: Default::default(),
+            min_samples: 3.0,
+        }
+    }
+}
+
+// Which of the sampled positions the Occupancy heatmap counts
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OccupancyOptions {
+    // None = both teams
+    pub team: Option<Team>,
+    // Indexed by Class
+    pub classes: [bool; 10],
+    // SteamIDs, empty = every player
+    pub players: Vec<String>,
+    pub alive_only: bool,
+}
+
+impl OccupancyOptions {
+    pub fn matches(&self, sample: &PositionSample, users: &BTreeMap<UserId, UserInfo>) -> bool {
+        (!self.alive_only || sample.state == PlayerState::Alive)
+            && (self.team.is_none() || self.team == Some(sample.team))
+            && self.classes[sample.class as usize]
+            && (self.players.is_empty() || users.get(&sample.user).is_some_and(|user| self.players.contains(&user.steam_id)))
+    }
+}
+
+impl Default for OccupancyOptions {
+    fn default() -> Self {
+        Self {
+            team: None,
+            classes: [true; 10],
+            players: Vec::new(),
+            alive_only: true,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Kernel {
+    Gaussian,
+    Epanechnikov,
+    UniformDisc,
+    HexagonalBinning,
+}
+
+impl Kernel {
+    pub const ALL: [Kernel; 4] = [Kernel::Gaussian, Kernel::Epanechnikov, Kernel::UniformDisc, Kernel::HexagonalBinning];
+
+    // How far from a death the kernel has to be evaluated, in bandwidths, the gaussian is cut at 2 like it always was
+    fn support(self) -> f32 {
+        match self {
+            Kernel::Gaussian => 2.0,
+            Kernel::Epanechnikov | Kernel::UniformDisc => 1.0,
+            Kernel::HexagonalBinning => 0.0,
+        }
+    }
+
+    // None for the hexagonal bins, they count the deaths in each bin instead of spreading them
+    fn weight(self, dist: f32, bandwidth: f32) -> Option<f32> {
+        match self {
+            Kernel::Gaussian => Some(gaussian(dist, bandwidth)),
+            Kernel::Epanechnikov => {
+                let u = dist / bandwidth;
+                Some((1.0 - u * u).max(0.0))
+            }
+            Kernel::UniformDisc => Some(if dist <= bandwidth { 1.0 } else { 0.0 }),
+            Kernel::HexagonalBinning => None,
+        }
+    }
+}
+
+impl Default for Kernel {
+    fn default() -> Self {
+        Self::Gaussian
+    }
+}
+
+impl Display for Kernel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Kernel::Gaussian => write!(f, "Gaussian"),
+            Kernel::Epanechnikov => write!(f, "Epanechnikov"),
+            Kernel::UniformDisc => write!(f, "Uniform disc"),
+            Kernel::HexagonalBinning => write!(f, "Hexagonal bins"),
+        }
+    }
+}
+
+// How the deaths are drawn, whatever the camera
+#[derive(Debug, Clone)]
+pub struct HeatmapStyle {
+    pub radius: f32,
+    pub kernel: Kernel,
+    // In Hammer units, None = use the radius in screen pixels
+    pub bandwidth: Option<f32>,
+    pub intensity: Option<f32>,
+    pub use_sentry_position: bool,
+    pub gradient: HeatmapGradient,
+}
+
 #[derive(Debug)]
 struct HeatMapParameters {
     screen_width: f32,
@@ -58,8 +258,12 @@ struct HeatMapParameters {
     top_y: f32,
     bottom_y: f32,
     radius: f32,
+    kernel: Kernel,
+    // In Hammer units, None = use the radius in screen pixels
+    bandwidth: Option<f32>,
     intensity: Option<f32>,
     use_sentry_position: bool,
+    gradient: HeatmapGradient,
 }
 
 #[derive(Debug)]
@@ -68,17 +272,15 @@ pub struct HeatMapGenerator {
 }
 
 impl HeatMapGenerator {
-    pub fn new(
-        pos_x: f32,
-        pos_y: f32,
-        screen_width: u32,
-        screen_height: u32,
-        scale: f32,
-        coords_type: CoordsType,
-        radius: f32,
-        intensity: Option<f32>,
-        use_sentry_position: bool,
-    ) -> Self {
+    pub fn new(pos_x: f32, pos_y: f32, screen_width: u32, screen_height: u32, scale: f32, coords_type: CoordsType, style: &HeatmapStyle) -> Self {
+        let HeatmapStyle {
+            radius,
+            kernel,
+            bandwidth,
+            intensity,
+            use_sentry_position,
+            ref gradient,
+        } = *style;
         let screen_width = screen_width as f32;
         let screen_height = screen_height as f32;
         let aspect_ratio = screen_width / screen_height;
@@ -92,8 +294,11 @@ impl HeatMapGenerator {
                     top_y: pos_y + scale * LEVELOVERVIEW_SCALE_MULTIPLIER,
                     bottom_y: pos_y - scale * LEVELOVERVIEW_SCALE_MULTIPLIER,
                     radius,
+                    kernel,
+                    bandwidth,
                     intensity,
                     use_sentry_position,
+                    gradient: gradient.clone(),
                 },
             },
             CoordsType::Console => Self {
@@ -105,159 +310,272 @@ impl HeatMapGenerator {
                     top_y: pos_y,
                     bottom_y: pos_y - scale * LEVELOVERVIEW_SCALE_MULTIPLIER * 2.0,
                     radius,
+                    kernel,
+                    bandwidth,
                     intensity,
                     use_sentry_position,
+                    gradient: gradient.clone(),
                 },
             },
         }
     }
 
-    pub fn generate_heatmap<'a>(&self, heatmap_type: HeatmapType, deaths: impl IntoIterator<Item = &'a Death>, image: &mut ImageBuffer<Rgb<u8>, Vec<u8>>) {
+    pub fn units_per_pixel(&self) -> f32 {
+        (self.params.right_x - self.params.left_x) / self.params.screen_width
+    }
+
+    // Returns how many deaths (weighted by the kernel) the hottest color of the gradient stands for, None for lines or without any death
+    pub fn generate_heatmap<'a>(&self, heatmap_type: HeatmapType, deaths: impl IntoIterator<Item = &'a Death>, image: &mut ImageBuffer<Rgb<u8>, Vec<u8>>) -> Option<f32> {
         // lines
         if heatmap_type == HeatmapType::Lines {
-            let line_gradient = Gradient::new(vec![
-                LinSrgba::new(0.0, 0.0, 1.0, 1.0),
-                LinSrgba::new(1.0, 1.0, 0.0, 1.0),
-                // LinSrgba::new(0.0, 0.6, 1.0, 1.0),
-                // LinSrgba::new(0.067, 0.8, 1.0, 1.0),
-                // LinSrgba::new(0.33, 1.0, 0.33, 1.0),
-                // LinSrgba::new(1.0, 0.0, 0.0, 1.0),
-                // LinSrgba::new(1.0, 0.8, 0.0, 1.0),
-
-                // LinSrgba::new(1.0, 0.0, 0.0, 1.0),
-                // LinSrgba::new(1.0, 1.0, 0.0, 1.0),
-                // LinSrgba::new(0.0, 1.0, 0.0, 1.0),
-                // LinSrgba::new(0.0, 1.0, 1.0, 1.0),
-                // LinSrgba::new(0.0, 0.0, 1.0, 1.0),
-            ]);
+            let line_gradient = self.params.gradient.line_gradient();
             for death in deaths {
-                let killer_pos = if self.params.use_sentry_position {
-                    if let Some(sentry_position) = death.sentry_position {
-                        Some(sentry_position)
-                    } else {
-                        death.killer_entity_state.as_ref().map(|entity| entity.position)
-                    }
-                } else {
-                    death.killer_entity_state.as_ref().map(|entity| entity.position)
-                };
-                let victim_pos = death.victim_entity_state.as_ref().map(|entity| entity.position);
-                if let (Some(killer_pos), Some(victim_pos)) = (killer_pos, victim_pos) {
-                    let killer_coords = self.game_coords_to_screen_coords(killer_pos.x, killer_pos.y);
-                    let victim_coords = self.game_coords_to_screen_coords(victim_pos.x, victim_pos.y);
-                    let points: Vec<((i32, i32), f32)> = line_drawing::XiaolinWu::<f32, i32>::new(killer_coords, victim_coords).collect();
-
-                    // this is needed because the line drawing algorithm doesn't always go in the start-end order, we need to check what order was used and invert the gradient as needed
-                    let (first_point_x, first_point_y) = match points.get(0) {
-                        Some(((first_point_x, first_point_y), _)) => (*first_point_x as f32, *first_point_y as f32),
-                        None => continue,
-                    };
-                    let dist_killer_x = killer_coords.0 - first_point_x;
-                    let dist_killer_y = killer_coords.1 - first_point_y;
-                    let dist_victim_x = victim_coords.0 - first_point_x;
-                    let dist_victim_y = victim_coords.1 - first_point_y;
-                    let invert_gradient = dist_killer_x * dist_killer_x + dist_killer_y * dist_killer_y > dist_victim_x * dist_victim_x + dist_victim_y * dist_victim_y;
-
-                    let len = points.len() as f32;
-                    for (index, ((x, y), alpha)) in points.iter().enumerate() {
-                        let (x, y) = (*x, *y);
-                        if y < 0 || y >= image.height() as i32 || x < 0 || x >= image.width() as i32 {
-                            continue;
-                        }
-                        let color = if invert_gradient {
-                            line_gradient.get(1.0 - ((index + 1) as f32 / len))
-                        } else {
-                            line_gradient.get((index + 1) as f32 / len)
-                        };
-                        let pixel = image.get_pixel_mut(x as u32, y as u32);
-                        if let [r, g, b] = pixel.channels() {
-                            *pixel = Rgb::from([
-                                ((alpha * color.red + (1.0 - alpha) * (*r as f32 / 255.0)) * 255.0) as u8,
-                                ((alpha * color.green + (1.0 - alpha) * (*g as f32 / 255.0)) * 255.0) as u8,
-                                ((alpha * color.blue + (1.0 - alpha) * (*b as f32 / 255.0)) * 255.0) as u8,
-                            ]);
-                        } else {
-                            unreachable!();
-                        }
-                    }
+                let killer_coords = self.screen_position(HeatmapType::KillerPosition, death);
+                let victim_coords = self.screen_position(HeatmapType::VictimPosition, death);
+                if let (Some(killer_coords), Some(victim_coords)) = (killer_coords, victim_coords) {
+                    draw_gradient_line(image, killer_coords, victim_coords, &line_gradient, 0.0, 1.0);
                 }
             }
-            return;
+            return None;
         }
 
         // heatmap
-        let heatmap_gradient = Gradient::new(vec![
-            // LinSrgba::new(0.0, 0.0, 0.0, 0.0),
-            LinSrgba::new(0.0, 0.0, 1.0, 0.0),
-            LinSrgba::new(0.0, 1.0, 1.0, 0.25),
-            LinSrgba::new(0.0, 1.0, 0.0, 0.5),
-            LinSrgba::new(1.0, 1.0, 0.0, 0.75),
-            LinSrgba::new(1.0, 0.0, 0.0, 1.0),
-            // LinSrgba::new(1.0, 1.0, 1.0, 1.0),
-        ]);
-        let nb_pixels = (image.width() * image.height()) as usize;
-        let mut intensities = Vec::with_capacity(nb_pixels);
-        intensities.resize_with(nb_pixels, || 0.0);
-        let mut max_intensity = f32::NEG_INFINITY;
         let intensity_increment = if let Some(increment) = self.params.intensity { increment / 100.0 } else { 1.0 };
-        let radius = self.params.radius / 10.0;
-        let pixels_iters = (radius * 2.0).ceil() as i32;
-        for death in deaths {
-            let game_coords = match (heatmap_type, self.params.use_sentry_position) {
-                (HeatmapType::VictimPosition, _) => death.victim_entity_state.as_ref().map(|entity| entity.position),
-                (HeatmapType::KillerPosition, false) => death.killer_entity_state.as_ref().map(|entity| entity.position),
-                (HeatmapType::KillerPosition, true) => {
-                    if let Some(sentry_position) = death.sentry_position {
-                        Some(sentry_position)
-                    } else {
-                        death.killer_entity_state.as_ref().map(|entity| entity.position)
-                    }
-                }
-                (HeatmapType::Lines, _) => unreachable!(),
+        let intensities = self.density(heatmap_type, deaths, intensity_increment, image.width(), image.height());
+        self.draw_density(intensities, intensity_increment, image)
+    }
+
+    // Time spent by the players at each position, the samples are grouped by how many seconds each of them stands for
+    // Returns how many seconds (weighted by the kernel) the hottest color of the gradient stands for
+    pub fn generate_occupancy_heatmap(&self, samples: &[(f32, Vec<&PositionSample>)], image: &mut ImageBuffer<Rgb<u8>, Vec<u8>>) -> Option<f32> {
+        let intensity_increment = if let Some(increment) = self.params.intensity { increment / 100.0 } else { 1.0 };
+        let mut intensities = vec![0.0; (image.width() * image.height()) as usize];
+        for (sample_interval, samples) in samples {
+            let positions: Vec<_> = samples
+                .iter()
+                .map(|sample| self.game_coords_to_screen_coords(sample.position.x, sample.position.y))
+                .collect();
+            let density = self.position_density(&positions, sample_interval * intensity_increment, image.width(), image.height());
+            for (intensity, value) in intensities.iter_mut().zip(density) {
+                *intensity += value;
+            }
+        }
+        self.draw_density(intensities, intensity_increment, image)
+    }
+
+    // The path of one player in each demo, split wherever they weren't alive or weren't sampled for a while
+    pub fn generate_path(&self, paths: &[PlayerPath], coloring: PathColoring, image: &mut ImageBuffer<Rgb<u8>, Vec<u8>>) {
+        let line_gradient = self.params.gradient.line_gradient();
+        let marker_size = (image.height() as f32 / 150.0).max(4.0);
+        for path in paths {
+            let (first_tick, last_tick) = match (path.samples.first(), path.samples.last()) {
+                (Some(first), Some(last)) => (first.tick, last.tick),
+                _ => continue,
+            };
+            let duration = last_tick.saturating_sub(first_tick).max(1) as f32;
+            let color_position = |sample: &PositionSample| match coloring {
+                PathColoring::Time => sample.tick.saturating_sub(first_tick) as f32 / duration,
+                PathColoring::Health => (sample.health as f32 / sample.max_health.max(1) as f32).clamp(0.0, 1.0),
             };
-            if let Some(game_coords) = game_coords {
-                let (x_f, y_f) = self.game_coords_to_screen_coords(game_coords.x, game_coords.y);
-                let x_i = x_f.round() as i32;
-                let y_i = y_f.round() as i32;
-                for y_offset in -pixels_iters..pixels_iters {
-                    let y = y_i + y_offset;
-                    if y < 0 || y >= image.height() as i32 {
-                        continue;
-                    }
-                    for x_offset in -pixels_iters..pixels_iters {
-                        let x = x_i + x_offset;
-                        if x < 0 || x >= image.width() as i32 {
-                            continue;
-                        }
-                        let x_dist = x_f - x as f32;
-                        let y_dist = y_f - y as f32;
-                        let dist = (x_dist * x_dist + y_dist * y_dist).sqrt();
-                        let intensity = intensity_increment * gaussian(dist, radius);
-                        let intensity_index = (y * image.width() as i32 + x) as usize;
-                        intensities[intensity_index] += intensity;
-                        if intensities[intensity_index] > max_intensity {
-                            max_intensity = intensities[intensity_index];
-                        }
-                    }
+            for pair in path.samples.windows(2) {
+                let (from, to) = (pair[0], pair[1]);
+                let gap = to.tick.saturating_sub(from.tick) as f32 * path.interval_per_tick;
+                if from.state != PlayerState::Alive || to.state != PlayerState::Alive || gap > path.max_gap {
+                    continue;
+                }
+                draw_gradient_line(
+                    image,
+                    self.game_coords_to_screen_coords(from.position.x, from.position.y),
+                    self.game_coords_to_screen_coords(to.position.x, to.position.y),
+                    &line_gradient,
+                    color_position(from),
+                    color_position(to),
+                );
+            }
+
+            // markers, white whatever the gradient so they stand out from the path, the spawns are at the first sample after them
+            let marker_color = LinSrgba::new(1.0, 1.0, 1.0, 1.0);
+            for spawn_tick in &path.spawns {
+                if let Some(sample) = path.samples.iter().find(|sample| sample.tick >= *spawn_tick) {
+                    let (x, y) = self.game_coords_to_screen_coords(sample.position.x, sample.position.y);
+                    draw_circle(image, x, y, marker_size, marker_color);
+                }
+            }
+            // the kills are where the player was, even for the sentry kills
+            for kill in &path.kills {
+                if let Some(killer) = &kill.killer_entity_state {
+                    let (x, y) = self.game_coords_to_screen_coords(killer.position.x, killer.position.y);
+                    draw_dot(image, x, y, marker_size * 0.5, marker_color);
+                }
+            }
+            for death in &path.deaths {
+                if let Some((x, y)) = self.screen_position(HeatmapType::VictimPosition, death) {
+                    draw_cross(image, x, y, marker_size, marker_color);
                 }
             }
         }
+    }
+
+    fn draw_density(&self, intensities: Vec<f32>, intensity_increment: f32, image: &mut ImageBuffer<Rgb<u8>, Vec<u8>>) -> Option<f32> {
+        let heatmap_gradient = self.params.gradient.heatmap_gradient();
+        let max_intensity = intensities.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        // nothing on screen, the auto intensity would divide by zero
+        if !max_intensity.is_finite() || max_intensity <= 0.0 {
+            return None;
+        }
         for (pixel, base_intensity) in image.pixels_mut().zip(intensities) {
             let intensity = if self.params.intensity.is_none() {
                 base_intensity * 2.0 / max_intensity // auto intensity
             } else {
                 base_intensity
             };
-            let heat_color = heatmap_gradient.get(intensity);
-            if let [r, g, b] = pixel.channels() {
-                *pixel = Rgb::from([
-                    ((heat_color.alpha * heat_color.red + (1.0 - heat_color.alpha) * (*r as f32 / 255.0)) * 255.0) as u8,
-                    ((heat_color.alpha * heat_color.green + (1.0 - heat_color.alpha) * (*g as f32 / 255.0)) * 255.0) as u8,
-                    ((heat_color.alpha * heat_color.blue + (1.0 - heat_color.alpha) * (*b as f32 / 255.0)) * 255.0) as u8,
-                ]);
+            blend(pixel, heatmap_gradient.get(intensity));
+        }
+        match self.params.intensity {
+            Some(_) => Some(1.0 / intensity_increment),
+            None => Some(max_intensity / 2.0),
+        }
+    }
+
+    // Density of the deaths at each pixel, in deaths (times the weight) at the center of the kernel
+    fn density<'a>(&self, heatmap_type: HeatmapType, deaths: impl IntoIterator<Item = &'a Death>, weight: f32, width: u32, height: u32) -> Vec<f32> {
+        let positions: Vec<_> = deaths.into_iter().filter_map(|death| self.screen_position(heatmap_type, death)).collect();
+        self.position_density(&positions, weight, width, height)
+    }
+
+    fn position_density(&self, positions: &[(f32, f32)], weight: f32, width: u32, height: u32) -> Vec<f32> {
+        let bandwidth = match self.params.bandwidth {
+            // the overview uses the same scale on both axes, so the horizontal one is enough
+            Some(bandwidth) => (bandwidth * self.params.screen_width / (self.params.right_x - self.params.left_x)).max(0.5),
+            None => self.params.radius / 10.0,
+        };
+        match self.params.kernel {
+            Kernel::HexagonalBinning => hexagonal_binning(positions, bandwidth, weight, width, height),
+            kernel => kernel_density(positions, kernel, bandwidth, weight, width, height),
+        }
+    }
+
+    // Kills of the subject at the killer's position against its deaths at the victim's position
+    // Returns the net value or the ratio the ends of the diverging gradient stand for, None without enough samples
+    pub fn generate_kill_death_heatmap(&self, deaths: &[&Death], options: &KillDeathOptions, image: &mut ImageBuffer<Rgb<u8>, Vec<u8>>) -> Option<f32> {
+        let kills = deaths.iter().copied().filter(|death| death.killer != death.victim && options.subject.is_killer(death));
+        let subject_deaths = deaths.iter().copied().filter(|death| options.subject.is_victim(death));
+        // where the killer stood even for the sentry kills, so kills and deaths are both fights of the subject at its own position
+        let kill_positions: Vec<_> = kills
+            .filter_map(|kill| kill.killer_entity_state.as_ref())
+            .map(|killer| self.game_coords_to_screen_coords(killer.position.x, killer.position.y))
+            .collect();
+        let kill_density = self.position_density(&kill_positions, 1.0, image.width(), image.height());
+        let death_density = self.density(HeatmapType::VictimPosition, subject_deaths, 1.0, image.width(), image.height());
+        let values: Vec<Option<f32>> = kill_density
+            .iter()
+            .zip(death_density)
+            .map(|(kills, deaths)| {
+                if kills + deaths <= 0.0 || kills + deaths < options.min_samples {
+                    return None;
+                }
+                Some(match options.metric {
+                    KillDeathMetric::Ratio => (kills / deaths).log(KILL_DEATH_RATIO_RANGE),
+                    KillDeathMetric::Net => kills - deaths,
+                })
+            })
+            .collect();
+        let range = match (options.metric, self.params.intensity) {
+            (KillDeathMetric::Ratio, _) => 1.0,
+            (KillDeathMetric::Net, Some(intensity)) => 100.0 / intensity,
+            (KillDeathMetric::Net, None) => values.iter().flatten().map(|value| value.abs()).fold(0.0, f32::max),
+        };
+        if !values.iter().any(Option::is_some) || range <= 0.0 {
+            return None;
+        }
+        let diverging_gradient = gradient::diverging_gradient();
+        for (pixel, value) in image.pixels_mut().zip(values) {
+            if let Some(value) = value {
+                blend(pixel, diverging_gradient.get((0.5 + value / range / 2.0).clamp(0.0, 1.0)));
+            }
+        }
+        match options.metric {
+            KillDeathMetric::Ratio => Some(KILL_DEATH_RATIO_RANGE),
+            KillDeathMetric::Net => Some(range),
+        }
+    }
+
+    // Density of deaths_a minus density of deaths_b, each death counting as the weight of its set (e.g. 1 / number of rounds for per round rates)
+    // Returns the difference the ends of the diverging gradient stand for, None without any difference
+    pub fn generate_difference_heatmap<'a>(
+        &self,
+        heatmap_type: HeatmapType,
+        deaths_a: impl IntoIterator<Item = &'a Death>,
+        weight_a: f32,
+        deaths_b: impl IntoIterator<Item = &'a Death>,
+        weight_b: f32,
+        image: &mut ImageBuffer<Rgb<u8>, Vec<u8>>,
+    ) -> Option<f32> {
+        let intensity_increment = if let Some(increment) = self.params.intensity { increment / 100.0 } else { 1.0 };
+        let density_a = self.density(heatmap_type, deaths_a, weight_a * intensity_increment, image.width(), image.height());
+        let density_b = self.density(heatmap_type, deaths_b, weight_b * intensity_increment, image.width(), image.height());
+        let differences: Vec<f32> = density_a.iter().zip(density_b).map(|(a, b)| a - b).collect();
+        let max_difference = differences.iter().map(|difference| difference.abs()).fold(0.0, f32::max);
+        if !max_difference.is_finite() || max_difference <= 0.0 {
+            return None;
+        }
+        // auto intensity: the biggest difference gets the most saturated color
+        let range = if self.params.intensity.is_none() { max_difference } else { 1.0 };
+        let diverging_gradient = gradient::diverging_gradient();
+        for (pixel, difference) in image.pixels_mut().zip(differences) {
+            blend(pixel, diverging_gradient.get(0.5 + difference / range / 2.0));
+        }
+        match self.params.intensity {
+            Some(_) => Some(1.0 / intensity_increment),
+            None => Some(max_difference),
+        }
+    }
+
+    // Lines every power of two Hammer units, about a dozen across the image, every fourth line and the world origin stand out
+    pub fn draw_grid(&self, image: &mut ImageBuffer<Rgb<u8>, Vec<u8>>) {
+        let p = &self.params;
+        let spacing = 2_f32.powf(((p.right_x - p.left_x) / 12.0).log2().round()).max(16.0);
+        let alpha = |coord: f32| {
+            let index = (coord / spacing).round() as i64;
+            if index == 0 {
+                0.5
+            } else if index % 4 == 0 {
+                0.25
             } else {
-                unreachable!();
+                0.1
             }
+        };
+        let (width, height) = (image.width() as f32, image.height() as f32);
+        let mut x = (p.left_x / spacing).ceil() * spacing;
+        while x <= p.right_x {
+            let (screen_x, _) = self.game_coords_to_screen_coords(x, p.top_y);
+            draw_line(image, (screen_x, 0.0), (screen_x, height), LinSrgba::new(1.0, 1.0, 1.0, alpha(x)));
+            x += spacing;
         }
+        let mut y = (p.bottom_y / spacing).ceil() * spacing;
+        while y <= p.top_y {
+            let (_, screen_y) = self.game_coords_to_screen_coords(p.left_x, y);
+            draw_line(image, (0.0, screen_y), (width, screen_y), LinSrgba::new(1.0, 1.0, 1.0, alpha(y)));
+            y += spacing;
+        }
+    }
+
+    // Where the victim, the killer (or their sentry) or the assister of the death is on the screen
+    pub fn screen_position(&self, heatmap_type: HeatmapType, death: &Death) -> Option<(f32, f32)> {
+        let game_coords = match (heatmap_type, self.params.use_sentry_position) {
+            (HeatmapType::VictimPosition, _) => death.victim_entity_state.as_ref().map(|entity| entity.position),
+            (HeatmapType::KillerPosition, false) => death.killer_entity_state.as_ref().map(|entity| entity.position),
+            (HeatmapType::KillerPosition, true) => {
+                if let Some(sentry_position) = death.sentry_position {
+                    Some(sentry_position)
+                } else {
+                    death.killer_entity_state.as_ref().map(|entity| entity.position)
+                }
+            }
+            (HeatmapType::AssisterPosition, _) => death.assister_entity_state.as_ref().map(|entity| entity.position),
+            (HeatmapType::Lines, _) | (HeatmapType::KillDeath, _) | (HeatmapType::Occupancy, _) | (HeatmapType::Path, _) => unreachable!(),
+        };
+        game_coords.map(|game_coords| self.game_coords_to_screen_coords(game_coords.x, game_coords.y))
     }
 
     fn game_coords_to_screen_coords(&self, x: f32, y: f32) -> (f32, f32) {
@@ -269,6 +587,150 @@ impl HeatMapGenerator {
     }
 }
 
+// Antialiased line, colored with the part of the gradient between from and to
+fn draw_gradient_line(image: &mut ImageBuffer<Rgb<u8>, Vec<u8>>, start: (f32, f32), end: (f32, f32), gradient: &Gradient<LinSrgba>, from: f32, to: f32) {
+    let points: Vec<((i32, i32), f32)> = line_drawing::XiaolinWu::<f32, i32>::new(start, end).collect();
+
+    // this is needed because the line drawing algorithm doesn't always go in the start-end order, we need to check what order was used and invert the gradient as needed
+    let (first_point_x, first_point_y) = match points.get(0) {
+        Some(((first_point_x, first_point_y), _)) => (*first_point_x as f32, *first_point_y as f32),
+        None => return,
+    };
+    let dist_start_x = start.0 - first_point_x;
+    let dist_start_y = start.1 - first_point_y;
+    let dist_end_x = end.0 - first_point_x;
+    let dist_end_y = end.1 - first_point_y;
+    let invert_gradient = dist_start_x * dist_start_x + dist_start_y * dist_start_y > dist_end_x * dist_end_x + dist_end_y * dist_end_y;
+
+    let len = points.len() as f32;
+    for (index, ((x, y), alpha)) in points.iter().enumerate() {
+        let (x, y) = (*x, *y);
+        if y < 0 || y >= image.height() as i32 || x < 0 || x >= image.width() as i32 {
+            continue;
+        }
+        let progress = if invert_gradient { 1.0 - ((index + 1) as f32 / len) } else { (index + 1) as f32 / len };
+        let color = gradient.get(from + (to - from) * progress);
+        blend(image.get_pixel_mut(x as u32, y as u32), LinSrgba::new(color.red, color.green, color.blue, *alpha));
+    }
+}
+
+// Circle outline
+fn draw_circle(image: &mut ImageBuffer<Rgb<u8>, Vec<u8>>, x: f32, y: f32, radius: f32, color: LinSrgba) {
+    let steps = (radius * 8.0).ceil().max(8.0) as usize;
+    for step in 0..steps {
+        let angle = |step: usize| step as f32 / steps as f32 * std::f32::consts::TAU;
+        let start = (x + radius * angle(step).cos(), y + radius * angle(step).sin());
+        let end = (x + radius * angle(step + 1).cos(), y + radius * angle(step + 1).sin());
+        draw_line(image, start, end, color);
+    }
+}
+
+fn draw_dot(image: &mut ImageBuffer<Rgb<u8>, Vec<u8>>, x: f32, y: f32, radius: f32, color: LinSrgba) {
+    let (min_x, max_x) = ((x - radius).floor().max(0.0) as u32, ((x + radius).ceil().max(0.0) as u32).min(image.width()));
+    let (min_y, max_y) = ((y - radius).floor().max(0.0) as u32, ((y + radius).ceil().max(0.0) as u32).min(image.height()));
+    for pixel_y in min_y..max_y {
+        for pixel_x in min_x..max_x {
+            let dist = ((pixel_x as f32 + 0.5 - x).powi(2) + (pixel_y as f32 + 0.5 - y).powi(2)).sqrt();
+            let alpha = (radius + 0.5 - dist).clamp(0.0, 1.0) * color.alpha;
+            if alpha > 0.0 {
+                blend(image.get_pixel_mut(pixel_x, pixel_y), LinSrgba::new(color.red, color.green, color.blue, alpha));
+            }
+        }
+    }
+}
+
+fn draw_cross(image: &mut ImageBuffer<Rgb<u8>, Vec<u8>>, x: f32, y: f32, size: f32, color: LinSrgba) {
+    let half = size * 0.7;
+    for offset in [-0.5, 0.0, 0.5] {
+        draw_line(image, (x - half + offset, y - half), (x + half + offset, y + half), color);
+        draw_line(image, (x - half + offset, y + half), (x + half + offset, y - half), color);
+    }
+}
+
+fn draw_line(image: &mut ImageBuffer<Rgb<u8>, Vec<u8>>, start: (f32, f32), end: (f32, f32), color: LinSrgba) {
+    for ((x, y), alpha) in line_drawing::XiaolinWu::<f32, i32>::new(start, end) {
+        if y < 0 || y >= image.height() as i32 || x < 0 || x >= image.width() as i32 {
+            continue;
+        }
+        blend(
+            image.get_pixel_mut(x as u32, y as u32),
+            LinSrgba::new(color.red, color.green, color.blue, alpha * color.alpha),
+        );
+    }
+}
+
+fn blend(pixel: &mut Rgb<u8>, color: LinSrgba) {
+    if let [r, g, b] = pixel.channels() {
+        *pixel = Rgb::from([
+            ((color.alpha * color.red + (1.0 - color.alpha) * (*r as f32 / 255.0)) * 255.0) as u8,
+            ((color.alpha * color.green + (1.0 - color.alpha) * (*g as f32 / 255.0)) * 255.0) as u8,
+            ((color.alpha * color.blue + (1.0 - color.alpha) * (*b as f32 / 255.0)) * 255.0) as u8,
+        ]);
+    } else {
+        unreachable!();
+    }
+}
+
+fn kernel_density(positions: &[(f32, f32)], kernel: Kernel, bandwidth: f32, intensity_increment: f32, width: u32, height: u32) -> Vec<f32> {
+    let mut intensities = vec![0.0; (width * height) as usize];
+    let pixels_iters = (bandwidth * kernel.support()).ceil() as i32;
+    for &(x_f, y_f) in positions {
+        let x_i = x_f.round() as i32;
+        let y_i = y_f.round() as i32;
+        for y_offset in -pixels_iters..pixels_iters {
+            let y = y_i + y_offset;
+            if y < 0 || y >= height as i32 {
+                continue;
+            }
+            for x_offset in -pixels_iters..pixels_iters {
+                let x = x_i + x_offset;
+                if x < 0 || x >= width as i32 {
+                    continue;
+                }
+                let x_dist = x_f - x as f32;
+                let y_dist = y_f - y as f32;
+                let dist = (x_dist * x_dist + y_dist * y_dist).sqrt();
+                if let Some(weight) = kernel.weight(dist, bandwidth) {
+                    intensities[(y * width as i32 + x) as usize] += intensity_increment * weight;
+                }
+            }
+        }
+    }
+    intensities
+}
+
+// Pointy-top hexagons whose corners are one bandwidth away from their center
+fn hexagonal_binning(positions: &[(f32, f32)], bandwidth: f32, intensity_increment: f32, width: u32, height: u32) -> Vec<f32> {
+    let mut bins: HashMap<(i32, i32), f32> = HashMap::new();
+    for &(x, y) in positions {
+        *bins.entry(hex_bin(x, y, bandwidth)).or_default() += intensity_increment;
+    }
+    let mut intensities = Vec::with_capacity((width * height) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            intensities.push(bins.get(&hex_bin(x as f32, y as f32, bandwidth)).copied().unwrap_or_default());
+        }
+    }
+    intensities
+}
+
+// Axial coordinates of the hexagon containing the point, see https://www.redblobgames.com/grids/hexagons/#pixel-to-hex
+fn hex_bin(x: f32, y: f32, size: f32) -> (i32, i32) {
+    let q = (3.0_f32.sqrt() / 3.0 * x - y / 3.0) / size;
+    let r = (2.0 / 3.0 * y) / size;
+    let s = -q - r;
+    let (mut q_round, mut r_round, s_round) = (q.round(), r.round(), s.round());
+    let q_diff = (q_round - q).abs();
+    let r_diff = (r_round - r).abs();
+    let s_diff = (s_round - s).abs();
+    if q_diff > r_diff && q_diff > s_diff {
+        q_round = -r_round - s_round;
+    } else if r_diff > s_diff {
+        r_round = -q_round - s_round;
+    }
+    (q_round as i32, r_round as i32)
+}
+
 fn gaussian(x: f32, std_dev: f32) -> f32 {
     (-((x * x) / (2.0 * std_dev * std_dev))).exp()
 }
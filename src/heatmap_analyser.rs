@@ -1,3 +1,4 @@
+use crate::error::DemoError;
 use fnv::FnvHashMap;
 use num_enum::TryFromPrimitive;
 use serde::{Deserialize, Serialize};
@@ -30,6 +31,9 @@ use tf_demo_parser::{ParserState, ReadResult, Stream};
 
 const MAX_PLAYER_ENTITY: u32 = 34;
 
+// In seconds, how often the position of every player is recorded for the occupancy heatmaps
+pub const DEFAULT_SAMPLE_INTERVAL: f32 = 0.5;
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct ChatMessage {
     pub kind: ChatMessageKind,
@@ -161,6 +165,7 @@ pub struct Death {
     pub victim_entity_state: Option<PlayerEntity>,
     pub assister: Option<UserId>,
     pub assister_steamid: Option<String>,
+    pub assister_entity_state: Option<PlayerEntity>,
     pub killer: UserId,
     pub killer_steamid: String,
     pub killer_entity: u32, // probably the projectile entity rather than the killer'sm unless it's hitscan?
@@ -169,13 +174,19 @@ pub struct Death {
     pub round: u32,
     pub during_round: bool,
     pub sentry_position: Option<Vector>,
+    // In seconds, None when the demo doesn't contain the start of the match / round / victim's life.
+    // round_time is also None between rounds, e.g. during the humiliation after a round win
+    pub match_time: Option<f32>,
+    pub round_time: Option<f32>,
+    pub time_alive: Option<f32>,
 }
 
 impl Death {
     pub fn from_event(event: &PlayerDeathEvent, tick: u32, users: &BTreeMap<UserId, UserInfo>, round: u32, during_round: bool) -> Self {
+        // the SteamIDs of unknown users are left empty
         let (assister, assister_steamid) = if event.assister < (16 * 1024) {
             let assister = UserId::from(event.assister);
-            (Some(assister), Some(users.get(&assister).expect("Can't get assister").steam_id.clone()))
+            (Some(assister), users.get(&assister).map(|user| user.steam_id.clone()))
         } else {
             (None, None)
         };
@@ -188,11 +199,12 @@ impl Death {
         Death {
             assister,
             assister_steamid,
+            assister_entity_state: None,
             tick,
             round,
             during_round,
             killer,
-            killer_steamid: users.get(&killer).expect("Can't get killer").steam_id.clone(),
+            killer_steamid: users.get(&killer).map(|user| user.steam_id.clone()).unwrap_or_default(),
             killer_entity: if event.attacker == 0 {
                 event.victim_ent_index // if world killed the player, count it as a suicide
             } else {
@@ -201,10 +213,34 @@ impl Death {
             killer_entity_state: None,
             weapon: event.weapon.clone(),
             victim,
-            victim_steamid: users.get(&victim).expect("Can't get victim").steam_id.clone(),
+            victim_steamid: users.get(&victim).map(|user| user.steam_id.clone()).unwrap_or_default(),
             victim_entity: event.victim_ent_index,
             victim_entity_state: None,
             sentry_position: None,
+            match_time: None,
+            round_time: None,
+            time_alive: None,
+        }
+    }
+
+    pub fn distance_2d(&self) -> Option<f32> {
+        if let (Some(killer_entity), Some(victim_entity)) = (&self.killer_entity_state, &self.victim_entity_state) {
+            let distance_x = killer_entity.position.x - victim_entity.position.x;
+            let distance_y = killer_entity.position.y - victim_entity.position.y;
+            Some((distance_x * distance_x + distance_y * distance_y).sqrt())
+        } else {
+            None
+        }
+    }
+
+    pub fn distance_3d(&self) -> Option<f32> {
+        if let (Some(killer_entity), Some(victim_entity)) = (&self.killer_entity_state, &self.victim_entity_state) {
+            let distance_x = killer_entity.position.x - victim_entity.position.x;
+            let distance_y = killer_entity.position.y - victim_entity.position.y;
+            let distance_z = killer_entity.position.z - victim_entity.position.z;
+            Some((distance_x * distance_x + distance_y * distance_y + distance_z * distance_z).sqrt())
+        } else {
+            None
         }
     }
 }
@@ -224,12 +260,39 @@ impl Round {
             end_tick: tick,
         }
     }
+
+    pub fn end_tick(&self) -> u32 {
+        self.end_tick
+    }
+}
+
+// Where a player was at some tick, recorded every sample_interval
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PositionSample {
+    pub user: UserId,
+    pub tick: u32,
+    pub position: Vector,
+    pub team: Team,
+    pub class: Class,
+    pub state: PlayerState,
+    pub health: u16,
+    pub max_health: u16,
 }
 
 #[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct World {
-    boundary_min: Vector,
-    boundary_max: Vector,
+    pub boundary_min: Vector,
+    pub boundary_max: Vector,
+}
+
+// What the server said about itself when the demo started
+#[derive(Default, Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ServerInfo {
+    // "l" for Linux, "w" for Windows
+    pub platform: String,
+    pub stv: bool,
+    pub dedicated: bool,
+    pub max_player_count: u8,
 }
 
 #[derive(Default, Clone, Debug, Serialize, Deserialize, PartialEq)]
@@ -238,6 +301,7 @@ pub struct HeatmapAnalyser {
     prop_names: FnvHashMap<SendPropIdentifier, (SendTableName, SendPropName)>,
     user_id_map: HashMap<EntityId, UserId>,
     class_names: Vec<ServerClassName>, // indexed by ClassId
+    next_sample_tick: u32,
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
@@ -294,7 +358,15 @@ impl MessageHandler for HeatmapAnalyser {
         }
         self.state.current_tick = tick - self.state.tick_offset; // first tick = start of the demo rather than map change
         match message {
-            Message::ServerInfo(message) => self.state.interval_per_tick = message.interval_per_tick,
+            Message::ServerInfo(message) => {
+                self.state.interval_per_tick = message.interval_per_tick;
+                self.state.server_info = Some(ServerInfo {
+                    platform: message.platform.clone(),
+                    stv: message.stv,
+                    dedicated: message.dedicated,
+                    max_player_count: message.max_player_count,
+                });
+            }
             Message::GameEvent(message) => self.handle_event(&message.event, tick),
             Message::UserMessage(message) => self.handle_user_message(&message, tick),
             Message::PacketEntities(message) => {
@@ -309,6 +381,11 @@ impl MessageHandler for HeatmapAnalyser {
                 for removed_entity in &message.removed_entities {
                     let _removed = self.state.other_entities.remove(removed_entity);
                 }
+                if self.state.sample_interval > 0.0 && self.state.interval_per_tick > 0.0 && tick >= self.next_sample_tick {
+                    self.sample_positions(tick);
+                    let interval_ticks = (self.state.sample_interval / self.state.interval_per_tick).round().max(1.0) as u32;
+                    self.next_sample_tick = tick + interval_ticks;
+                }
             }
             _ => unreachable!(),
         }
@@ -345,6 +422,34 @@ impl BorrowMessageHandler for HeatmapAnalyser {
 }
 
 impl HeatmapAnalyser {
+    // sample_interval is in seconds, 0 doesn't record the positions of the players
+    pub fn new(sample_interval: f32) -> Self {
+        let mut analyser = Self::default();
+        analyser.state.sample_interval = sample_interval;
+        analyser
+    }
+
+    fn sample_positions(&mut self, tick: u32) {
+        for player in &self.state.player_entities {
+            // spectators and the world don't have a position worth keeping
+            if !matches!(player.team, Team::Red | Team::Blu) {
+                continue;
+            }
+            if let Some(user) = self.state.users.values().find(|user| user.entity_id == Some(player.entity)) {
+                self.state.position_samples.push(PositionSample {
+                    user: user.user_id,
+                    tick,
+                    position: player.position,
+                    team: player.team,
+                    class: player.class,
+                    state: player.state,
+                    health: player.health,
+                    max_health: player.max_health,
+                });
+            }
+        }
+    }
+
     pub fn handle_entity(&mut self, entity: &PacketEntity) {
         let class_name: &str = self.class_names.get(usize::from(entity.server_class)).map(|class_name| class_name.as_str()).unwrap_or("");
         match class_name {
@@ -488,19 +593,44 @@ impl HeatmapAnalyser {
             GameEvent::PlayerDeath(event) => {
                 let round = self.state.rounds.len() as u32 + 1;
                 let mut death = Death::from_event(event, tick, &self.state.users, round, self.state.in_round);
-                let killer = self.state.users.get_mut(&death.killer).expect("got a kill from unknown user");
-                if death.killer_entity < MAX_PLAYER_ENTITY {
-                    killer.entity_id = Some(EntityId::from(death.killer_entity));
-                }
-                if let Some(killer_entity) = killer.entity_id {
-                    death.killer_entity_state = Some(self.state.get_or_create_player_entity(killer_entity).clone());
+                let seconds_since = |start_tick: u32| tick.saturating_sub(start_tick) as f32 * self.state.interval_per_tick;
+                // The match starts with the first round, anything before that is pre-game
+                death.match_time = self.state.round_start_ticks.first().copied().map(seconds_since);
+                death.round_time = self.state.round_start_ticks.last().copied().filter(|_| death.during_round).map(seconds_since);
+                death.time_alive = self
+                    .state
+                    .spawns
+                    .iter()
+                    .rev()
+                    .find(|spawn| spawn.user == death.victim)
+                    .map(|spawn| seconds_since(spawn.tick));
+                // STV demos can start after some players joined, their deaths are kept without their positions
+                if let Some(killer) = self.state.users.get_mut(&death.killer) {
+                    if death.killer_entity < MAX_PLAYER_ENTITY {
+                        killer.entity_id = Some(EntityId::from(death.killer_entity));
+                    }
+                    if let Some(killer_entity) = killer.entity_id {
+                        death.killer_entity_state = Some(self.state.get_or_create_player_entity(killer_entity).clone());
+                    }
+                } else {
+                    self.warn(format!("Kill from unknown user {}", death.killer.0));
                 }
-                let victim = self.state.users.get_mut(&death.victim).expect("got a kill on unknown user");
-                if death.victim_entity < MAX_PLAYER_ENTITY {
-                    victim.entity_id = Some(EntityId::from(death.victim_entity));
+                if let Some(victim) = self.state.users.get_mut(&death.victim) {
+                    if death.victim_entity < MAX_PLAYER_ENTITY {
+                        victim.entity_id = Some(EntityId::from(death.victim_entity));
+                    }
+                    if let Some(victim_entity) = victim.entity_id {
+                        death.victim_entity_state = Some(self.state.get_or_create_player_entity(victim_entity).clone());
+                    }
+                } else {
+                    self.warn(format!("Kill on unknown user {}", death.victim.0));
                 }
-                if let Some(victim_entity) = victim.entity_id {
-                    death.victim_entity_state = Some(self.state.get_or_create_player_entity(victim_entity).clone());
+                if let Some(assister) = death.assister {
+                    match self.state.users.get(&assister).map(|assister| assister.entity_id) {
+                        Some(Some(assister_entity)) => death.assister_entity_state = Some(self.state.get_or_create_player_entity(assister_entity).clone()),
+                        Some(None) => {}
+                        None => self.warn(format!("Assist from unknown user {}", assister.0)),
+                    }
                 }
                 match death.weapon.as_str() {
                     "obj_sentrygun" | "obj_sentrygun2" | "obj_sentrygun3" | "obj_minisentry" => {
@@ -533,9 +663,11 @@ impl HeatmapAnalyser {
                 if let Some(user_state) = self.state.users.get_mut(&spawn.user) {
                     user_state.team = spawn.team;
                 }
+                self.state.spawns.push(spawn);
             }
             GameEvent::TeamPlayRoundStart(_event) => {
                 self.state.in_round = true;
+                self.state.round_start_ticks.push(tick);
             }
             GameEvent::TeamPlayRoundWin(event) => {
                 self.state.in_round = false;
@@ -547,16 +679,26 @@ impl HeatmapAnalyser {
         }
     }
 
+    fn warn(&mut self, message: String) {
+        self.state.warnings.push(DemoError::InconsistentState {
+            tick: self.state.current_tick,
+            message,
+        });
+    }
+
     fn parse_user_info(&mut self, text: Option<&str>, data: Option<Stream>) -> ReadResult<()> {
         if let Some(mut data) = data {
             let name: String = data.read_sized(32).unwrap_or_else(|_| "Malformed Name".into());
             let user_id: UserId = data.read::<u32>()?.into();
             let steam_id: String = data.read()?;
 
-            let entity_id = if let Some(slot_id) = text {
-                Some((slot_id.parse::<u32>().expect("can't parse player slot") + 1).into())
-            } else {
-                None
+            let entity_id = match text.map(|slot_id| (slot_id, slot_id.parse::<u32>())) {
+                Some((_, Ok(slot))) => Some((slot + 1).into()),
+                Some((slot_id, Err(_))) => {
+                    self.warn(format!("Can't parse the player slot \"{}\" of {}", slot_id, name));
+                    None
+                }
+                None => None,
             };
 
             if !steam_id.is_empty() {
@@ -591,12 +733,20 @@ pub struct HeatmapAnalysis {
     pub users: BTreeMap<UserId, UserInfo>,
     pub deaths: Vec<Death>,
     pub rounds: Vec<Round>,
+    pub round_start_ticks: Vec<u32>,
+    pub spawns: Vec<Spawn>,
     pub in_round: bool,
+    pub position_samples: Vec<PositionSample>,
+    // In seconds, 0 when the positions weren't sampled
+    pub sample_interval: f32,
 
     pub player_entities: Vec<PlayerEntity>,
     pub other_entities: HashMap<EntityId, OtherEntity>,
     pub world: Option<World>,
+    pub server_info: Option<ServerInfo>,
     pub map: String,
+    // Problems that were skipped over, with the tick they happened at
+    pub warnings: Vec<DemoError>,
 }
 
 impl Default for HeatmapAnalysis {
@@ -617,7 +767,11 @@ impl Default for HeatmapAnalysis {
             },
             deaths: Default::default(),
             rounds: Default::default(),
+            round_start_ticks: Default::default(),
+            spawns: Default::default(),
             in_round: Default::default(),
+            position_samples: Default::default(),
+            sample_interval: Default::default(),
             tick_offset: Default::default(),
             current_tick: Default::default(),
             interval_per_tick: Default::default(),
@@ -639,7 +793,9 @@ impl Default for HeatmapAnalysis {
             },
             other_entities: Default::default(),
             world: Default::default(),
+            server_info: Default::default(),
             map: Default::default(),
+            warnings: Default::default(),
         }
     }
 }
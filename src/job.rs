@@ -0,0 +1,312 @@
+use crate::{
+    annotations::{self, AnnotationInfo, Annotations},
+    camera::{self, Background, CameraFit},
+    comparison::Comparison,
+    export::DeathRecord,
+    filters::{Filter, FilterTrait},
+    gradient::HeatmapGradient,
+    heatmap::{CoordsType, HeatMapGenerator, HeatmapStyle, HeatmapType, Kernel, KillDeathOptions, OccupancyOptions},
+    heatmap_analyser::{Death, HeatmapAnalysis},
+    path::PathOptions,
+    query, report, svg,
+};
+use image::{io::Reader, RgbImage};
+use serde::{Deserialize, Serialize};
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+// Everything needed to render a heatmap, except for the demos themselves
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HeatmapJob {
+    // Relative paths are relative to the job file
+    pub image_path: Option<PathBuf>,
+    pub pos_x: f32,
+    pub pos_y: f32,
+    pub scale: f32,
+    #[serde(default)]
+    pub coords_type: CoordsType,
+    // Replaces the position and scale above when it finds something to fit
+    #[serde(default)]
+    pub camera_fit: CameraFit,
+    // Drawn instead of the level overview unless it's LevelOverview
+    #[serde(default)]
+    pub background: Background,
+    #[serde(default)]
+    pub heatmap_type: HeatmapType,
+    #[serde(default = "default_radius")]
+    pub radius: f32,
+    #[serde(default)]
+    pub kernel: Kernel,
+    // In Hammer units, None = use the radius
+    #[serde(default)]
+    pub bandwidth: Option<f32>,
+    // None = auto intensity
+    #[serde(default)]
+    pub intensity: Option<f32>,
+    #[serde(default)]
+    pub desaturate: f32,
+    #[serde(default = "default_use_sentry_position")]
+    pub use_sentry_position: bool,
+    #[serde(default)]
+    pub gradient: HeatmapGradient,
+    #[serde(default)]
+    pub annotations: Annotations,
+    #[serde(default)]
+    pub kill_death: KillDeathOptions,
+    #[serde(default)]
+    pub occupancy: OccupancyOptions,
+    #[serde(default)]
+    pub path: PathOptions,
+    // Renders the difference between two sets of deaths instead
+    #[serde(default)]
+    pub comparison: Option<Comparison>,
+    #[serde(default)]
+    pub filters: Vec<Filter>,
+}
+
+fn default_radius() -> f32 {
+    50.0
+}
+
+fn default_use_sentry_position() -> bool {
+    true
+}
+
+impl HeatmapJob {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let path = path.as_ref();
+        let mut job: HeatmapJob = serde_json::from_slice(&fs::read(path)?)?;
+        if let (Some(image_path), Some(job_dir)) = (&job.image_path, path.parent()) {
+            if image_path.is_relative() {
+                job.image_path = Some(job_dir.join(image_path));
+            }
+        }
+        Ok(job)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), Box<dyn Error>> {
+        let path = path.as_ref();
+        let mut job = self.clone();
+        // Store the image path relative to the job file when possible so the job can be shared along with the image
+        if let (Some(image_path), Some(job_dir)) = (&self.image_path, path.parent().and_then(|dir| dir.canonicalize().ok())) {
+            if let Ok(relative_path) = image_path.canonicalize().unwrap_or_else(|_| image_path.clone()).strip_prefix(&job_dir) {
+                job.image_path = Some(relative_path.to_path_buf());
+            }
+        }
+        fs::write(path, serde_json::to_string_pretty(&job)?)?;
+        Ok(())
+    }
+
+    // The level overview as it is on disk, the GUI desaturates it when drawing the heatmap
+    pub fn load_overview(&self) -> Result<RgbImage, Box<dyn Error>> {
+        let image_path = self.image_path.as_ref().ok_or("The job doesn't specify a level overview image")?;
+        Ok(Reader::open(image_path)?.with_guessed_format()?.decode()?.into_rgb8())
+    }
+
+    pub fn load_image(&self) -> Result<RgbImage, Box<dyn Error>> {
+        Ok(crate::apply_image_transformations(&self.load_overview()?, self.desaturate))
+    }
+
+    // The level overview, or a blank image to draw the background on
+    pub fn base_image(&self) -> Result<RgbImage, Box<dyn Error>> {
+        match self.background {
+            Background::LevelOverview => self.load_image(),
+            Background::Blank | Background::Grid => Ok(camera::blank_image(camera::BACKGROUND_WIDTH, camera::BACKGROUND_HEIGHT)),
+        }
+    }
+
+    // demos are (file name, map, analysis)
+    pub fn render(&self, demos: &[(&str, &str, &HeatmapAnalysis)]) -> Result<RgbImage, Box<dyn Error>> {
+        let image = self.base_image()?;
+        Ok(self.render_on(image, demos))
+    }
+
+    // Same as render, with a level overview that is already loaded and desaturated
+    pub fn render_on(&self, mut image: RgbImage, demos: &[(&str, &str, &HeatmapAnalysis)]) -> RgbImage {
+        let heatmap_generator = self.heatmap_generator(&image, demos);
+        self.draw_background(&mut image, &heatmap_generator);
+        // only densities can be subtracted from each other
+        let comparison = self
+            .comparison
+            .as_ref()
+            .filter(|_| matches!(self.heatmap_type, HeatmapType::VictimPosition | HeatmapType::KillerPosition | HeatmapType::AssisterPosition));
+        let kill_death = Some(&self.kill_death).filter(|_| self.heatmap_type == HeatmapType::KillDeath);
+        let occupancy = Some(&self.occupancy).filter(|_| self.heatmap_type == HeatmapType::Occupancy);
+        let path = Some(&self.path).filter(|_| self.heatmap_type == HeatmapType::Path);
+        let mut player_time = 0.0;
+        let mut kill_count = 0;
+        let (gradient_max, death_count) = match comparison {
+            Some(comparison) => {
+                let analyses: Vec<_> = demos.iter().map(|(file_name, _, heatmap_analysis)| (*file_name, *heatmap_analysis)).collect();
+                let (deaths_a, weight_a) = comparison.select(&comparison.set_a, &analyses, &self.filters);
+                let (deaths_b, weight_b) = comparison.select(&comparison.set_b, &analyses, &self.filters);
+                let gradient_max =
+                    heatmap_generator.generate_difference_heatmap(self.heatmap_type, deaths_a.iter().copied(), weight_a, deaths_b.iter().copied(), weight_b, &mut image);
+                (gradient_max, deaths_a.len() + deaths_b.len())
+            }
+            // the filters only apply to deaths
+            None if occupancy.is_some() => {
+                let samples: Vec<_> = demos
+                    .iter()
+                    .map(|(_, _, heatmap_analysis)| {
+                        let samples: Vec<_> = heatmap_analysis
+                            .position_samples
+                            .iter()
+                            .filter(|sample| self.occupancy.matches(sample, &heatmap_analysis.users))
+                            .collect();
+                        (heatmap_analysis.sample_interval, samples)
+                    })
+                    .collect();
+                player_time = samples.iter().map(|(sample_interval, samples)| sample_interval * samples.len() as f32).sum();
+                (heatmap_generator.generate_occupancy_heatmap(&samples, &mut image), 0)
+            }
+            None if path.is_some() => {
+                let paths: Vec<_> = demos.iter().filter_map(|(_, _, heatmap_analysis)| self.path.select(heatmap_analysis)).collect();
+                heatmap_generator.generate_path(&paths, self.path.coloring, &mut image);
+                kill_count = paths.iter().map(|path| path.kills.len()).sum();
+                (None, paths.iter().map(|path| path.deaths.len()).sum())
+            }
+            None => {
+                let deaths: Vec<_> = demos
+                    .iter()
+                    .flat_map(|(_, _, heatmap_analysis)| heatmap_analysis.deaths.iter())
+                    .filter(|death| self.filters.iter().all(|filter| filter.apply(death)))
+                    .collect();
+                let gradient_max = match kill_death {
+                    Some(kill_death) => heatmap_generator.generate_kill_death_heatmap(&deaths, kill_death, &mut image),
+                    None => heatmap_generator.generate_heatmap(self.heatmap_type, deaths.iter().copied(), &mut image),
+                };
+                (gradient_max, deaths.len())
+            }
+        };
+
+        let map = map_names(demos);
+        let path_player = player_name(demos, &self.path.player);
+        let info = AnnotationInfo {
+            map: &map,
+            demo_count: demos.len(),
+            death_count,
+            filters: &self.filters,
+            heatmap_type: self.heatmap_type,
+            gradient: &self.gradient,
+            comparison,
+            kill_death,
+            occupancy,
+            player_time,
+            path,
+            path_player: &path_player,
+            kill_count,
+            gradient_max,
+            units_per_pixel: heatmap_generator.units_per_pixel(),
+        };
+        annotations::annotate(&mut image, self.annotations, &info);
+        image
+    }
+
+    // Only the lines and the victim / killer positions can be drawn as vectors, one point per death
+    pub fn render_svg(&self, demos: &[(&str, &str, &HeatmapAnalysis)]) -> Result<String, Box<dyn Error>> {
+        let image = self.base_image()?;
+        self.render_svg_on(&image, demos)
+    }
+
+    pub fn render_svg_on(&self, image: &RgbImage, demos: &[(&str, &str, &HeatmapAnalysis)]) -> Result<String, Box<dyn Error>> {
+        if matches!(self.heatmap_type, HeatmapType::KillDeath | HeatmapType::Occupancy | HeatmapType::Path) {
+            return Err(format!("{} heatmaps can't be rendered as SVG", self.heatmap_type).into());
+        }
+        if self.comparison.is_some() && self.heatmap_type != HeatmapType::Lines {
+            return Err("Comparisons can't be rendered as SVG".into());
+        }
+        let heatmap_generator = self.heatmap_generator(image, demos);
+        let mut image = image.clone();
+        self.draw_background(&mut image, &heatmap_generator);
+        svg::render_svg(&heatmap_generator, self.heatmap_type, &self.gradient, &image, self.death_records(demos))
+    }
+
+    // Interactive html page with every death matching the filters, whatever the heatmap type
+    pub fn render_report(&self, demos: &[(&str, &str, &HeatmapAnalysis)]) -> Result<String, Box<dyn Error>> {
+        let image = self.base_image()?;
+        self.render_report_on(&image, demos)
+    }
+
+    pub fn render_report_on(&self, image: &RgbImage, demos: &[(&str, &str, &HeatmapAnalysis)]) -> Result<String, Box<dyn Error>> {
+        let heatmap_generator = self.heatmap_generator(image, demos);
+        let mut image = image.clone();
+        self.draw_background(&mut image, &heatmap_generator);
+        let deaths: Vec<_> = self.death_records(demos).collect();
+        let title = format!("coldmaps report: {}", map_names(demos));
+        let mut summary = format!(
+            "{} demo{}, {} death{}",
+            demos.len(),
+            if demos.len() > 1 { "s" } else { "" },
+            deaths.len(),
+            if deaths.len() > 1 { "s" } else { "" }
+        );
+        if !self.filters.is_empty() {
+            summary.push_str(&format!(" matching {}", query::format_filters(&self.filters)));
+        }
+        report::render_report(&heatmap_generator, &self.gradient, &image, &title, &summary, deaths)
+    }
+
+    fn death_records<'a>(&'a self, demos: &'a [(&'a str, &'a str, &'a HeatmapAnalysis)]) -> impl Iterator<Item = (&'a Death, DeathRecord)> + 'a {
+        demos.iter().flat_map(move |(file_name, map, heatmap_analysis)| {
+            heatmap_analysis
+                .deaths
+                .iter()
+                .filter(move |death| self.filters.iter().all(|filter| filter.apply(death)))
+                .map(move |death| (death, DeathRecord::new(file_name, map, heatmap_analysis, death)))
+        })
+    }
+
+    // The camera of the job, unless it's fitted to the demos
+    pub fn camera(&self, image: &RgbImage, demos: &[(&str, &str, &HeatmapAnalysis)]) -> (f32, f32, f32, CoordsType) {
+        let analyses: Vec<_> = demos.iter().map(|(_, _, heatmap_analysis)| *heatmap_analysis).collect();
+        match camera::fit_camera(self.camera_fit, &analyses, image.width(), image.height()) {
+            Some((pos_x, pos_y, scale)) => (pos_x, pos_y, scale, CoordsType::ShowPos),
+            None => (self.pos_x, self.pos_y, self.scale, self.coords_type),
+        }
+    }
+
+    fn draw_background(&self, image: &mut RgbImage, heatmap_generator: &HeatMapGenerator) {
+        if self.background == Background::LevelOverview {
+            return;
+        }
+        *image = camera::blank_image(image.width(), image.height());
+        if self.background == Background::Grid {
+            heatmap_generator.draw_grid(image);
+        }
+    }
+
+    fn heatmap_generator(&self, image: &RgbImage, demos: &[(&str, &str, &HeatmapAnalysis)]) -> HeatMapGenerator {
+        let (pos_x, pos_y, scale, coords_type) = self.camera(image, demos);
+        let style = HeatmapStyle {
+            radius: self.radius,
+            kernel: self.kernel,
+            bandwidth: self.bandwidth,
+            intensity: self.intensity,
+            use_sentry_position: self.use_sentry_position,
+            gradient: self.gradient.clone(),
+        };
+        HeatMapGenerator::new(pos_x, pos_y, image.width(), image.height(), scale, coords_type, &style)
+    }
+}
+
+fn map_names(demos: &[(&str, &str, &HeatmapAnalysis)]) -> String {
+    let mut maps: Vec<&str> = demos.iter().map(|(_, map, _)| *map).collect();
+    maps.sort_unstable();
+    maps.dedup();
+    maps.join(", ")
+}
+
+// The last name the player used, or their SteamID if they aren't in any of the demos
+fn player_name(demos: &[(&str, &str, &HeatmapAnalysis)], steam_id: &str) -> String {
+    demos
+        .iter()
+        .rev()
+        .flat_map(|(_, _, heatmap_analysis)| heatmap_analysis.users.values())
+        .find(|user| user.steam_id == steam_id)
+        .map(|user| user.name.clone())
+        .unwrap_or_else(|| steam_id.to_string())
+}
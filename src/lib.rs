@@ -1,71 +1,191 @@
+pub mod annotations;
+pub mod cache;
+pub mod camera;
+pub mod comparison;
+pub mod error;
+pub mod export;
 pub mod filters;
+pub mod gradient;
 pub mod heatmap;
 pub mod heatmap_analyser;
+pub mod job;
+pub mod maps;
+pub mod metadata;
+pub mod path;
+pub mod query;
+pub mod report;
+pub mod svg;
+pub mod weapons;
 
-use heatmap_analyser::{Death, HeatmapAnalyser, HeatmapAnalysis};
+use cache::{AnalysisCache, CachedAnalysis};
+use error::DemoError;
+use heatmap_analyser::{Death, HeatmapAnalyser, HeatmapAnalysis, DEFAULT_SAMPLE_INTERVAL};
 use image::{ImageBuffer, Rgb};
+use metadata::DemoMetadata;
 use rayon::prelude::*;
-use std::{fs, path::PathBuf};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 
-use heatmap::{CoordsType, HeatmapType};
+use heatmap::{CoordsType, HeatMapGenerator, HeatmapStyle, HeatmapType, Kernel};
 use tf_demo_parser::{Demo, DemoParser};
 
+// How many ticks are parsed between two progress events of a demo, about 15 seconds of game
+const PROGRESS_TICKS: u32 = 1000;
+
 #[derive(Debug, Clone, Default)]
 pub struct DemoProcessingOutput {
     pub path: PathBuf,
     pub heatmap_analysis: Option<HeatmapAnalysis>,
-    pub error: Option<String>,
+    // Why there is no analysis, or why it stops before the end of the demo
+    pub error: Option<DemoError>,
     pub map: String,
+    // None when the header couldn't be read
+    pub metadata: Option<DemoMetadata>,
+}
+
+impl DemoProcessingOutput {
+    // The error, then the warnings of the analysis
+    pub fn problems(&self) -> impl Iterator<Item = &DemoError> {
+        let warnings = self.heatmap_analysis.iter().flat_map(|heatmap_analysis| &heatmap_analysis.warnings);
+        self.error.iter().chain(warnings)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum ProcessingEvent {
+    // index is the position of the demo in the inputs
+    Progress { index: usize, tick: u32, ticks: u32 },
+    Done { index: usize, output: Box<DemoProcessingOutput> },
+}
+
+// Shared between the caller and the workers, the demos being parsed stop at their next progress event once cancelled
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
 }
 
 pub fn process_demos(inputs: Vec<PathBuf>) -> Vec<DemoProcessingOutput> {
+    process_demos_with_interval(inputs, None, DEFAULT_SAMPLE_INTERVAL)
+}
+
+// Same as process_demos, but the analyses are looked up in the cache first, and stored there once parsed
+pub fn process_demos_cached(inputs: Vec<PathBuf>, cache: &AnalysisCache) -> Vec<DemoProcessingOutput> {
+    process_demos_with_interval(inputs, Some(cache), DEFAULT_SAMPLE_INTERVAL)
+}
+
+// sample_interval is how often the positions of the players are recorded, in seconds
+pub fn process_demos_with_interval(inputs: Vec<PathBuf>, cache: Option<&AnalysisCache>, sample_interval: f32) -> Vec<DemoProcessingOutput> {
     inputs
         .par_iter()
-        .map(|path| {
-            let file = match fs::read(&path) {
-                Ok(file) => file,
-                Err(err) => {
-                    return DemoProcessingOutput {
-                        path: path.clone(),
-                        heatmap_analysis: None,
-                        error: Some(err.to_string()),
-                        map: String::new(),
+        .filter_map(|path| process_demo(path, cache, sample_interval, &CancelToken::default(), |_, _| ()))
+        .collect()
+}
+
+// Calls on_event with the progress of each demo and with its output as soon as it's done, returns once they all are or the batch is cancelled.
+// The cancelled demos don't get a Done event.
+pub fn process_demos_streaming(inputs: &[PathBuf], cache: Option<&AnalysisCache>, sample_interval: f32, cancel: &CancelToken, on_event: impl Fn(ProcessingEvent) + Sync) {
+    inputs.par_iter().enumerate().for_each(|(index, path)| {
+        if cancel.is_cancelled() {
+            return;
+        }
+        let output = process_demo(path, cache, sample_interval, cancel, |tick, ticks| {
+            on_event(ProcessingEvent::Progress { index, tick, ticks })
+        });
+        if let Some(output) = output {
+            on_event(ProcessingEvent::Done { index, output: Box::new(output) });
+        }
+    });
+}
+
+// None when cancelled
+fn process_demo(path: &PathBuf, cache: Option<&AnalysisCache>, sample_interval: f32, cancel: &CancelToken, progress: impl Fn(u32, u32)) -> Option<DemoProcessingOutput> {
+    let file = match fs::read(path) {
+        Ok(file) => file,
+        Err(err) => {
+            return Some(DemoProcessingOutput {
+                path: path.clone(),
+                heatmap_analysis: None,
+                error: Some(DemoError::Io(err.to_string())),
+                map: String::new(),
+                metadata: None,
+            })
+        }
+    };
+    let hash = cache.map(|_| AnalysisCache::hash_demo(&file));
+    if let (Some(cache), Some(hash)) = (cache, &hash) {
+        // the positions have to be sampled again when the interval changes
+        if let Some(cached) = cache.get(hash).filter(|cached| cached.heatmap_analysis.sample_interval == sample_interval) {
+            return Some(analysed_output(path, cached));
+        }
+    }
+    let demo = Demo::owned(file);
+    let (header, mut ticker) = match DemoParser::new_with_analyser(demo.get_stream(), HeatmapAnalyser::new(sample_interval)).ticker() {
+        Ok(ticker) => ticker,
+        Err(err) => {
+            return Some(DemoProcessingOutput {
+                path: path.clone(),
+                heatmap_analysis: None,
+                error: Some(DemoError::Header(err.to_string())),
+                map: String::new(),
+                metadata: None,
+            })
+        }
+    };
+    let mut reported_tick = 0;
+    let (heatmap_analysis, error) = loop {
+        match ticker.tick() {
+            Ok(true) => {
+                let tick = ticker.state().current_tick;
+                if tick >= reported_tick + PROGRESS_TICKS {
+                    if cancel.is_cancelled() {
+                        return None;
                     }
+                    reported_tick = tick;
+                    progress(tick, header.ticks);
                 }
-            };
-            let demo = Demo::owned(file);
-            let (header, mut ticker) = DemoParser::new_with_analyser(demo.get_stream(), HeatmapAnalyser::default()).ticker().unwrap();
-            loop {
-                match ticker.tick() {
-                    Ok(true) => continue,
-                    Ok(false) => {
-                        break DemoProcessingOutput {
-                            path: path.clone(),
-                            heatmap_analysis: Some(ticker.into_state()),
-                            error: None,
-                            map: header.map,
-                        }
-                    }
-                    Err(_err) => {
-                        let heatmap_analysis = ticker.into_state();
-                        let error = Some(format!(
-                            "{}: Demo is corrupted, could only analyse up to tick {}",
-                            path.to_string_lossy(),
-                            heatmap_analysis.current_tick
-                        ));
-                        break DemoProcessingOutput {
-                            path: path.clone(),
-                            heatmap_analysis: Some(heatmap_analysis),
-                            map: header.map,
-                            error,
-                        };
-                    }
-                };
             }
-        })
-        .collect()
+            Ok(false) => break (ticker.into_state(), None),
+            Err(err) => {
+                let tick = ticker.state().current_tick;
+                break (ticker.into_state(), Some(DemoError::Corrupted { tick, message: err.to_string() }));
+            }
+        };
+    };
+    let mut cached = CachedAnalysis { header, error, heatmap_analysis };
+    if let (Some(cache), Some(hash)) = (cache, &hash) {
+        // only this output gets the warning, not the cached analysis
+        if let Err(err) = cache.insert(hash, &cached) {
+            cached.heatmap_analysis.warnings.push(DemoError::CacheWrite(err.to_string()));
+        }
+    }
+    Some(analysed_output(path, cached))
+}
+
+fn analysed_output(path: &Path, cached: CachedAnalysis) -> DemoProcessingOutput {
+    DemoProcessingOutput {
+        path: path.to_path_buf(),
+        error: cached.error,
+        map: cached.header.map.clone(),
+        metadata: Some(DemoMetadata::new(cached.header, &cached.heatmap_analysis)),
+        heatmap_analysis: Some(cached.heatmap_analysis),
+    }
 }
 
+// Draws with the gaussian kernel and the classic colors, HeatmapJob renders with every option
 pub fn generate_heatmap<'a>(
     heatmap_type: HeatmapType,
     deaths: impl IntoIterator<Item = &'a Death>,
@@ -80,7 +200,39 @@ pub fn generate_heatmap<'a>(
     intensity: Option<f32>,
     use_sentry_position: bool,
 ) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
-    let heatmap_generator = heatmap::HeatMapGenerator::new(pos_x, pos_y, screen_width, screen_height, scale, coords_type, radius, intensity, use_sentry_position);
+    let style = HeatmapStyle {
+        radius,
+        kernel: Kernel::Gaussian,
+        bandwidth: None,
+        intensity,
+        use_sentry_position,
+        gradient: Default::default(),
+    };
+    let heatmap_generator = HeatMapGenerator::new(pos_x, pos_y, screen_width, screen_height, scale, coords_type, &style);
     heatmap_generator.generate_heatmap(heatmap_type, deaths, &mut image);
     image
 }
+
+// just desaturate for now
+pub fn apply_image_transformations(image: &ImageBuffer<Rgb<u8>, Vec<u8>>, desaturate: f32) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
+    let desaturate = desaturate / 100.0;
+    let mut output_image = ImageBuffer::new(image.width(), image.height());
+
+    for (x, y, pixel) in image.enumerate_pixels() {
+        let Rgb(data) = *pixel;
+
+        // Convert the pixel to grayscale
+        let gray_value = (0.3 * data[0] as f32 + 0.59 * data[1] as f32 + 0.11 * data[2] as f32) as u8;
+
+        // Linearly interpolate between the original pixel and the grayscale value
+        let new_pixel = Rgb([
+            ((1.0 - desaturate) * data[0] as f32 + desaturate * gray_value as f32) as u8,
+            ((1.0 - desaturate) * data[1] as f32 + desaturate * gray_value as f32) as u8,
+            ((1.0 - desaturate) * data[2] as f32 + desaturate * gray_value as f32) as u8,
+        ]);
+
+        output_image.put_pixel(x, y, new_pixel);
+    }
+
+    output_image
+}
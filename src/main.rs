@@ -2,25 +2,41 @@ use chat::format_chat_messages;
 use coldmaps::*;
 
 mod chat;
+mod cli;
 mod demo_player;
 mod demostf;
 mod gui_filters;
 mod style;
 
-use filters::{FilterTrait, OrderedOperator, Property, PropertyOperator};
-use gui_filters::{FilterType, FiltersPane};
-use heatmap::{CoordsType, HeatmapType};
-use heatmap_analyser::{HeatmapAnalysis, Team};
+use annotations::Annotations;
+use cache::AnalysisCache;
+use camera::{Background, CameraFit};
+use comparison::{Comparison, ComparisonSet, Normalization};
+use export::{DeathRecord, ExportFormat};
+use filters::{Filter, FilterTrait, OrderedOperator, Property, PropertyOperator};
+use gradient::{GradientPreset, HeatmapGradient};
+use gui_filters::{FilterPath, FilterType, FiltersPane, PlayerChoice, WeaponChoice};
+use heatmap::{CoordsType, HeatmapType, KillDeathMetric, KillDeathOptions, KillDeathSubject, Kernel, OccupancyOptions};
+use heatmap_analyser::{HeatmapAnalysis, Team, DEFAULT_SAMPLE_INTERVAL};
 use iced::{
-    alignment, button, executor, image::Handle, pane_grid, scrollable, slider, text_input, window, Application, Button, Checkbox, Column, Command, Container, Element, Font, Image,
-    Length, Point, Radio, Rectangle, Row, Scrollable, Settings, Size, Slider, Subscription, Text, TextInput,
+    alignment, button, executor,
+    futures::{channel::mpsc, future, StreamExt},
+    image::Handle,
+    pane_grid, pick_list, scrollable, slider, text_input, window, Alignment, Application, Button, Checkbox, Column, Command, Container, Element, Font, Image, Length, PickList,
+    Point, ProgressBar, Radio, Rectangle, Row, Scrollable, Settings, Size, Slider, Subscription, Text, TextInput,
 };
 use image::{io::Reader, ImageBuffer, Pixel, Rgb, RgbImage};
+use job::HeatmapJob;
+use maps::{MapDatabase, MapEntry};
+use metadata::DemoMetadata;
+use path::{PathColoring, PathOptions, PathRange};
 use pane_grid::{Axis, Pane};
 use rfd::AsyncFileDialog;
-use std::{mem, path::PathBuf, time::Instant};
+use std::{collections::BTreeMap, error::Error, mem, path::{Path, PathBuf}, time::Instant};
 
 const VERSION: Option<&'static str> = option_env!("CARGO_PKG_VERSION");
+// Per demo, so that a demo with a broken user list doesn't flood the log
+const MAX_LOGGED_PROBLEMS: usize = 10;
 
 const ICONS: Font = Font::External {
     name: "Icons",
@@ -50,6 +66,13 @@ pub fn main() -> Result<(), iced::Error> {
             demo_player::run().unwrap();
             return Ok(());
         }
+        if arg == "--cli" {
+            if let Err(err) = cli::run(&args[2..]) {
+                eprintln!("{}", err);
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
     }
     App::run(Settings {
         antialiasing: true,
@@ -67,6 +90,10 @@ struct App {
     busy: bool,
     // TODO visual indicator?
     dropped_files: Vec<PathBuf>,
+    batch: Option<Batch>,
+    batch_count: u64,
+    // Of the map groups that aren't selected
+    map_settings: BTreeMap<String, MapSettings>,
     demos_pane: Pane,
     filters_pane: Pane,
     settings_pane: Pane,
@@ -74,6 +101,45 @@ struct App {
     log_pane: Pane,
 }
 
+// Demos being processed in the background, they are added to the list as they finish
+struct Batch {
+    // Identifies the subscription, so that each batch gets a new one
+    id: u64,
+    inputs: Vec<PathBuf>,
+    sample_interval: f32,
+    cancel: CancelToken,
+    // Of each demo, from 0 to 1
+    progress: Vec<f32>,
+    demo_count: usize,
+    death_count: usize,
+    chrono: Instant,
+}
+
+// Camera and level overview of a map group, kept while another group is selected
+#[derive(Default)]
+struct MapSettings {
+    x_pos_input: String,
+    y_pos_input: String,
+    scale_input: String,
+    coords_type: CoordsType,
+    camera_fit: CameraFit,
+    background: Background,
+    image: Option<RgbImage>,
+    image_path: Option<PathBuf>,
+}
+
+impl MapSettings {
+    fn has_camera(&self) -> bool {
+        !self.x_pos_input.is_empty() || self.image.is_some()
+    }
+}
+
+enum BatchState {
+    Starting(Vec<PathBuf>, f32, CancelToken),
+    Processing(mpsc::UnboundedReceiver<ProcessingEvent>),
+    Finished,
+}
+
 struct HeatmapImage {
     image: ImageBuffer<Rgb<u8>, Vec<u8>>,
     image_with_heatmap_overlay: ImageBuffer<Rgb<u8>, Vec<u8>>,
@@ -84,9 +150,23 @@ struct HeatmapImage {
 struct DemoFile {
     _path: PathBuf,
     file_name: String,
+    map: String,
     delete_button: button::State,
     chat_preview_button: button::State,
+    details_button: button::State,
+    // Whether the metadata is shown under the file name
+    expanded: bool,
     heatmap_analysis: HeatmapAnalysis,
+    metadata: Option<DemoMetadata>,
+}
+
+// Camera and level overview of a map, from the map database or from demos.tf
+#[derive(Debug, Clone)]
+struct LoadedMap {
+    map: String,
+    source: String,
+    entry: MapEntry,
+    image: Option<RgbImage>,
 }
 
 #[derive(Debug, Clone)]
@@ -94,9 +174,13 @@ enum Message {
     WindowEventOccurred(iced_native::Event),
     PaneResized(pane_grid::ResizeEvent),
     DemoRemoved(usize),
+    DemoDetailsToggled(usize),
+    MapGroupSelected(MapGroup),
     ChatPreview(usize),
     ThemeChanged(style::Theme),
     CoordsTypeChanged(CoordsType),
+    CameraFitSelected(CameraFit),
+    BackgroundSelected(Background),
     HeatmapTypeChanged(HeatmapType),
     XPosInputChanged(String),
     YPosInputChanged(String),
@@ -105,30 +189,64 @@ enum Message {
     UseSentryPositionCheckboxToggled(bool),
     IntensityChanged(f32),
     RadiusChanged(f32),
+    KernelSelected(Kernel),
+    HammerUnitsBandwidthToggled(bool),
+    BandwidthChanged(f32),
+    GradientSelected(GradientChoice),
+    LegendCheckboxToggled(bool),
+    ScaleBarCheckboxToggled(bool),
+    TitleCheckboxToggled(bool),
+    ComparisonCheckboxToggled(bool),
+    SetAFiltersChanged(String),
+    SetBFiltersChanged(String),
+    SetADemosChanged(String),
+    SetBDemosChanged(String),
+    NormalizationSelected(Normalization),
+    KillDeathSubjectSelected(KillDeathSubjectChoice),
+    KillDeathMetricSelected(KillDeathMetric),
+    MinSamplesChanged(f32),
+    OccupancyTeamSelected(TeamChoice),
+    OccupancyPlayerSelected(OccupancyPlayerChoice),
+    OccupancyClassToggled(usize, bool),
+    AliveOnlyToggled(bool),
+    SampleIntervalChanged(f32),
+    PathPlayerSelected(PlayerChoice),
+    PathRangeKindSelected(PathRangeKind),
+    PathRangeInputChanged(String),
+    PathColoringSelected(PathColoring),
+    GradientFileSelected(Option<PathBuf>),
     DesaturateChanged(f32),
-    ProcessDemosDone(TimedResult<Vec<DemoProcessingOutput>>),
+    DemoProcessed(ProcessingEvent),
+    ProcessDemosDone,
+    CancelProcessingPressed,
     ExportImagePressed,
     ImageNameSelected(Option<PathBuf>),
+    ExportDeathsPressed,
+    DeathsExportNameSelected(Option<PathBuf>),
+    SaveJobPressed,
+    JobSaveNameSelected(Option<PathBuf>),
+    LoadJobPressed,
+    JobLoadNameSelected(Option<PathBuf>),
     EndOfDemoFilesDrop(()),
     MapSet(String),
-    DemosTFImageLoader(Option<(RgbImage, f32, f32, f32)>),
+    MapLoaded(Result<Option<LoadedMap>, String>),
+    SaveMapPressed,
     LevelImageSet(RgbImage),
     AddFilter,
-    FilterSelected(usize, FilterType),
-    ClassIconClicked(usize, usize),
-    BluTeamClicked(usize),
-    RedTeamClicked(usize),
-    OrderedOperatorSelected(usize, OrderedOperator),
-    PropertyOperatorSelected(usize, PropertyOperator),
-    PropertySelected(usize, Property),
-    FilterTextInputChanged(usize, String),
-    FilterRemoved(usize),
-}
-
-#[derive(Debug, Clone)]
-struct TimedResult<T: std::fmt::Debug + Clone> {
-    result: T,
-    time_elapsed: f32,
+    AddChildFilter(FilterPath),
+    FilterSelected(FilterPath, FilterType),
+    ClassIconClicked(FilterPath, usize),
+    BluTeamClicked(FilterPath),
+    RedTeamClicked(FilterPath),
+    OrderedOperatorSelected(FilterPath, OrderedOperator),
+    PropertyOperatorSelected(FilterPath, PropertyOperator),
+    PropertySelected(FilterPath, Property),
+    WeaponChoiceSelected(FilterPath, WeaponChoice),
+    PlayerSelected(FilterPath, PlayerChoice),
+    FilterTextInputChanged(FilterPath, String),
+    FilterRemoved(FilterPath),
+    FilterQueryChanged(String),
+    FilterQuerySubmitted,
 }
 
 enum PaneState {
@@ -157,10 +275,48 @@ struct DemoList {
     busy: bool,
     scroll_state: scrollable::State,
     demo_files: Vec<DemoFile>,
+    // The heatmap only shows the demos of that map, their coordinates don't match the ones of other maps
+    selected_map: Option<String>,
+    map_pick_list: pick_list::State<MapGroup>,
+    // Of the batch being processed
+    progress: Option<f32>,
+    cancel_button: button::State,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct MapGroup {
+    map: String,
+    demo_count: usize,
+}
+
+impl std::fmt::Display for MapGroup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({} demo{})", self.map, self.demo_count, if self.demo_count > 1 { "s" } else { "" })
+    }
 }
 
 impl DemoList {
+    fn selected_demos(&self) -> impl Iterator<Item = &DemoFile> {
+        self.demo_files.iter().filter(move |demo_file| self.selected_map.as_ref() == Some(&demo_file.map))
+    }
+
+    // In the order the maps were loaded
+    fn map_groups(&self) -> Vec<MapGroup> {
+        let mut groups: Vec<MapGroup> = Vec::new();
+        for demo_file in &self.demo_files {
+            match groups.iter_mut().find(|group| group.map == demo_file.map) {
+                Some(group) => group.demo_count += 1,
+                None => groups.push(MapGroup {
+                    map: demo_file.map.clone(),
+                    demo_count: 1,
+                }),
+            }
+        }
+        groups
+    }
+
     fn view(&mut self) -> Element<Message> {
+        let map_groups = self.map_groups();
         let (demos_list, style): (Element<_>, _) = if self.demo_files.is_empty() {
             (
                 Container::new(
@@ -175,6 +331,7 @@ impl DemoList {
             )
         } else {
             let theme = self.theme;
+            let selected_map = &self.selected_map;
             (
                 self.demo_files
                     .iter_mut()
@@ -184,12 +341,29 @@ impl DemoList {
                         let chat_preview_button = Button::new(&mut demo.chat_preview_button, chat_preview_icon())
                             .style(theme)
                             .on_press(Message::ChatPreview(index));
+                        let details_button = Button::new(&mut demo.details_button, Text::new(if demo.expanded { "-" } else { "+" }).size(20))
+                            .style(theme)
+                            .on_press(Message::DemoDetailsToggled(index));
                         let row = Row::new()
                             .spacing(2)
                             .push(delete_button)
                             .push(chat_preview_button)
-                            .push(Text::new(&demo.file_name).size(20));
-                        column.push(row)
+                            .push(details_button)
+                            .push(if selected_map.as_ref() == Some(&demo.map) {
+                                Text::new(&demo.file_name).size(20)
+                            } else {
+                                Text::new(&demo.file_name).size(20).color([0.5, 0.5, 0.5])
+                            });
+                        let column = column.push(row);
+                        match &demo.metadata {
+                            Some(metadata) if demo.expanded => {
+                                let details = demo_details(metadata)
+                                    .into_iter()
+                                    .fold(Column::new().spacing(2).padding([0, 0, 0, 40]), |details, line| details.push(Text::new(line).size(16)));
+                                column.push(details)
+                            }
+                            _ => column,
+                        }
                     })
                     .into(),
                 style::ResultContainer::Ok,
@@ -205,7 +379,246 @@ impl DemoList {
             .padding(10)
             .style(style);
 
-        Container::new(result_container).padding(4).width(Length::Fill).height(Length::Fill).into()
+        let mut content = Column::new().spacing(4);
+        // the heatmap of a group can't contain the deaths of the other ones, so there is only something to pick with several maps
+        if map_groups.len() > 1 {
+            let selected_group = map_groups.iter().find(|group| self.selected_map.as_ref() == Some(&group.map)).cloned();
+            let map_pick_list = PickList::new(&mut self.map_pick_list, map_groups, selected_group, Message::MapGroupSelected);
+            let map_row = Row::new()
+                .spacing(10)
+                .padding([0, 0, 0, 6])
+                .align_items(Alignment::Center)
+                .push(Text::new("Map:"))
+                .push(map_pick_list);
+            content = content.push(map_row);
+        }
+        content = content.push(result_container);
+        if let Some(progress) = self.progress {
+            let progress_row = Row::new()
+                .spacing(4)
+                .align_items(Alignment::Center)
+                .push(ProgressBar::new(0.0..=1.0, progress).height(Length::Units(20)).style(self.theme))
+                .push(
+                    Button::new(&mut self.cancel_button, Text::new("Cancel"))
+                        .style(self.theme)
+                        .on_press(Message::CancelProcessingPressed),
+                );
+            content = content.push(progress_row);
+        }
+
+        Container::new(content).padding(4).width(Length::Fill).height(Length::Fill).into()
+    }
+}
+
+fn demo_details(metadata: &DemoMetadata) -> Vec<String> {
+    let header = &metadata.header;
+    let recorder = match metadata.is_stv() {
+        Some(true) => " (STV)",
+        Some(false) => " (POV)",
+        None => "",
+    };
+    let format_time = |seconds: f32| {
+        let seconds = seconds.max(0.0) as u32;
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    };
+    vec![
+        format!("Server: {}", header.server),
+        format!("Recorded by: {}{}", header.nick, recorder),
+        format!("Map: {}", header.map),
+        format!("Duration: {} ({} ticks)", format_time(header.duration), header.ticks),
+        format!("Platform: {}", metadata.platform().unwrap_or("unknown")),
+        format!("Players: {}", metadata.player_count),
+        format!(
+            "Score: BLU {} - {} RED ({} round{})",
+            metadata.blu_score,
+            metadata.red_score,
+            metadata.round_count,
+            if metadata.round_count == 1 { "" } else { "s" }
+        ),
+        format!("Match length: {}", metadata.match_length.map_or_else(|| "unknown".into(), format_time)),
+    ]
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum GradientChoice {
+    Preset(GradientPreset),
+    Custom,
+}
+
+impl GradientChoice {
+    const ALL: [GradientChoice; 6] = [
+        GradientChoice::Preset(GradientPreset::Classic),
+        GradientChoice::Preset(GradientPreset::Viridis),
+        GradientChoice::Preset(GradientPreset::Magma),
+        GradientChoice::Preset(GradientPreset::Colorblind),
+        GradientChoice::Preset(GradientPreset::Monochrome),
+        GradientChoice::Custom,
+    ];
+}
+
+impl std::fmt::Display for GradientChoice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GradientChoice::Preset(preset) => write!(f, "{}", preset),
+            GradientChoice::Custom => write!(f, "Custom (from a file)"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct KillDeathSubjectChoice {
+    subject: KillDeathSubject,
+    label: String,
+}
+
+impl std::fmt::Display for KillDeathSubjectChoice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.label)
+    }
+}
+
+impl From<KillDeathSubject> for KillDeathSubjectChoice {
+    fn from(subject: KillDeathSubject) -> Self {
+        Self { label: subject.to_string(), subject }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct TeamChoice(Option<Team>);
+
+impl TeamChoice {
+    const ALL: [TeamChoice; 3] = [TeamChoice(None), TeamChoice(Some(Team::Red)), TeamChoice(Some(Team::Blu))];
+}
+
+impl std::fmt::Display for TeamChoice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            Some(Team::Red) => write!(f, "RED"),
+            Some(Team::Blu) => write!(f, "BLU"),
+            Some(team) => write!(f, "{:?}", team),
+            None => write!(f, "Both teams"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct OccupancyPlayerChoice {
+    // SteamIDs, empty = everyone
+    players: Vec<String>,
+    label: String,
+}
+
+impl OccupancyPlayerChoice {
+    fn everyone() -> Self {
+        Self {
+            players: Vec::new(),
+            label: "Everyone".into(),
+        }
+    }
+}
+
+impl std::fmt::Display for OccupancyPlayerChoice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.label)
+    }
+}
+
+const OCCUPANCY_CLASSES: [(usize, &str); 9] = [
+    (1, "Scout"),
+    (3, "Soldier"),
+    (7, "Pyro"),
+    (4, "Demoman"),
+    (6, "Heavy"),
+    (9, "Engineer"),
+    (5, "Medic"),
+    (2, "Sniper"),
+    (8, "Spy"),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PathRangeKind {
+    All,
+    Life,
+    Ticks,
+}
+
+impl PathRangeKind {
+    const ALL: [PathRangeKind; 3] = [PathRangeKind::All, PathRangeKind::Life, PathRangeKind::Ticks];
+
+    // The text input holds the life number or the "start-end" ticks
+    fn parse(self, input: &str) -> Option<PathRange> {
+        match self {
+            PathRangeKind::All => Some(PathRange::All),
+            PathRangeKind::Life => input.trim().parse().ok().filter(|life| *life > 0).map(PathRange::Life),
+            PathRangeKind::Ticks => {
+                let (start, end) = input.split_once('-')?;
+                let (start, end) = (start.trim().parse().ok()?, end.trim().parse().ok()?);
+                Some(PathRange::Ticks { start, end }).filter(|_| start <= end)
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for PathRangeKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PathRangeKind::All => write!(f, "Whole demo"),
+            PathRangeKind::Life => write!(f, "One life"),
+            PathRangeKind::Ticks => write!(f, "Tick range"),
+        }
+    }
+}
+
+struct ComparisonSetInput {
+    filters_input_state: text_input::State,
+    filters_input: String,
+    // None when the query can't be parsed
+    filters: Option<Vec<Filter>>,
+    demos_input_state: text_input::State,
+    demos_input: String,
+}
+
+impl Default for ComparisonSetInput {
+    fn default() -> Self {
+        Self {
+            filters_input_state: Default::default(),
+            filters_input: Default::default(),
+            filters: Some(Vec::new()),
+            demos_input_state: Default::default(),
+            demos_input: Default::default(),
+        }
+    }
+}
+
+impl ComparisonSetInput {
+    fn set_filters_input(&mut self, input: String) {
+        self.filters = query::parse_filters(&input).ok();
+        self.filters_input = input;
+    }
+
+    fn apply_set(&mut self, set: &ComparisonSet) {
+        self.filters_input = query::format_filters(&set.filters);
+        self.filters = Some(set.filters.clone());
+        self.demos_input = set.demos.join(" ");
+    }
+
+    fn set(&self) -> Option<ComparisonSet> {
+        Some(ComparisonSet {
+            filters: self.filters.clone()?,
+            demos: self.demos_input.split_whitespace().map(String::from).collect(),
+        })
+    }
+
+    fn view(&mut self, name: &str, theme: style::Theme, on_filters_changed: fn(String) -> Message, on_demos_changed: fn(String) -> Message) -> Element<'_, Message> {
+        let filters_style = if self.filters.is_some() {
+            style::ResultContainer::Ok
+        } else {
+            style::ResultContainer::Error
+        };
+        let filters_input = TextInput::new(&mut self.filters_input_state, "Filters, e.g. victim.team = red", &self.filters_input, on_filters_changed).style(theme);
+        let filters_border = Container::new(filters_input).padding(3).width(Length::Fill).style(filters_style);
+        let demos_input = TextInput::new(&mut self.demos_input_state, "Demo file names, e.g. *_week42_* (empty = every demo)", &self.demos_input, on_demos_changed).style(theme);
+        Column::new().spacing(10).push(Text::new(format!("Set {}:", name))).push(filters_border).push(demos_input).into()
     }
 }
 
@@ -223,8 +636,16 @@ struct SettingsPane {
     scale_input: String,
     scale: Option<f32>,
     export_image_button: button::State,
+    export_deaths_button: button::State,
+    save_job_button: button::State,
+    save_map_button: button::State,
+    load_job_button: button::State,
     image_ready: bool,
     coords_type: CoordsType,
+    camera_fit_pick_list: pick_list::State<CameraFit>,
+    camera_fit: CameraFit,
+    background_pick_list: pick_list::State<Background>,
+    background: Background,
     heatmap_type: HeatmapType,
     auto_intensity: bool,
     use_sentry_position: bool,
@@ -232,6 +653,39 @@ struct SettingsPane {
     intensity: f32,
     radius_state: slider::State,
     radius: f32,
+    kernel_pick_list: pick_list::State<Kernel>,
+    kernel: Kernel,
+    hammer_units_bandwidth: bool,
+    bandwidth_state: slider::State,
+    bandwidth: f32,
+    gradient_pick_list: pick_list::State<GradientChoice>,
+    gradient: HeatmapGradient,
+    annotations: Annotations,
+    comparison_enabled: bool,
+    set_a: ComparisonSetInput,
+    set_b: ComparisonSetInput,
+    normalization_pick_list: pick_list::State<Normalization>,
+    normalization: Normalization,
+    kill_death: KillDeathOptions,
+    kill_death_subjects: Vec<KillDeathSubjectChoice>,
+    kill_death_subject_pick_list: pick_list::State<KillDeathSubjectChoice>,
+    kill_death_metric_pick_list: pick_list::State<KillDeathMetric>,
+    min_samples_state: slider::State,
+    occupancy: OccupancyOptions,
+    occupancy_players: Vec<OccupancyPlayerChoice>,
+    occupancy_team_pick_list: pick_list::State<TeamChoice>,
+    occupancy_player_pick_list: pick_list::State<OccupancyPlayerChoice>,
+    path: PathOptions,
+    path_players: Vec<PlayerChoice>,
+    path_player_pick_list: pick_list::State<PlayerChoice>,
+    path_range_kind: PathRangeKind,
+    path_range_kind_pick_list: pick_list::State<PathRangeKind>,
+    path_range_input_state: text_input::State,
+    path_range_input: String,
+    path_coloring_pick_list: pick_list::State<PathColoring>,
+    // Applies to the demos loaded afterwards
+    sample_interval_state: slider::State,
+    sample_interval: f32,
     desaturate_state: slider::State,
     desaturate: f32,
 }
@@ -252,8 +706,16 @@ impl Default for SettingsPane {
             scale_input: Default::default(),
             scale: Default::default(),
             export_image_button: Default::default(),
+            export_deaths_button: Default::default(),
+            save_job_button: Default::default(),
+            save_map_button: Default::default(),
+            load_job_button: Default::default(),
             image_ready: Default::default(),
             coords_type: Default::default(),
+            camera_fit_pick_list: Default::default(),
+            camera_fit: Default::default(),
+            background_pick_list: Default::default(),
+            background: Default::default(),
             heatmap_type: Default::default(),
             auto_intensity: true,
             use_sentry_position: true,
@@ -261,6 +723,38 @@ impl Default for SettingsPane {
             intensity: 50.0,
             radius_state: Default::default(),
             radius: 50.0,
+            kernel_pick_list: Default::default(),
+            kernel: Default::default(),
+            hammer_units_bandwidth: false,
+            bandwidth_state: Default::default(),
+            bandwidth: 64.0,
+            gradient_pick_list: Default::default(),
+            gradient: Default::default(),
+            annotations: Default::default(),
+            comparison_enabled: false,
+            set_a: Default::default(),
+            set_b: Default::default(),
+            normalization_pick_list: Default::default(),
+            normalization: Default::default(),
+            kill_death: Default::default(),
+            kill_death_subjects: vec![KillDeathSubject::Team(Team::Red).into(), KillDeathSubject::Team(Team::Blu).into()],
+            kill_death_subject_pick_list: Default::default(),
+            kill_death_metric_pick_list: Default::default(),
+            min_samples_state: Default::default(),
+            occupancy: Default::default(),
+            occupancy_players: vec![OccupancyPlayerChoice::everyone()],
+            occupancy_team_pick_list: Default::default(),
+            occupancy_player_pick_list: Default::default(),
+            path: Default::default(),
+            path_players: Vec::new(),
+            path_player_pick_list: Default::default(),
+            path_range_kind: PathRangeKind::All,
+            path_range_kind_pick_list: Default::default(),
+            path_range_input_state: Default::default(),
+            path_range_input: Default::default(),
+            path_coloring_pick_list: Default::default(),
+            sample_interval_state: Default::default(),
+            sample_interval: DEFAULT_SAMPLE_INTERVAL,
             desaturate_state: Default::default(),
             desaturate: 0.0,
         }
@@ -268,8 +762,91 @@ impl Default for SettingsPane {
 }
 
 impl SettingsPane {
+    fn apply_job(&mut self, job: &HeatmapJob) {
+        self.x_pos = Some(job.pos_x);
+        self.x_pos_input = format!("{}", job.pos_x);
+        self.y_pos = Some(job.pos_y);
+        self.y_pos_input = format!("{}", job.pos_y);
+        self.scale = Some(job.scale);
+        self.scale_input = format!("{}", job.scale);
+        self.coords_type = job.coords_type;
+        self.camera_fit = job.camera_fit;
+        self.background = job.background;
+        self.heatmap_type = job.heatmap_type;
+        self.radius = job.radius;
+        self.kernel = job.kernel;
+        self.hammer_units_bandwidth = job.bandwidth.is_some();
+        if let Some(bandwidth) = job.bandwidth {
+            self.bandwidth = bandwidth;
+        }
+        self.auto_intensity = job.intensity.is_none();
+        if let Some(intensity) = job.intensity {
+            self.intensity = intensity;
+        }
+        self.desaturate = job.desaturate;
+        self.use_sentry_position = job.use_sentry_position;
+        self.gradient = job.gradient.clone();
+        self.annotations = job.annotations;
+        self.kill_death = job.kill_death.clone();
+        self.occupancy = job.occupancy.clone();
+        self.path = job.path.clone();
+        let (path_range_kind, path_range_input) = match job.path.range {
+            PathRange::All => (PathRangeKind::All, String::new()),
+            PathRange::Life(life) => (PathRangeKind::Life, life.to_string()),
+            PathRange::Ticks { start, end } => (PathRangeKind::Ticks, format!("{}-{}", start, end)),
+        };
+        self.path_range_kind = path_range_kind;
+        self.path_range_input = path_range_input;
+        self.comparison_enabled = job.comparison.is_some();
+        if let Some(comparison) = &job.comparison {
+            self.set_a.apply_set(&comparison.set_a);
+            self.set_b.apply_set(&comparison.set_b);
+            self.normalization = comparison.normalization;
+        }
+    }
+
+    fn set_players(&mut self, players: &[PlayerChoice]) {
+        self.kill_death_subjects = vec![KillDeathSubject::Team(Team::Red).into(), KillDeathSubject::Team(Team::Blu).into()];
+        self.kill_death_subjects.extend(players.iter().map(|player| KillDeathSubjectChoice {
+            subject: KillDeathSubject::Player(player.steam_id.clone()),
+            label: player.to_string(),
+        }));
+        self.occupancy_players = vec![OccupancyPlayerChoice::everyone()];
+        self.occupancy_players.extend(players.iter().map(|player| OccupancyPlayerChoice {
+            players: vec![player.steam_id.clone()],
+            label: player.to_string(),
+        }));
+        self.path_players = players.to_vec();
+    }
+
+    // None when disabled or when one of the sets is invalid
+    fn comparison(&self) -> Option<Comparison> {
+        if !self.comparison_enabled {
+            return None;
+        }
+        Some(Comparison {
+            set_a: self.set_a.set()?,
+            set_b: self.set_b.set()?,
+            normalization: self.normalization,
+        })
+    }
+
+    fn bandwidth(&self) -> Option<f32> {
+        if self.hammer_units_bandwidth {
+            Some(self.bandwidth)
+        } else {
+            None
+        }
+    }
+
+    // A fitted camera doesn't need the position and scale
+    fn camera_ready(&self) -> bool {
+        self.camera_fit != CameraFit::Manual || (self.x_pos.is_some() && self.y_pos.is_some() && self.scale.is_some())
+    }
+
     fn view(&mut self) -> Element<Message> {
-        let style = if self.x_pos.is_some() && self.y_pos.is_some() && self.scale.is_some() {
+        let camera_ready = self.camera_ready();
+        let style = if camera_ready {
             style::ResultContainer::Ok
         } else {
             style::ResultContainer::Error
@@ -282,7 +859,15 @@ impl SettingsPane {
             .fold(Column::new().spacing(10).push(Text::new("Coordinates origin:")), |column, coords_type| {
                 column.push(Radio::new(*coords_type, &format!("{}", coords_type), Some(self.coords_type), Message::CoordsTypeChanged).style(self.theme))
             });
-        let choose_heatmap_type = [HeatmapType::VictimPosition, HeatmapType::KillerPosition, HeatmapType::Lines]
+        let choose_heatmap_type = [
+            HeatmapType::VictimPosition,
+            HeatmapType::KillerPosition,
+            HeatmapType::AssisterPosition,
+            HeatmapType::Lines,
+            HeatmapType::KillDeath,
+            HeatmapType::Occupancy,
+            HeatmapType::Path,
+        ]
             .iter()
             .fold(Column::new().spacing(10).push(Text::new("Heatmap type:")), |column, heatmap_type| {
                 column.push(Radio::new(*heatmap_type, &format!("{}", heatmap_type), Some(self.heatmap_type), Message::HeatmapTypeChanged).style(self.theme))
@@ -318,6 +903,32 @@ impl SettingsPane {
         if self.image_ready {
             export_image_button = export_image_button.on_press(Message::ExportImagePressed);
         }
+        let export_deaths_button = Button::new(&mut self.export_deaths_button, Text::new("Export deaths"))
+            .padding(10)
+            .style(self.theme)
+            .width(Length::Fill)
+            .on_press(Message::ExportDeathsPressed);
+        let export_buttons = Row::new().spacing(10).push(export_image_button).push(export_deaths_button);
+        let mut save_job_button = Button::new(&mut self.save_job_button, Text::new("Save job"))
+            .padding(10)
+            .style(self.theme)
+            .width(Length::Fill);
+        if camera_ready {
+            save_job_button = save_job_button.on_press(Message::SaveJobPressed);
+        }
+        let load_job_button = Button::new(&mut self.load_job_button, Text::new("Load job"))
+            .padding(10)
+            .style(self.theme)
+            .width(Length::Fill)
+            .on_press(Message::LoadJobPressed);
+        let mut save_map_button = Button::new(&mut self.save_map_button, Text::new("Save map"))
+            .padding(10)
+            .style(self.theme)
+            .width(Length::Fill);
+        if self.camera_fit == CameraFit::Manual && camera_ready {
+            save_map_button = save_map_button.on_press(Message::SaveMapPressed);
+        }
+        let job_buttons = Row::new().spacing(10).push(save_job_button).push(load_job_button).push(save_map_button);
 
         let coords_label = match self.coords_type {
             CoordsType::ShowPos => "Camera coordinates (use cl_showpos)",
@@ -325,7 +936,7 @@ impl SettingsPane {
         };
 
         let mut heatmap_options = Column::new().spacing(10);
-        if self.heatmap_type != HeatmapType::Lines {
+        if !matches!(self.heatmap_type, HeatmapType::Lines | HeatmapType::Path) {
             let intensity_text = if self.auto_intensity {
                 "Heatmap intensity: Auto".into()
             } else {
@@ -340,25 +951,159 @@ impl SettingsPane {
                 let intensity_slider = Slider::new(&mut self.intensity_state, 1.0..=100.0, self.intensity, Message::IntensityChanged).style(self.theme);
                 heatmap_options = heatmap_options.push(intensity_slider);
             }
-            let radius_label = Row::new().spacing(10).push(Text::new(&format!("Heatmap radius: {:.1}", self.radius / 10.0)));
-            let radius_slider = Slider::new(&mut self.radius_state, 1.0..=100.0, self.radius, Message::RadiusChanged).style(self.theme);
-            heatmap_options = heatmap_options.push(radius_label).push(radius_slider);
+            let kernel_pick_list = PickList::new(&mut self.kernel_pick_list, &Kernel::ALL[..], Some(self.kernel), Message::KernelSelected);
+            let kernel_row = Row::new().spacing(10).align_items(Alignment::Center).push(Text::new("Density estimator:")).push(kernel_pick_list);
+            heatmap_options = heatmap_options.push(kernel_row);
+            let hammer_units_checkbox = Container::new(Checkbox::new(self.hammer_units_bandwidth, "Hammer units", Message::HammerUnitsBandwidthToggled).style(self.theme))
+                .align_x(alignment::Horizontal::Right)
+                .width(Length::Fill);
+            if self.hammer_units_bandwidth {
+                let bandwidth_label = Row::new().spacing(10).push(Text::new(format!("Heatmap bandwidth: {:.0} units", self.bandwidth))).push(hammer_units_checkbox);
+                let bandwidth_slider = Slider::new(&mut self.bandwidth_state, 8.0..=512.0, self.bandwidth, Message::BandwidthChanged).style(self.theme);
+                heatmap_options = heatmap_options.push(bandwidth_label).push(bandwidth_slider);
+            } else {
+                let radius_label = Row::new().spacing(10).push(Text::new(&format!("Heatmap radius: {:.1}", self.radius / 10.0))).push(hammer_units_checkbox);
+                let radius_slider = Slider::new(&mut self.radius_state, 1.0..=100.0, self.radius, Message::RadiusChanged).style(self.theme);
+                heatmap_options = heatmap_options.push(radius_label).push(radius_slider);
+            }
+        }
+        if self.heatmap_type == HeatmapType::KillDeath {
+            let selected_subject = self
+                .kill_death_subjects
+                .iter()
+                .find(|choice| choice.subject == self.kill_death.subject)
+                .cloned()
+                .unwrap_or_else(|| self.kill_death.subject.clone().into());
+            let subject_pick_list = PickList::new(&mut self.kill_death_subject_pick_list, &self.kill_death_subjects[..], Some(selected_subject), Message::KillDeathSubjectSelected);
+            let subject_row = Row::new().spacing(10).align_items(Alignment::Center).push(Text::new("Kills and deaths of:")).push(subject_pick_list);
+            let metric_pick_list = PickList::new(&mut self.kill_death_metric_pick_list, &KillDeathMetric::ALL[..], Some(self.kill_death.metric), Message::KillDeathMetricSelected);
+            let metric_row = Row::new().spacing(10).align_items(Alignment::Center).push(Text::new("Show:")).push(metric_pick_list);
+            let min_samples_label = Text::new(format!("Minimum kills + deaths per position: {:.1}", self.kill_death.min_samples));
+            let min_samples_slider = Slider::new(&mut self.min_samples_state, 0.0..=20.0, self.kill_death.min_samples, Message::MinSamplesChanged)
+                .step(0.5)
+                .style(self.theme);
+            heatmap_options = heatmap_options.push(subject_row).push(metric_row).push(min_samples_label).push(min_samples_slider);
+        }
+        if self.heatmap_type == HeatmapType::Occupancy {
+            let team_pick_list = PickList::new(&mut self.occupancy_team_pick_list, &TeamChoice::ALL[..], Some(TeamChoice(self.occupancy.team)), Message::OccupancyTeamSelected);
+            let team_row = Row::new().spacing(10).align_items(Alignment::Center).push(Text::new("Team:")).push(team_pick_list);
+            let selected_players = self
+                .occupancy_players
+                .iter()
+                .find(|choice| choice.players == self.occupancy.players)
+                .cloned()
+                .unwrap_or_else(|| OccupancyPlayerChoice {
+                    players: self.occupancy.players.clone(),
+                    label: format!("{} players", self.occupancy.players.len()),
+                });
+            let player_pick_list = PickList::new(&mut self.occupancy_player_pick_list, &self.occupancy_players[..], Some(selected_players), Message::OccupancyPlayerSelected);
+            let player_row = Row::new().spacing(10).align_items(Alignment::Center).push(Text::new("Player:")).push(player_pick_list);
+            let theme = self.theme;
+            let classes = &self.occupancy.classes;
+            let class_checkboxes = OCCUPANCY_CLASSES.chunks(3).fold(Column::new().spacing(5), |column, chunk| {
+                column.push(chunk.iter().fold(Row::new().spacing(10), |row, (class, name)| {
+                    let class = *class;
+                    row.push(
+                        Checkbox::new(classes[class], *name, move |checked| Message::OccupancyClassToggled(class, checked))
+                            .width(Length::Fill)
+                            .style(theme),
+                    )
+                }))
+            });
+            let alive_only_checkbox = Checkbox::new(self.occupancy.alive_only, "Only count alive players", Message::AliveOnlyToggled).style(self.theme);
+            let sample_interval_label = Text::new(format!(
+                "Position sample interval: {:.1}s (for the demos loaded afterwards)",
+                self.sample_interval
+            ));
+            let sample_interval_slider = Slider::new(&mut self.sample_interval_state, 0.1..=5.0, self.sample_interval, Message::SampleIntervalChanged)
+                .step(0.1)
+                .style(self.theme);
+            heatmap_options = heatmap_options
+                .push(team_row)
+                .push(player_row)
+                .push(class_checkboxes)
+                .push(alive_only_checkbox)
+                .push(sample_interval_label)
+                .push(sample_interval_slider);
+        }
+        if self.heatmap_type == HeatmapType::Path {
+            let selected_player = self.path_players.iter().find(|player| player.steam_id == self.path.player).cloned().or_else(|| {
+                Some(PlayerChoice {
+                    steam_id: self.path.player.clone(),
+                    name: String::new(),
+                })
+                .filter(|player| !player.steam_id.is_empty())
+            });
+            let player_pick_list = PickList::new(&mut self.path_player_pick_list, &self.path_players[..], selected_player, Message::PathPlayerSelected).placeholder("Choose a player");
+            let player_row = Row::new().spacing(10).align_items(Alignment::Center).push(Text::new("Player:")).push(player_pick_list);
+            let range_kind_pick_list = PickList::new(&mut self.path_range_kind_pick_list, &PathRangeKind::ALL[..], Some(self.path_range_kind), Message::PathRangeKindSelected);
+            let mut range_row = Row::new().spacing(10).align_items(Alignment::Center).push(Text::new("Show:")).push(range_kind_pick_list);
+            if self.path_range_kind != PathRangeKind::All {
+                let placeholder = if self.path_range_kind == PathRangeKind::Life { "Life number" } else { "Start tick-end tick" };
+                let range_input = TextInput::new(&mut self.path_range_input_state, placeholder, &self.path_range_input, Message::PathRangeInputChanged).style(self.theme);
+                let range_style = if self.path_range_kind.parse(&self.path_range_input).is_some() {
+                    style::ResultContainer::Ok
+                } else {
+                    style::ResultContainer::Error
+                };
+                range_row = range_row.push(Container::new(range_input).padding(3).width(Length::Fill).style(range_style));
+            }
+            let coloring_pick_list = PickList::new(&mut self.path_coloring_pick_list, &PathColoring::ALL[..], Some(self.path.coloring), Message::PathColoringSelected);
+            let coloring_row = Row::new().spacing(10).align_items(Alignment::Center).push(Text::new("Colored by:")).push(coloring_pick_list);
+            heatmap_options = heatmap_options.push(player_row).push(range_row).push(coloring_row);
         }
+        let selected_gradient = match self.gradient {
+            HeatmapGradient::Preset(preset) => GradientChoice::Preset(preset),
+            HeatmapGradient::Custom(_) => GradientChoice::Custom,
+        };
+        let gradient_pick_list = PickList::new(&mut self.gradient_pick_list, &GradientChoice::ALL[..], Some(selected_gradient), Message::GradientSelected);
+        let gradient_row = Row::new().spacing(10).align_items(Alignment::Center).push(Text::new("Colors:")).push(gradient_pick_list);
+        heatmap_options = heatmap_options.push(gradient_row);
         let desaturate_label = Row::new().spacing(10).push(Text::new(&format!("Desaturate level overview: {:.0}%", self.desaturate)));
         let desaturate_slider = Slider::new(&mut self.desaturate_state, 0.0..=100.0, self.desaturate, Message::DesaturateChanged).style(self.theme);
         heatmap_options = heatmap_options.push(desaturate_label).push(desaturate_slider);
         let use_sentry_position_checkbox =
             Checkbox::new(self.use_sentry_position, "Use sentry position for sentry kills", Message::UseSentryPositionCheckboxToggled).style(self.theme);
         heatmap_options = heatmap_options.push(use_sentry_position_checkbox);
+        if matches!(self.heatmap_type, HeatmapType::VictimPosition | HeatmapType::KillerPosition | HeatmapType::AssisterPosition) {
+            let comparison_checkbox = Checkbox::new(self.comparison_enabled, "Compare two sets of deaths (A - B)", Message::ComparisonCheckboxToggled).style(self.theme);
+            heatmap_options = heatmap_options.push(comparison_checkbox);
+            if self.comparison_enabled {
+                let normalization_pick_list = PickList::new(&mut self.normalization_pick_list, &Normalization::ALL[..], Some(self.normalization), Message::NormalizationSelected);
+                let normalization_row = Row::new().spacing(10).align_items(Alignment::Center).push(Text::new("Normalization:")).push(normalization_pick_list);
+                heatmap_options = heatmap_options
+                    .push(self.set_a.view("A", self.theme, Message::SetAFiltersChanged, Message::SetADemosChanged))
+                    .push(self.set_b.view("B", self.theme, Message::SetBFiltersChanged, Message::SetBDemosChanged))
+                    .push(normalization_row);
+            }
+        }
+        let annotations_row = Row::new()
+            .spacing(10)
+            .push(Checkbox::new(self.annotations.title, "Title", Message::TitleCheckboxToggled).style(self.theme))
+            .push(Checkbox::new(self.annotations.legend, "Legend", Message::LegendCheckboxToggled).style(self.theme))
+            .push(Checkbox::new(self.annotations.scale_bar, "Scale bar", Message::ScaleBarCheckboxToggled).style(self.theme));
+        heatmap_options = heatmap_options.push(Text::new("Annotations:")).push(annotations_row);
+
+        let camera_fit_pick_list = PickList::new(&mut self.camera_fit_pick_list, &CameraFit::ALL[..], Some(self.camera_fit), Message::CameraFitSelected);
+        let camera_fit_row = Row::new().spacing(10).align_items(Alignment::Center).push(Text::new("Camera:")).push(camera_fit_pick_list);
+        let background_pick_list = PickList::new(&mut self.background_pick_list, &Background::ALL[..], Some(self.background), Message::BackgroundSelected);
+        let background_row = Row::new().spacing(10).align_items(Alignment::Center).push(Text::new("Background:")).push(background_pick_list);
+        let mut camera_settings = Column::new().spacing(10).push(camera_fit_row);
+        if self.camera_fit == CameraFit::Manual {
+            camera_settings = camera_settings
+                .push(Text::new(coords_label))
+                .push(x_pos_border)
+                .push(y_pos_border)
+                .push(Text::new("cl_leveloverview scale"))
+                .push(scale_border);
+        }
+        camera_settings = camera_settings.push(background_row);
 
         let settings_content: Element<_> = Column::new()
             .push(choose_heatmap_type)
-            .push(Text::new(coords_label))
-            .push(x_pos_border)
-            .push(y_pos_border)
-            .push(Text::new("cl_leveloverview scale"))
-            .push(scale_border)
-            .push(export_image_button)
+            .push(camera_settings)
+            .push(export_buttons)
+            .push(job_buttons)
             .push(heatmap_options)
             .push(choose_coords_type)
             .push(choose_theme)
@@ -377,6 +1122,7 @@ impl SettingsPane {
 struct Preview {
     theme: style::Theme,
     heatmap_image: Option<HeatmapImage>,
+    image_path: Option<PathBuf>,
 }
 
 impl Preview {
@@ -385,7 +1131,7 @@ impl Preview {
             (Image::new(heatmap_image.handle.clone()).into(), style::ResultContainer::Ok)
         } else {
             (
-                Text::new("Drag and drop the level overview screenshot to use it")
+                Text::new("Drag and drop the level overview screenshot to use it, or choose a blank or grid background")
                     .width(Length::Fill)
                     .size(24)
                     .horizontal_alignment(alignment::Horizontal::Center)
@@ -462,6 +1208,9 @@ impl Application for App {
             App {
                 busy: false,
                 dropped_files: Default::default(),
+                batch: None,
+                batch_count: 0,
+                map_settings: Default::default(),
                 pane_grid_state,
                 theme: Default::default(),
                 demos_pane,
@@ -494,6 +1243,7 @@ impl Application for App {
                     if let Ok(reader) = Reader::open(&path) {
                         if let Ok(image) = reader.decode() {
                             let image = image.into_rgb8();
+                            self.get_preview_pane_mut().image_path = Some(path);
                             return Command::perform(async { image }, Message::LevelImageSet);
                         }
                     }
@@ -501,26 +1251,7 @@ impl Application for App {
             }
             Message::WindowEventOccurred(_) => {}
             Message::MapSet(map) => {
-                return Command::perform(
-                    async move {
-                        let (x, y, scale) = match demostf::get_boundary(&map).await {
-                            Ok(boundaries) => boundaries,
-                            Err(e) => {
-                                eprintln!("Error while fetching demostf boundaries {}", e);
-                                None
-                            }
-                        }?;
-                        let image = match demostf::get_image(&map).await {
-                            Ok(image) => image,
-                            Err(e) => {
-                                eprintln!("Error while fetching demostf image {}", e);
-                                None
-                            }
-                        }?;
-                        Some((image, x, y, scale))
-                    },
-                    Message::DemosTFImageLoader,
-                );
+                return Command::perform(load_map(map), Message::MapLoaded);
             }
             Message::LevelImageSet(image) => {
                 let image_with_heatmap_overlay = image.clone();
@@ -533,25 +1264,54 @@ impl Application for App {
                 self.get_settings_pane_mut().image_ready = true;
                 self.try_generate_heatmap();
             }
-            Message::DemosTFImageLoader(Some((image, x, y, scale))) => {
+            Message::MapLoaded(Ok(Some(loaded_map))) => {
+                // another group was selected in the meantime, it's loaded again when it's selected back
+                if self.get_demo_list_pane().selected_map.as_ref() != Some(&loaded_map.map) {
+                    return Command::none();
+                }
                 let settings_pane = self.get_settings_pane_mut();
-                settings_pane.x_pos = Some(x);
-                settings_pane.x_pos_input = format!("{}", x);
-                settings_pane.y_pos = Some(y);
-                settings_pane.y_pos_input = format!("{}", y);
-                settings_pane.scale = Some(scale);
-                settings_pane.scale_input = format!("{}", scale);
-
-                return Command::perform(async { image }, Message::LevelImageSet);
+                let entry = loaded_map.entry;
+                settings_pane.x_pos = Some(entry.pos_x);
+                settings_pane.x_pos_input = format!("{}", entry.pos_x);
+                settings_pane.y_pos = Some(entry.pos_y);
+                settings_pane.y_pos_input = format!("{}", entry.pos_y);
+                settings_pane.scale = Some(entry.scale);
+                settings_pane.scale_input = format!("{}", entry.scale);
+                settings_pane.coords_type = entry.coords_type;
+                self.log(&format!(
+                    "Camera{} of {} loaded from {}",
+                    if loaded_map.image.is_some() { " and level overview" } else { "" },
+                    loaded_map.map,
+                    loaded_map.source
+                ));
+                if let Some(image) = loaded_map.image {
+                    self.get_preview_pane_mut().image_path = None;
+                    return Command::perform(async { image }, Message::LevelImageSet);
+                }
+                self.try_generate_heatmap();
             }
-            Message::DemosTFImageLoader(None) => {}
+            Message::MapLoaded(Ok(None)) => {}
+            Message::MapLoaded(Err(err)) => self.log(&err),
+            Message::SaveMapPressed => self.save_map(),
             Message::EndOfDemoFilesDrop(_) => {
-                if !self.dropped_files.is_empty() {
+                // the demos dropped during a batch are processed once it's done
+                if !self.dropped_files.is_empty() && self.batch.is_none() {
                     self.set_busy(true);
                     let demo_count = self.dropped_files.len();
                     self.log(&format!("Processing {} demo{}...", demo_count, if demo_count > 1 { "s" } else { "" }));
-                    let input_paths = mem::take(&mut self.dropped_files);
-                    return Command::perform(process_demos_async(input_paths), Message::ProcessDemosDone);
+                    let inputs = mem::take(&mut self.dropped_files);
+                    self.batch_count += 1;
+                    self.batch = Some(Batch {
+                        id: self.batch_count,
+                        progress: vec![0.0; inputs.len()],
+                        inputs,
+                        sample_interval: self.get_settings_pane().sample_interval,
+                        cancel: Default::default(),
+                        demo_count: 0,
+                        death_count: 0,
+                        chrono: Instant::now(),
+                    });
+                    self.get_demo_list_pane_mut().progress = Some(0.0);
                 }
             }
             Message::PaneResized(pane_grid::ResizeEvent { split, ratio }) => {
@@ -568,8 +1328,17 @@ impl Application for App {
                     if death_count > 1 { "s" } else { "" }
                 ));
                 self.show_stats();
+                self.update_players();
+                // switch to another group once the last demo of the selected one is gone
+                let demo_list = self.get_demo_list_pane();
+                if demo_list.selected_demos().next().is_none() {
+                    if let Some(group) = demo_list.map_groups().into_iter().next() {
+                        return self.select_map_group(group.map);
+                    }
+                }
                 self.try_generate_heatmap();
             }
+            Message::MapGroupSelected(group) => return self.select_map_group(group.map),
             Message::ChatPreview(index) => {
                 let demo_list = self.get_demo_list_pane_mut();
                 let demo = &demo_list.demo_files[index];
@@ -595,6 +1364,19 @@ impl Application for App {
                 self.get_settings_pane_mut().coords_type = coords_type;
                 self.try_generate_heatmap();
             }
+            Message::CameraFitSelected(camera_fit) => {
+                self.get_settings_pane_mut().camera_fit = camera_fit;
+                self.try_generate_heatmap();
+            }
+            Message::BackgroundSelected(background) => {
+                self.get_settings_pane_mut().background = background;
+                // the background is drawn on a blank image when there is no level overview yet
+                if background != Background::LevelOverview && self.get_preview_pane().heatmap_image.is_none() {
+                    let image = camera::blank_image(camera::BACKGROUND_WIDTH, camera::BACKGROUND_HEIGHT);
+                    return Command::perform(async { image }, Message::LevelImageSet);
+                }
+                self.try_generate_heatmap();
+            }
             Message::HeatmapTypeChanged(heatmap_type) => {
                 self.get_settings_pane_mut().heatmap_type = heatmap_type;
                 self.try_generate_heatmap();
@@ -652,53 +1434,255 @@ impl Application for App {
                 settings_pane.radius = radius;
                 self.try_generate_heatmap();
             }
+            Message::KernelSelected(kernel) => {
+                let settings_pane = self.get_settings_pane_mut();
+                settings_pane.kernel = kernel;
+                self.try_generate_heatmap();
+            }
+            Message::HammerUnitsBandwidthToggled(hammer_units_bandwidth) => {
+                let settings_pane = self.get_settings_pane_mut();
+                settings_pane.hammer_units_bandwidth = hammer_units_bandwidth;
+                self.try_generate_heatmap();
+            }
+            Message::BandwidthChanged(bandwidth) => {
+                let settings_pane = self.get_settings_pane_mut();
+                settings_pane.bandwidth = bandwidth;
+                self.try_generate_heatmap();
+            }
+            Message::GradientSelected(GradientChoice::Preset(preset)) => {
+                let settings_pane = self.get_settings_pane_mut();
+                settings_pane.gradient = HeatmapGradient::Preset(preset);
+                self.try_generate_heatmap();
+            }
+            Message::GradientSelected(GradientChoice::Custom) => {
+                return Command::perform(open_gradient_dialog(), Message::GradientFileSelected);
+            }
+            Message::GradientFileSelected(path) => {
+                if let Some(path) = path {
+                    match gradient::load_gradient(&path) {
+                        Ok(gradient) => {
+                            self.get_settings_pane_mut().gradient = gradient;
+                            self.log(&format!("Gradient loaded: {}", path.file_name().unwrap().to_string_lossy()));
+                            self.try_generate_heatmap();
+                        }
+                        Err(err) => self.log(&format!("Couldn't load the gradient: {}", err)),
+                    }
+                }
+            }
+            Message::LegendCheckboxToggled(legend) => {
+                let settings_pane = self.get_settings_pane_mut();
+                settings_pane.annotations.legend = legend;
+                self.try_generate_heatmap();
+            }
+            Message::ScaleBarCheckboxToggled(scale_bar) => {
+                let settings_pane = self.get_settings_pane_mut();
+                settings_pane.annotations.scale_bar = scale_bar;
+                self.try_generate_heatmap();
+            }
+            Message::TitleCheckboxToggled(title) => {
+                let settings_pane = self.get_settings_pane_mut();
+                settings_pane.annotations.title = title;
+                self.try_generate_heatmap();
+            }
+            Message::ComparisonCheckboxToggled(comparison_enabled) => {
+                let settings_pane = self.get_settings_pane_mut();
+                settings_pane.comparison_enabled = comparison_enabled;
+                self.try_generate_heatmap();
+            }
+            Message::SetAFiltersChanged(input) => {
+                let settings_pane = self.get_settings_pane_mut();
+                settings_pane.set_a.set_filters_input(input);
+                self.try_generate_heatmap();
+            }
+            Message::SetBFiltersChanged(input) => {
+                let settings_pane = self.get_settings_pane_mut();
+                settings_pane.set_b.set_filters_input(input);
+                self.try_generate_heatmap();
+            }
+            Message::SetADemosChanged(input) => {
+                let settings_pane = self.get_settings_pane_mut();
+                settings_pane.set_a.demos_input = input;
+                self.try_generate_heatmap();
+            }
+            Message::SetBDemosChanged(input) => {
+                let settings_pane = self.get_settings_pane_mut();
+                settings_pane.set_b.demos_input = input;
+                self.try_generate_heatmap();
+            }
+            Message::NormalizationSelected(normalization) => {
+                let settings_pane = self.get_settings_pane_mut();
+                settings_pane.normalization = normalization;
+                self.try_generate_heatmap();
+            }
+            Message::KillDeathSubjectSelected(choice) => {
+                let settings_pane = self.get_settings_pane_mut();
+                settings_pane.kill_death.subject = choice.subject;
+                self.try_generate_heatmap();
+            }
+            Message::KillDeathMetricSelected(metric) => {
+                let settings_pane = self.get_settings_pane_mut();
+                settings_pane.kill_death.metric = metric;
+                self.try_generate_heatmap();
+            }
+            Message::MinSamplesChanged(min_samples) => {
+                let settings_pane = self.get_settings_pane_mut();
+                settings_pane.kill_death.min_samples = min_samples;
+                self.try_generate_heatmap();
+            }
+            Message::OccupancyTeamSelected(team) => {
+                let settings_pane = self.get_settings_pane_mut();
+                settings_pane.occupancy.team = team.0;
+                self.try_generate_heatmap();
+            }
+            Message::OccupancyPlayerSelected(choice) => {
+                let settings_pane = self.get_settings_pane_mut();
+                settings_pane.occupancy.players = choice.players;
+                self.try_generate_heatmap();
+            }
+            Message::OccupancyClassToggled(class, checked) => {
+                let settings_pane = self.get_settings_pane_mut();
+                settings_pane.occupancy.classes[class] = checked;
+                self.try_generate_heatmap();
+            }
+            Message::AliveOnlyToggled(alive_only) => {
+                let settings_pane = self.get_settings_pane_mut();
+                settings_pane.occupancy.alive_only = alive_only;
+                self.try_generate_heatmap();
+            }
+            Message::SampleIntervalChanged(sample_interval) => {
+                let settings_pane = self.get_settings_pane_mut();
+                settings_pane.sample_interval = sample_interval;
+            }
+            Message::PathPlayerSelected(player) => {
+                let settings_pane = self.get_settings_pane_mut();
+                settings_pane.path.player = player.steam_id;
+                self.try_generate_heatmap();
+            }
+            Message::PathRangeKindSelected(kind) => {
+                let settings_pane = self.get_settings_pane_mut();
+                settings_pane.path_range_kind = kind;
+                if let Some(range) = kind.parse(&settings_pane.path_range_input) {
+                    settings_pane.path.range = range;
+                }
+                self.try_generate_heatmap();
+            }
+            Message::PathRangeInputChanged(input) => {
+                let settings_pane = self.get_settings_pane_mut();
+                if let Some(range) = settings_pane.path_range_kind.parse(&input) {
+                    settings_pane.path.range = range;
+                }
+                settings_pane.path_range_input = input;
+                self.try_generate_heatmap();
+            }
+            Message::PathColoringSelected(coloring) => {
+                let settings_pane = self.get_settings_pane_mut();
+                settings_pane.path.coloring = coloring;
+                self.try_generate_heatmap();
+            }
+            Message::DemoDetailsToggled(index) => {
+                let demo_file = &mut self.get_demo_list_pane_mut().demo_files[index];
+                demo_file.expanded = !demo_file.expanded;
+            }
             Message::DesaturateChanged(desaturate) => {
                 let settings_pane = self.get_settings_pane_mut();
                 settings_pane.desaturate = desaturate;
                 self.try_generate_heatmap();
             }
-            Message::ProcessDemosDone(mut timed_result) => {
-                let mut demo_count = 0;
-                let mut death_count = 0;
-                let demo_list = self.get_demo_list_pane_mut();
-                let mut errors = Vec::new();
-                let mut map = String::new();
-                for demo in timed_result.result.iter_mut() {
-                    let demo = mem::take(demo);
-                    demo_count += 1;
-                    map = demo.map.clone();
-                    if let Some(heatmap_analysis) = demo.heatmap_analysis {
-                        death_count += heatmap_analysis.deaths.len();
-                        let path = demo.path;
-                        let file_name = path.file_name().unwrap().to_string_lossy().to_string();
-                        let demo_file = DemoFile {
-                            _path: path,
-                            file_name,
-                            heatmap_analysis,
-                            delete_button: Default::default(),
-                            chat_preview_button: Default::default(),
-                        };
-                        demo_list.demo_files.push(demo_file);
+            Message::DemoProcessed(ProcessingEvent::Progress { index, tick, ticks }) => {
+                if let Some(batch) = &mut self.batch {
+                    batch.progress[index] = if ticks > 0 { (tick as f32 / ticks as f32).min(1.0) } else { 0.0 };
+                    self.update_progress();
+                }
+            }
+            Message::DemoProcessed(ProcessingEvent::Done { index, output: demo }) => {
+                let batch = match &mut self.batch {
+                    Some(batch) => batch,
+                    None => return Command::none(),
+                };
+                batch.progress[index] = 1.0;
+                batch.demo_count += 1;
+                let demo = *demo;
+                batch.death_count += demo.heatmap_analysis.as_ref().map_or(0, |heatmap_analysis| heatmap_analysis.deaths.len());
+                let file_name = demo.path.file_name().unwrap().to_string_lossy().to_string();
+                let problem_count = demo.problems().count();
+                for problem in demo.problems().take(MAX_LOGGED_PROBLEMS) {
+                    self.log(&format!("{}: {}", file_name, problem));
+                }
+                if problem_count > MAX_LOGGED_PROBLEMS {
+                    self.log(&format!("{}: {} more warnings", file_name, problem_count - MAX_LOGGED_PROBLEMS));
+                }
+                let mut set_map = false;
+                if let Some(heatmap_analysis) = demo.heatmap_analysis {
+                    let demo_list = self.get_demo_list_pane();
+                    let is_new_map = !demo_list.demo_files.iter().any(|demo_file| demo_file.map == demo.map);
+                    match &demo_list.selected_map {
+                        // the camera and overview of the first demo replace the ones without demos
+                        _ if demo_list.selected_demos().next().is_none() => set_map = true,
+                        Some(selected_map) if is_new_map && *selected_map != demo.map => {
+                            let message = format!(
+                                "{} is on {} rather than {}, its deaths aren't in the heatmap, pick its map above the demo list to show them",
+                                file_name, demo.map, selected_map
+                            );
+                            self.log(&message);
+                        }
+                        _ => {}
                     }
-                    if let Some(error) = demo.error {
-                        errors.push(error);
+                    let demo_file = DemoFile {
+                        _path: demo.path,
+                        file_name,
+                        map: demo.map.clone(),
+                        heatmap_analysis,
+                        metadata: demo.metadata,
+                        expanded: false,
+                        delete_button: Default::default(),
+                        chat_preview_button: Default::default(),
+                        details_button: Default::default(),
+                    };
+                    let demo_list = self.get_demo_list_pane_mut();
+                    demo_list.demo_files.push(demo_file);
+                    if set_map {
+                        demo_list.selected_map = Some(demo.map.clone());
                     }
                 }
-                for error in errors {
-                    self.log(&error);
+                self.update_progress();
+                self.update_players();
+                self.try_generate_heatmap();
+                if set_map {
+                    let map = demo.map;
+                    return Command::perform(async { map }, Message::MapSet);
                 }
-                self.log(&format!(
-                    "Loaded {} death{} from {} demo{} in {:.2}s",
-                    death_count,
-                    if death_count > 1 { "s" } else { "" },
-                    demo_count,
-                    if demo_count > 1 { "s" } else { "" },
-                    timed_result.time_elapsed
-                ));
+            }
+            Message::ProcessDemosDone => {
+                if let Some(batch) = self.batch.take() {
+                    let death_count = batch.death_count;
+                    let demo_count = batch.demo_count;
+                    let cancelled_count = batch.inputs.len() - demo_count;
+                    self.log(&format!(
+                        "Loaded {} death{} from {} demo{} in {:.2}s{}",
+                        death_count,
+                        if death_count > 1 { "s" } else { "" },
+                        demo_count,
+                        if demo_count > 1 { "s" } else { "" },
+                        batch.chrono.elapsed().as_secs_f32(),
+                        if batch.cancel.is_cancelled() {
+                            format!(", cancelled {} demo{}", cancelled_count, if cancelled_count > 1 { "s" } else { "" })
+                        } else {
+                            String::new()
+                        }
+                    ));
+                }
+                self.get_demo_list_pane_mut().progress = None;
                 self.show_stats();
-                self.try_generate_heatmap();
                 self.set_busy(false);
-                return Command::perform(async { map }, Message::MapSet);
+                if !self.dropped_files.is_empty() {
+                    return Command::perform(async {}, Message::EndOfDemoFilesDrop);
+                }
+            }
+            Message::CancelProcessingPressed => {
+                if let Some(batch) = &self.batch {
+                    batch.cancel.cancel();
+                    self.log("Cancelling...");
+                }
             }
             Message::ExportImagePressed => {
                 return Command::perform(open_save_dialog(), Message::ImageNameSelected);
@@ -709,6 +1693,18 @@ impl Application for App {
                         self.log("File extension not specified, defaulting to png");
                         path.set_extension("png");
                     }
+                    let extension = path.extension().unwrap_or_default().to_string_lossy().to_lowercase();
+                    match extension.as_str() {
+                        "svg" => {
+                            self.export_document(&path, "SVG", HeatmapJob::render_svg_on);
+                            return Command::none();
+                        }
+                        "html" | "htm" => {
+                            self.export_document(&path, "report", HeatmapJob::render_report_on);
+                            return Command::none();
+                        }
+                        _ => (),
+                    }
                     match &self.get_preview_pane().heatmap_image {
                         Some(heatmap_image) => {
                             if let Err(err) = heatmap_image.image_with_heatmap_overlay.save(&path) {
@@ -721,69 +1717,172 @@ impl Application for App {
                     }
                 }
             }
-            Message::FilterSelected(index, selected) => {
-                let filter_row = &mut self.get_filters_pane_mut().filters[index];
-                filter_row.selected_filter = selected;
-                filter_row.filter = filter_row.try_generate_filter();
+            Message::ExportDeathsPressed => {
+                return Command::perform(open_deaths_export_dialog(), Message::DeathsExportNameSelected);
+            }
+            Message::DeathsExportNameSelected(path) => {
+                if let Some(mut path) = path {
+                    if path.extension().is_none() {
+                        self.log("File extension not specified, defaulting to csv");
+                        path.set_extension("csv");
+                    }
+                    let format = match ExportFormat::from_path(&path) {
+                        Some(format) => format,
+                        None => {
+                            self.log("Unsupported file extension, use .csv or .json");
+                            return Command::none();
+                        }
+                    };
+                    let filters: Vec<_> = self.get_filters_pane().filters.iter().filter_map(|filter_row| filter_row.filter.as_ref()).collect();
+                    let records: Vec<_> = self
+                        .get_demo_list_pane()
+                        .selected_demos()
+                        .flat_map(|demo_file| {
+                            demo_file
+                                .heatmap_analysis
+                                .deaths
+                                .iter()
+                                .filter(|death| filters.iter().all(|filter| filter.apply(death)))
+                                .map(move |death| DeathRecord::new(&demo_file.file_name, &demo_file.map, &demo_file.heatmap_analysis, death))
+                        })
+                        .collect();
+                    let death_count = records.len();
+                    if let Err(err) = export::export_deaths(&path, format, records) {
+                        self.log(&format!("Couldn't export the deaths: {}", err));
+                    } else {
+                        self.log(&format!(
+                            "Exported {} death{}: {}",
+                            death_count,
+                            if death_count > 1 { "s" } else { "" },
+                            path.file_name().unwrap().to_string_lossy()
+                        ));
+                    }
+                }
+            }
+            Message::SaveJobPressed => {
+                return Command::perform(open_job_save_dialog(), Message::JobSaveNameSelected);
+            }
+            Message::JobSaveNameSelected(path) => {
+                if let Some(mut path) = path {
+                    if path.extension().is_none() {
+                        path.set_extension("json");
+                    }
+                    match self.current_job() {
+                        Some(job) => {
+                            if let Err(err) = job.save(&path) {
+                                self.log(&format!("Couldn't save the job: {}", err));
+                            } else {
+                                if job.image_path.is_none() {
+                                    self.log("The level overview doesn't come from a file, it won't be saved in the job");
+                                }
+                                self.log(&format!("Job saved: {}", path.file_name().unwrap().to_string_lossy()));
+                            }
+                        }
+                        None => self.log("Couldn't save the job: the camera coordinates are incomplete"),
+                    }
+                }
+            }
+            Message::LoadJobPressed => {
+                return Command::perform(open_job_load_dialog(), Message::JobLoadNameSelected);
+            }
+            Message::JobLoadNameSelected(path) => {
+                if let Some(path) = path {
+                    let job = match HeatmapJob::load(&path) {
+                        Ok(job) => job,
+                        Err(err) => {
+                            self.log(&format!("Couldn't load the job: {}", err));
+                            return Command::none();
+                        }
+                    };
+                    self.get_settings_pane_mut().apply_job(&job);
+                    self.get_filters_pane_mut().set_filters(job.filters.clone());
+                    self.log(&format!("Job loaded: {}", path.file_name().unwrap().to_string_lossy()));
+                    if job.image_path.is_some() {
+                        match job.load_overview() {
+                            Ok(image) => {
+                                self.get_preview_pane_mut().image_path = job.image_path;
+                                return Command::perform(async { image }, Message::LevelImageSet);
+                            }
+                            Err(err) => self.log(&format!("Couldn't load the level overview: {}", err)),
+                        }
+                    }
+                    self.try_generate_heatmap();
+                }
+            }
+            Message::FilterSelected(path, selected) => {
+                self.get_filters_pane_mut().update_row(&path, |filter_row| filter_row.selected_filter = selected);
                 self.try_generate_heatmap();
             }
             Message::AddFilter => {
                 self.get_filters_pane_mut().filters.push(Default::default());
             }
-            Message::ClassIconClicked(index, class_index) => {
-                let filter_row = &mut self.get_filters_pane_mut().filters[index];
-                let button_active = &mut filter_row.class_buttons_selected[class_index];
-                *button_active = !*button_active;
-                filter_row.filter = filter_row.try_generate_filter();
+            Message::AddChildFilter(path) => {
+                self.get_filters_pane_mut().update_row(&path, |filter_row| filter_row.children.push(Default::default()));
+                self.try_generate_heatmap();
+            }
+            Message::ClassIconClicked(path, class_index) => {
+                self.get_filters_pane_mut().update_row(&path, |filter_row| {
+                    let button_active = &mut filter_row.class_buttons_selected[class_index];
+                    *button_active = !*button_active;
+                });
+                self.try_generate_heatmap();
+            }
+            Message::BluTeamClicked(path) => {
+                self.get_filters_pane_mut().update_row(&path, |filter_row| filter_row.team_button_selected = Team::Blu);
+                self.try_generate_heatmap();
+            }
+            Message::RedTeamClicked(path) => {
+                self.get_filters_pane_mut().update_row(&path, |filter_row| filter_row.team_button_selected = Team::Red);
                 self.try_generate_heatmap();
             }
-            Message::BluTeamClicked(index) => {
-                let filter_row = &mut self.get_filters_pane_mut().filters[index];
-                filter_row.team_button_selected = Team::Blu;
-                filter_row.filter = filter_row.try_generate_filter();
+            Message::OrderedOperatorSelected(path, selected) => {
+                self.get_filters_pane_mut().update_row(&path, |filter_row| filter_row.selected_ordered_operator = selected);
                 self.try_generate_heatmap();
             }
-            Message::RedTeamClicked(index) => {
-                let filter_row = &mut self.get_filters_pane_mut().filters[index];
-                filter_row.team_button_selected = Team::Red;
-                filter_row.filter = filter_row.try_generate_filter();
+            Message::PropertyOperatorSelected(path, selected) => {
+                self.get_filters_pane_mut().update_row(&path, |filter_row| filter_row.selected_property_operator = selected);
                 self.try_generate_heatmap();
             }
-            Message::OrderedOperatorSelected(index, selected) => {
-                let filter_row = &mut self.get_filters_pane_mut().filters[index];
-                filter_row.selected_ordered_operator = selected;
-                filter_row.filter = filter_row.try_generate_filter();
+            Message::PropertySelected(path, selected) => {
+                self.get_filters_pane_mut().update_row(&path, |filter_row| filter_row.selected_property = selected);
                 self.try_generate_heatmap();
             }
-            Message::PropertyOperatorSelected(index, selected) => {
-                let filter_row = &mut self.get_filters_pane_mut().filters[index];
-                filter_row.selected_property_operator = selected;
-                filter_row.filter = filter_row.try_generate_filter();
+            Message::WeaponChoiceSelected(path, selected) => {
+                self.get_filters_pane_mut().update_row(&path, |filter_row| filter_row.selected_weapon_choice = selected);
                 self.try_generate_heatmap();
             }
-            Message::PropertySelected(index, selected) => {
-                let filter_row = &mut self.get_filters_pane_mut().filters[index];
-                filter_row.selected_property = selected;
-                filter_row.filter = filter_row.try_generate_filter();
+            Message::PlayerSelected(path, selected) => {
+                self.get_filters_pane_mut()
+                    .update_row(&path, |filter_row| filter_row.selected_player = Some(selected.steam_id));
                 self.try_generate_heatmap();
             }
-            Message::FilterTextInputChanged(index, text_input) => {
-                let filter_row = &mut self.get_filters_pane_mut().filters[index];
-                filter_row.text_input = text_input;
-                filter_row.filter = filter_row.try_generate_filter();
+            Message::FilterTextInputChanged(path, text_input) => {
+                self.get_filters_pane_mut().update_row(&path, |filter_row| filter_row.text_input = text_input);
                 self.try_generate_heatmap();
             }
-            Message::FilterRemoved(index) => {
-                self.get_filters_pane_mut().filters.remove(index);
+            Message::FilterRemoved(path) => {
+                self.get_filters_pane_mut().remove_row(&path);
                 self.try_generate_heatmap();
             }
+            Message::FilterQueryChanged(query) => {
+                self.get_filters_pane_mut().set_query(query);
+            }
+            Message::FilterQuerySubmitted => {
+                if self.get_filters_pane_mut().submit_query() {
+                    self.try_generate_heatmap();
+                }
+            }
         };
 
         Command::none()
     }
 
     fn subscription(&self) -> Subscription<Message> {
-        iced_native::subscription::events().map(Message::WindowEventOccurred)
+        let events = iced_native::subscription::events().map(Message::WindowEventOccurred);
+        match &self.batch {
+            Some(batch) => Subscription::batch([events, process_demos(batch)]),
+            None => events,
+        }
     }
 
     fn view(&mut self) -> Element<Message> {
@@ -876,6 +1975,10 @@ impl App {
     fn log(&mut self, message: &str) {
         self.get_log_pane_mut().log(message);
     }
+    fn update_progress(&mut self) {
+        let progress = self.batch.as_ref().map(|batch| batch.progress.iter().sum::<f32>() / batch.progress.len() as f32);
+        self.get_demo_list_pane_mut().progress = progress;
+    }
     fn set_busy(&mut self, busy: bool) {
         self.busy = busy;
         self.get_demo_list_pane_mut().busy = busy;
@@ -884,6 +1987,22 @@ impl App {
         // self.get_preview_pane().busy = busy;
         // self.get_log_pane().busy = busy;
     }
+    fn update_players(&mut self) {
+        // Players are identified by SteamID, the name shown is the one from the most recently loaded demo
+        let mut names = BTreeMap::new();
+        for demo_file in &self.get_demo_list_pane().demo_files {
+            for user in demo_file.heatmap_analysis.users.values() {
+                // Bots all share the same "BOT" SteamID, so they can't be told apart
+                if !user.steam_id.is_empty() && user.steam_id != "BOT" {
+                    names.insert(user.steam_id.clone(), user.name.clone());
+                }
+            }
+        }
+        let mut players: Vec<_> = names.into_iter().map(|(steam_id, name)| PlayerChoice { steam_id, name }).collect();
+        players.sort_by_key(|player| player.name.to_lowercase());
+        self.get_settings_pane_mut().set_players(&players);
+        self.get_filters_pane_mut().players = players;
+    }
     fn show_stats(&mut self) {
         let demo_list = self.get_demo_list_pane();
         let death_count: usize = demo_list.demo_files.iter().map(|demo_file| demo_file.heatmap_analysis.deaths.len()).sum();
@@ -904,6 +2023,165 @@ impl App {
             red_wins as f32 * 100.0 / round_count as f32,
         ));
     }
+    // Stores the camera and the level overview for the map of the demos, so they're used offline and instead of the ones from demos.tf
+    fn save_map(&mut self) {
+        let map = match &self.get_demo_list_pane().selected_map {
+            Some(map) => map.clone(),
+            None => return self.log("Load a demo of the map first"),
+        };
+        let database = match MapDatabase::default_location() {
+            Some(database) => database,
+            None => return self.log("Couldn't find where to store the map database"),
+        };
+        let settings_pane = self.get_settings_pane();
+        let entry = match (settings_pane.x_pos, settings_pane.y_pos, settings_pane.scale) {
+            (Some(pos_x), Some(pos_y), Some(scale)) => MapEntry {
+                pos_x,
+                pos_y,
+                scale,
+                coords_type: settings_pane.coords_type,
+            },
+            _ => return self.log("Couldn't save the map: the camera coordinates are incomplete"),
+        };
+        // a generated background isn't worth saving
+        let image = match (settings_pane.background, &self.get_preview_pane().heatmap_image) {
+            (Background::LevelOverview, Some(heatmap_image)) => Some(&heatmap_image.image),
+            _ => None,
+        };
+        match database.insert(&map, &entry, image) {
+            Ok(()) => self.log(&format!(
+                "Camera{} of {} saved to the map database: {}",
+                if image.is_some() { " and level overview" } else { "" },
+                map,
+                database.dir().to_string_lossy()
+            )),
+            Err(err) => self.log(&format!("Couldn't save the map: {}", err)),
+        }
+    }
+    // Renders the demos of that map, with the camera and overview it had when it was last selected
+    fn select_map_group(&mut self, map: String) -> Command<Message> {
+        let current_settings = self.current_map_settings();
+        let demo_list = self.get_demo_list_pane_mut();
+        if demo_list.selected_map.as_ref() == Some(&map) {
+            return Command::none();
+        }
+        if let Some(previous_map) = demo_list.selected_map.replace(map.clone()) {
+            self.map_settings.insert(previous_map, current_settings);
+        }
+        match self.map_settings.remove(&map) {
+            Some(settings) if settings.has_camera() => {
+                self.restore_map_settings(settings);
+                self.try_generate_heatmap();
+                Command::none()
+            }
+            _ => {
+                // a new group starts without a camera but keeps how it's framed
+                let current_settings = self.get_settings_pane();
+                let settings = MapSettings {
+                    camera_fit: current_settings.camera_fit,
+                    background: current_settings.background,
+                    ..Default::default()
+                };
+                self.restore_map_settings(settings);
+                Command::perform(async { map }, Message::MapSet)
+            }
+        }
+    }
+    fn current_map_settings(&self) -> MapSettings {
+        let settings_pane = self.get_settings_pane();
+        let preview_pane = self.get_preview_pane();
+        MapSettings {
+            x_pos_input: settings_pane.x_pos_input.clone(),
+            y_pos_input: settings_pane.y_pos_input.clone(),
+            scale_input: settings_pane.scale_input.clone(),
+            coords_type: settings_pane.coords_type,
+            camera_fit: settings_pane.camera_fit,
+            background: settings_pane.background,
+            image: preview_pane.heatmap_image.as_ref().map(|heatmap_image| heatmap_image.image.clone()),
+            image_path: preview_pane.image_path.clone(),
+        }
+    }
+    fn restore_map_settings(&mut self, settings: MapSettings) {
+        // the background is drawn on a blank image when there is no level overview
+        let image = match settings.image {
+            None if settings.background != Background::LevelOverview => Some(camera::blank_image(camera::BACKGROUND_WIDTH, camera::BACKGROUND_HEIGHT)),
+            image => image,
+        };
+        let settings_pane = self.get_settings_pane_mut();
+        settings_pane.x_pos = settings.x_pos_input.parse().ok();
+        settings_pane.x_pos_input = settings.x_pos_input;
+        settings_pane.y_pos = settings.y_pos_input.parse().ok();
+        settings_pane.y_pos_input = settings.y_pos_input;
+        settings_pane.scale = settings.scale_input.parse().ok();
+        settings_pane.scale_input = settings.scale_input;
+        settings_pane.coords_type = settings.coords_type;
+        settings_pane.camera_fit = settings.camera_fit;
+        settings_pane.background = settings.background;
+        settings_pane.image_ready = image.is_some();
+        let preview_pane = self.get_preview_pane_mut();
+        preview_pane.image_path = settings.image_path;
+        preview_pane.heatmap_image = image.map(|image| HeatmapImage {
+            image_with_heatmap_overlay: image.clone(),
+            handle: image_to_handle(&image),
+            image,
+        });
+    }
+    fn current_job(&self) -> Option<HeatmapJob> {
+        let settings_pane = self.get_settings_pane();
+        // the fitted camera replaces the position and scale, they don't need to be valid
+        let fitted = settings_pane.camera_fit != CameraFit::Manual;
+        Some(HeatmapJob {
+            image_path: self.get_preview_pane().image_path.clone(),
+            pos_x: settings_pane.x_pos.or(Some(0.0).filter(|_| fitted))?,
+            pos_y: settings_pane.y_pos.or(Some(0.0).filter(|_| fitted))?,
+            scale: settings_pane.scale.or(Some(1.0).filter(|_| fitted))?,
+            coords_type: settings_pane.coords_type,
+            camera_fit: settings_pane.camera_fit,
+            background: settings_pane.background,
+            heatmap_type: settings_pane.heatmap_type,
+            radius: settings_pane.radius,
+            kernel: settings_pane.kernel,
+            bandwidth: settings_pane.bandwidth(),
+            intensity: if settings_pane.auto_intensity { None } else { Some(settings_pane.intensity) },
+            desaturate: settings_pane.desaturate,
+            use_sentry_position: settings_pane.use_sentry_position,
+            gradient: settings_pane.gradient.clone(),
+            annotations: settings_pane.annotations,
+            kill_death: settings_pane.kill_death.clone(),
+            occupancy: settings_pane.occupancy.clone(),
+            path: settings_pane.path.clone(),
+            comparison: settings_pane.comparison(),
+            filters: self.get_filters_pane().filters.iter().filter_map(|filter_row| filter_row.filter.clone()).collect(),
+        })
+    }
+    fn export_document(
+        &mut self,
+        path: &Path,
+        kind: &str,
+        render: impl FnOnce(&HeatmapJob, &RgbImage, &[(&str, &str, &HeatmapAnalysis)]) -> Result<String, Box<dyn Error>>,
+    ) {
+        let settings_pane = self.get_settings_pane();
+        let image = match &self.get_preview_pane().heatmap_image {
+            Some(image) => apply_image_transformations(&image.image, settings_pane.desaturate),
+            None => unreachable!(),
+        };
+        let job = match self.current_job() {
+            Some(job) => job,
+            None => {
+                self.log(&format!("Couldn't export the {}: the camera coordinates are incomplete", kind));
+                return;
+            }
+        };
+        let demos: Vec<_> = self
+            .get_demo_list_pane()
+            .selected_demos()
+            .map(|demo_file| (demo_file.file_name.as_str(), demo_file.map.as_str(), &demo_file.heatmap_analysis))
+            .collect();
+        match render(&job, &image, &demos).and_then(|document| Ok(std::fs::write(path, document)?)) {
+            Ok(()) => self.log(&format!("{} saved: {}", kind, path.file_name().unwrap().to_string_lossy())),
+            Err(err) => self.log(&format!("Couldn't export the {}: {}", kind, err)),
+        }
+    }
     fn try_generate_heatmap(&mut self) {
         let preview_pane = self.get_preview_pane();
         let settings_pane = self.get_settings_pane();
@@ -911,36 +2189,13 @@ impl App {
             Some(image) => apply_image_transformations(&image.image, settings_pane.desaturate),
             None => return,
         };
-        if let (Some(pos_x), Some(pos_y), Some(scale)) = (settings_pane.x_pos, settings_pane.y_pos, settings_pane.scale) {
-            let coords_type = settings_pane.coords_type;
-            let heatmap_type = settings_pane.heatmap_type;
-            let radius = settings_pane.radius;
-            let intensity = if settings_pane.auto_intensity { None } else { Some(settings_pane.intensity) };
-            let use_sentry_position = settings_pane.use_sentry_position;
-            let screen_width = image.width();
-            let screen_height = image.height();
-            let filters: Vec<_> = self.get_filters_pane().filters.iter().filter_map(|filter_row| filter_row.filter.as_ref()).collect();
+        if let Some(job) = self.current_job() {
             let demo_list = self.get_demo_list_pane();
-            let deaths = demo_list
-                .demo_files
-                .iter()
-                .map(|demo_file| demo_file.heatmap_analysis.deaths.iter())
-                .flatten()
-                .filter(|death| filters.iter().all(|filter| filter.apply(death)));
-            let heatmap_generation_output = coldmaps::generate_heatmap(
-                heatmap_type,
-                deaths,
-                image,
-                screen_width,
-                screen_height,
-                pos_x,
-                pos_y,
-                scale,
-                coords_type,
-                radius,
-                intensity,
-                use_sentry_position,
-            );
+            let demos: Vec<_> = demo_list
+                .selected_demos()
+                .map(|demo_file| (demo_file.file_name.as_str(), demo_file.map.as_str(), &demo_file.heatmap_analysis))
+                .collect();
+            let heatmap_generation_output = job.render_on(image, &demos);
             match &mut self.get_preview_pane_mut().heatmap_image {
                 Some(heatmap_image) => {
                     heatmap_image.handle = image_to_handle(&heatmap_generation_output);
@@ -961,30 +2216,6 @@ impl App {
     }
 }
 
-// just desaturate for now
-fn apply_image_transformations(image: &ImageBuffer<Rgb<u8>, Vec<u8>>, desaturate: f32) -> ImageBuffer<Rgb<u8>, Vec<u8>> {
-    let desaturate = desaturate / 100.0;
-    let mut output_image = ImageBuffer::new(image.width(), image.height());
-
-    for (x, y, pixel) in image.enumerate_pixels() {
-        let Rgb(data) = *pixel;
-
-        // Convert the pixel to grayscale
-        let gray_value = (0.3 * data[0] as f32 + 0.59 * data[1] as f32 + 0.11 * data[2] as f32) as u8;
-
-        // Linearly interpolate between the original pixel and the grayscale value
-        let new_pixel = Rgb([
-            ((1.0 - desaturate) * data[0] as f32 + desaturate * gray_value as f32) as u8,
-            ((1.0 - desaturate) * data[1] as f32 + desaturate * gray_value as f32) as u8,
-            ((1.0 - desaturate) * data[2] as f32 + desaturate * gray_value as f32) as u8,
-        ]);
-
-        output_image.put_pixel(x, y, new_pixel);
-    }
-
-    output_image
-}
-
 fn image_to_handle(image: &ImageBuffer<Rgb<u8>, Vec<u8>>) -> Handle {
     Handle::from_pixels(
         image.width(),
@@ -1003,13 +2234,109 @@ fn image_to_handle(image: &ImageBuffer<Rgb<u8>, Vec<u8>>) -> Handle {
     )
 }
 
-async fn process_demos_async<'a>(inputs: Vec<PathBuf>) -> TimedResult<Vec<DemoProcessingOutput>> {
-    let chrono = Instant::now();
-    let result = tokio::task::spawn_blocking(move || coldmaps::process_demos(inputs)).await.unwrap();
-    let time_elapsed = chrono.elapsed().as_secs_f32();
-    TimedResult { result, time_elapsed }
+// Parses the demos of the batch on the blocking threads and forwards their events, ends with ProcessDemosDone
+fn process_demos(batch: &Batch) -> Subscription<Message> {
+    let initial_state = BatchState::Starting(batch.inputs.clone(), batch.sample_interval, batch.cancel.clone());
+    iced_native::subscription::unfold(batch.id, initial_state, |state| async move {
+        match state {
+            BatchState::Starting(inputs, sample_interval, cancel) => {
+                let (sender, receiver) = mpsc::unbounded();
+                tokio::task::spawn_blocking(move || {
+                    let cache = AnalysisCache::default_location();
+                    coldmaps::process_demos_streaming(&inputs, cache.as_ref(), sample_interval, &cancel, |event| {
+                        let _ = sender.unbounded_send(event);
+                    });
+                });
+                (None, BatchState::Processing(receiver))
+            }
+            BatchState::Processing(mut receiver) => match receiver.next().await {
+                Some(event) => (Some(Message::DemoProcessed(event)), BatchState::Processing(receiver)),
+                // every demo is done or cancelled once the sender is dropped
+                None => (Some(Message::ProcessDemosDone), BatchState::Finished),
+            },
+            BatchState::Finished => future::pending().await,
+        }
+    })
 }
 
 async fn open_save_dialog() -> Option<PathBuf> {
-    AsyncFileDialog::new().add_filter("image", &["png"]).save_file().await.map(|handle| handle.path().into())
+    AsyncFileDialog::new()
+        .add_filter("image", &["png"])
+        .add_filter("SVG", &["svg"])
+        .add_filter("HTML report", &["html"])
+        .save_file()
+        .await
+        .map(|handle| handle.path().into())
+}
+
+async fn open_deaths_export_dialog() -> Option<PathBuf> {
+    AsyncFileDialog::new()
+        .add_filter("CSV", &["csv"])
+        .add_filter("Newline-delimited JSON", &["json", "ndjson", "jsonl"])
+        .save_file()
+        .await
+        .map(|handle| handle.path().into())
+}
+
+async fn open_job_save_dialog() -> Option<PathBuf> {
+    AsyncFileDialog::new()
+        .add_filter("coldmaps job", &["json"])
+        .save_file()
+        .await
+        .map(|handle| handle.path().into())
+}
+
+async fn open_gradient_dialog() -> Option<PathBuf> {
+    AsyncFileDialog::new()
+        .add_filter("Gradient stops", &["txt"])
+        .pick_file()
+        .await
+        .map(|handle| handle.path().into())
+}
+
+async fn open_job_load_dialog() -> Option<PathBuf> {
+    AsyncFileDialog::new()
+        .add_filter("coldmaps job", &["json"])
+        .pick_file()
+        .await
+        .map(|handle| handle.path().into())
+}
+
+// The map database comes first, what's fetched from demos.tf is added to it for the next time
+async fn load_map(map: String) -> Result<Option<LoadedMap>, String> {
+    let database = MapDatabase::default_location();
+    if let Some(found) = database.as_ref().and_then(|database| database.get(&map)) {
+        let source = if found.name == map {
+            "the map database".to_string()
+        } else {
+            format!("the map database entry of {}", found.name)
+        };
+        let image = found.load_image();
+        return Ok(Some(LoadedMap {
+            map,
+            source,
+            entry: found.entry,
+            image,
+        }));
+    }
+    let entry = match demostf::get_boundary(&map).await {
+        Ok(Some(entry)) => entry,
+        Ok(None) => return Ok(None),
+        Err(err) => return Err(format!("Couldn't fetch the camera of {} from demos.tf: {}", map, err)),
+    };
+    let image = match demostf::get_image(&map).await {
+        Ok(Some(image)) => image,
+        Ok(None) => return Ok(None),
+        Err(err) => return Err(format!("Couldn't fetch the level overview of {} from demos.tf: {}", map, err)),
+    };
+    let source = match database.map(|database| database.insert(&map, &entry, Some(&image))) {
+        Some(Err(err)) => format!("demos.tf (couldn't add it to the map database: {})", err),
+        _ => "demos.tf".to_string(),
+    };
+    Ok(Some(LoadedMap {
+        map,
+        source,
+        entry,
+        image: Some(image),
+    }))
 }
@@ -0,0 +1,161 @@
+use crate::heatmap::{CoordsType, LEVELOVERVIEW_SCALE_MULTIPLIER};
+use image::{io::Reader, RgbImage};
+use serde::{Deserialize, Serialize};
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+// Camera of a map's level overview, <map>.json in the database, next to the overview itself as <map>.png
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct MapEntry {
+    pub pos_x: f32,
+    pub pos_y: f32,
+    pub scale: f32,
+    #[serde(default)]
+    pub coords_type: CoordsType,
+}
+
+impl MapEntry {
+    // From the boundary of the overview in Hammer units, like the ones demos.tf has
+    pub fn from_boundary(min_x: f32, min_y: f32, max_x: f32, max_y: f32) -> Self {
+        Self {
+            pos_x: (max_x + min_x) / 2.0,
+            pos_y: (max_y + min_y) / 2.0,
+            scale: (max_y - min_y) / LEVELOVERVIEW_SCALE_MULTIPLIER / 2.0,
+            coords_type: CoordsType::ShowPos,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FoundMap {
+    // The map the entry was made for, another version of the map when there isn't an exact match
+    pub name: String,
+    pub entry: MapEntry,
+    pub image_path: Option<PathBuf>,
+}
+
+impl FoundMap {
+    pub fn load_image(&self) -> Option<RgbImage> {
+        let image = Reader::open(self.image_path.as_ref()?).ok()?.with_guessed_format().ok()?.decode().ok()?;
+        Some(image.into_rgb8())
+    }
+}
+
+// Cameras and level overviews that work without internet, filled from demos.tf and by the users
+#[derive(Debug, Clone)]
+pub struct MapDatabase {
+    dir: PathBuf,
+}
+
+impl MapDatabase {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn default_location() -> Option<Self> {
+        dirs::data_dir().map(|dir| Self::new(dir.join("coldmaps").join("maps")))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    // The entry of the map or else of another version of it, e.g. cp_process_final for cp_process_f12
+    pub fn get(&self, map: &str) -> Option<FoundMap> {
+        if !is_valid_name(map) {
+            return None;
+        }
+        let name = if self.entry_path(map).is_file() {
+            map.to_string()
+        } else {
+            let base = base_name(map).to_lowercase();
+            // the closest version, the most recent one on ties
+            self.names()
+                .into_iter()
+                .filter(|name| base_name(name).to_lowercase() == base)
+                .max_by_key(|name| (common_prefix_len(name, map), name.clone()))?
+        };
+        let entry = serde_json::from_slice(&fs::read(self.entry_path(&name)).ok()?).ok()?;
+        let image_path = Some(self.image_path(&name)).filter(|path| path.is_file());
+        Some(FoundMap { name, entry, image_path })
+    }
+
+    pub fn insert(&self, map: &str, entry: &MapEntry, image: Option<&RgbImage>) -> Result<(), Box<dyn Error>> {
+        if !is_valid_name(map) {
+            return Err(format!("Invalid map name \"{}\"", map).into());
+        }
+        fs::create_dir_all(&self.dir)?;
+        if let Some(image) = image {
+            image.save(self.image_path(map))?;
+        }
+        fs::write(self.entry_path(map), serde_json::to_string_pretty(entry)?)?;
+        Ok(())
+    }
+
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<_> = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries
+                .filter_map(|entry| {
+                    let path = entry.ok()?.path();
+                    if path.extension()? != "json" {
+                        return None;
+                    }
+                    Some(path.file_stem()?.to_string_lossy().to_string())
+                })
+                .collect(),
+            Err(_) => Vec::new(),
+        };
+        names.sort_unstable();
+        names
+    }
+
+    fn entry_path(&self, map: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", map))
+    }
+
+    fn image_path(&self, map: &str) -> PathBuf {
+        self.dir.join(format!("{}.png", map))
+    }
+}
+
+// The map name without its version suffixes: cp_process_f12 -> cp_process, koth_product_rc8 -> koth_product
+pub fn base_name(map: &str) -> &str {
+    let mut base = map;
+    while let Some(index) = base.rfind('_') {
+        // keep at least the game mode and the name
+        if !base[..index].contains('_') || !is_version(&base[index + 1..]) {
+            break;
+        }
+        base = &base[..index];
+    }
+    base
+}
+
+// final, rc2, b4a, f12, v3, a7...
+fn is_version(segment: &str) -> bool {
+    let segment = segment.to_lowercase();
+    if matches!(segment.as_str(), "final" | "beta" | "alpha" | "rcx") {
+        return true;
+    }
+    ["final", "beta", "alpha", "rc", "a", "b", "f", "v", ""]
+        .iter()
+        .any(|prefix| match segment.strip_prefix(prefix) {
+            Some(rest) => {
+                let letters = rest.trim_start_matches(|c: char| c.is_ascii_digit());
+                letters.len() < rest.len() && letters.len() <= 1 && letters.chars().all(|c| c.is_ascii_lowercase())
+            }
+            None => false,
+        })
+}
+
+fn common_prefix_len(a: &str, b: &str) -> usize {
+    a.chars().zip(b.chars()).take_while(|(a, b)| a.eq_ignore_ascii_case(b)).count()
+}
+
+// The names end up in file names
+fn is_valid_name(map: &str) -> bool {
+    !map.is_empty() && map.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.') && !map.starts_with('.')
+}
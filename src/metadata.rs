@@ -0,0 +1,59 @@
+use crate::heatmap_analyser::{HeatmapAnalysis, ServerInfo, Team};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use tf_demo_parser::demo::header::Header;
+
+// What tells demos apart, their header and a summary of the match
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DemoMetadata {
+    pub header: Header,
+    // None when the demo is corrupted before the server sent it
+    pub server_info: Option<ServerInfo>,
+    // The users that spawned at least once, spectators don't count
+    pub player_count: usize,
+    pub round_count: usize,
+    pub blu_score: usize,
+    pub red_score: usize,
+    // In seconds, from the start of the first round to the end of the last one, or to the end of the demo when it doesn't contain it
+    pub match_length: Option<f32>,
+}
+
+impl DemoMetadata {
+    pub fn new(header: Header, heatmap_analysis: &HeatmapAnalysis) -> Self {
+        let players: BTreeSet<_> = heatmap_analysis.spawns.iter().map(|spawn| spawn.user).collect();
+        let rounds_won_by = |team: Team| heatmap_analysis.rounds.iter().filter(|round| round.winner == team).count();
+        let demo_end_tick = heatmap_analysis.current_tick + heatmap_analysis.tick_offset;
+        let match_length = heatmap_analysis.round_start_ticks.first().map(|&start_tick| {
+            let end_tick = match heatmap_analysis.rounds.last() {
+                Some(round) if round.end_tick() > start_tick => round.end_tick(),
+                _ => demo_end_tick,
+            };
+            end_tick.saturating_sub(start_tick) as f32 * heatmap_analysis.interval_per_tick
+        });
+        Self {
+            header,
+            server_info: heatmap_analysis.server_info.clone(),
+            player_count: players.len(),
+            round_count: heatmap_analysis.rounds.len(),
+            blu_score: rounds_won_by(Team::Blu),
+            red_score: rounds_won_by(Team::Red),
+            match_length,
+        }
+    }
+
+    // Operating system of the server
+    pub fn platform(&self) -> Option<&str> {
+        let server_info = self.server_info.as_ref()?;
+        Some(match server_info.platform.as_str() {
+            "l" => "Linux",
+            "w" => "Windows",
+            "m" | "o" => "macOS",
+            platform => platform,
+        })
+    }
+
+    // Recorded by the server (STV) or by a player (POV)
+    pub fn is_stv(&self) -> Option<bool> {
+        self.server_info.as_ref().map(|server_info| server_info.stv)
+    }
+}
@@ -0,0 +1,121 @@
+use crate::heatmap_analyser::{Death, HeatmapAnalysis, PositionSample};
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
+
+// Ticks are counted from the start of the demo, like the ticks shown in the demo player
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum PathRange {
+    All,
+    // From the nth spawn of the player (starting at 1) to their next death or spawn
+    Life(u32),
+    Ticks { start: u32, end: u32 },
+}
+
+impl Default for PathRange {
+    fn default() -> Self {
+        Self::All
+    }
+}
+
+impl Display for PathRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PathRange::All => write!(f, "whole demo"),
+            PathRange::Life(life) => write!(f, "life {}", life),
+            PathRange::Ticks { start, end } => write!(f, "ticks {} to {}", start, end),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum PathColoring {
+    Time,
+    Health,
+}
+
+impl PathColoring {
+    pub const ALL: [PathColoring; 2] = [PathColoring::Time, PathColoring::Health];
+}
+
+impl Default for PathColoring {
+    fn default() -> Self {
+        Self::Time
+    }
+}
+
+impl Display for PathColoring {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PathColoring::Time => write!(f, "Time"),
+            PathColoring::Health => write!(f, "Health"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PathOptions {
+    // SteamID
+    pub player: String,
+    pub range: PathRange,
+    pub coloring: PathColoring,
+}
+
+// Everything the player did during the range, in one demo
+#[derive(Debug, Clone)]
+pub struct PlayerPath<'a> {
+    pub samples: Vec<&'a PositionSample>,
+    // In seconds, the longest gap between two samples that still gets joined by a line
+    pub max_gap: f32,
+    pub interval_per_tick: f32,
+    // Ticks at which the player spawned
+    pub spawns: Vec<u32>,
+    pub deaths: Vec<&'a Death>,
+    pub kills: Vec<&'a Death>,
+}
+
+impl PathOptions {
+    // None when the player isn't in the demo or doesn't have that many lives
+    pub fn select<'a>(&self, heatmap_analysis: &'a HeatmapAnalysis) -> Option<PlayerPath<'a>> {
+        let users: Vec<_> = heatmap_analysis
+            .users
+            .values()
+            .filter(|user| user.steam_id == self.player)
+            .map(|user| user.user_id)
+            .collect();
+        if users.is_empty() {
+            return None;
+        }
+        let spawns: Vec<u32> = heatmap_analysis.spawns.iter().filter(|spawn| users.contains(&spawn.user)).map(|spawn| spawn.tick).collect();
+        let deaths = heatmap_analysis.deaths.iter().filter(|death| death.victim_steamid == self.player);
+        let offset = heatmap_analysis.tick_offset;
+        let (start, end) = match self.range {
+            PathRange::All => (0, u32::MAX),
+            PathRange::Ticks { start, end } => (start.saturating_add(offset), end.saturating_add(offset)),
+            PathRange::Life(life) => {
+                let index = (life as usize).checked_sub(1)?;
+                let start = *spawns.get(index)?;
+                let next_spawn = spawns.get(index + 1).copied().unwrap_or(u32::MAX);
+                let death = deaths.clone().map(|death| death.tick).find(|tick| *tick >= start).unwrap_or(u32::MAX);
+                (start, next_spawn.min(death))
+            }
+        };
+        let in_range = |tick: u32| tick >= start && tick <= end;
+        Some(PlayerPath {
+            samples: heatmap_analysis
+                .position_samples
+                .iter()
+                .filter(|sample| users.contains(&sample.user) && in_range(sample.tick))
+                .collect(),
+            max_gap: heatmap_analysis.sample_interval * 2.5,
+            interval_per_tick: heatmap_analysis.interval_per_tick,
+            spawns: spawns.into_iter().filter(|tick| in_range(*tick)).collect(),
+            deaths: deaths.filter(|death| in_range(death.tick)).collect(),
+            kills: heatmap_analysis
+                .deaths
+                .iter()
+                .filter(|death| death.killer_steamid == self.player && death.killer != death.victim && in_range(death.tick))
+                .collect(),
+        })
+    }
+}
@@ -0,0 +1,806 @@
+use crate::{
+    filters::{
+        AndFilter, AssisterClassFilter, AssisterPlayerFilter, AssisterTeamFilter, Distance2DFilter, Distance3DFilter, Filter, KillerClassFilter, KillerElevationFilter,
+        KillerPlayerFilter, KillerTeamFilter, MatchTimeFilter, NotFilter, OrFilter, OrderedOperator, Property, PropertyFilter, PropertyOperator, RoundFilter, RoundTimeFilter,
+        TimeAliveFilter, VictimClassFilter, VictimElevationFilter, VictimPlayerFilter, VictimTeamFilter, WeaponFilter, WeaponSelection,
+    },
+    heatmap_analyser::Team,
+    weapons::WeaponGroup,
+};
+use std::{error::Error, fmt::Display, ops::Range};
+
+// Filter query language, e.g. `killer.class in (scout, soldier) and distance2d < 512 and not suicide and round >= 2`
+//
+// query     = or
+// or        = and ("or" and)*
+// and       = not ("and" not)*
+// not       = "not" not | "(" or ")" | predicate
+// predicate = property | field operator value | field "in" "(" [value ("," value)*] ")"
+
+// Indexed like the class buttons of the filters pane
+const CLASS_NAMES: [&str; 10] = ["other", "scout", "sniper", "soldier", "demoman", "medic", "heavy", "pyro", "spy", "engineer"];
+
+// A value of a predicate with where it is in the query
+struct Value {
+    text: String,
+    span: Range<usize>,
+    // quoted weapons are always kill icons, even when they are named like a group
+    quoted: bool,
+}
+
+type Values = Vec<Value>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryError {
+    pub message: String,
+    // Byte range of the query the error refers to
+    pub span: Range<usize>,
+}
+
+impl QueryError {
+    fn new(message: impl Into<String>, span: Range<usize>) -> Self {
+        Self { message: message.into(), span }
+    }
+
+    // The message followed by the query with the error underlined, for the terminal
+    pub fn pretty(&self, query: &str) -> String {
+        let padding = query[..self.span.start].chars().count();
+        let length = query[self.span.clone()].chars().count().max(1);
+        format!("{}\n  {}\n  {}{}", self.message, query, " ".repeat(padding), "^".repeat(length))
+    }
+
+    // Single line version, for the GUI
+    pub fn describe(&self, query: &str) -> String {
+        if self.span.start >= query.len() {
+            format!("{} at the end of the query", self.message)
+        } else {
+            format!(
+                "{} at \"{}\" (column {})",
+                self.message,
+                &query[self.span.clone()],
+                query[..self.span.start].chars().count() + 1
+            )
+        }
+    }
+}
+
+impl Display for QueryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (at {}..{})", self.message, self.span.start, self.span.end)
+    }
+}
+
+impl Error for QueryError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TokenKind {
+    OpenParen,
+    CloseParen,
+    Comma,
+    Operator(OrderedOperator),
+    Word(String),
+    Quoted(String),
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    span: Range<usize>,
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '.' | ':' | '[' | ']' | '-' | '+')
+}
+
+fn tokenize(query: &str) -> Result<Vec<Token>, QueryError> {
+    let mut tokens = Vec::new();
+    let mut chars = query.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        let mut next_is = |expected: char| chars.next_if(|&(_, c)| c == expected).is_some();
+        let kind = match c {
+            c if c.is_whitespace() => continue,
+            '(' => TokenKind::OpenParen,
+            ')' => TokenKind::CloseParen,
+            ',' => TokenKind::Comma,
+            '=' => {
+                next_is('=');
+                TokenKind::Operator(OrderedOperator::Equal)
+            }
+            '!' if next_is('=') => TokenKind::Operator(OrderedOperator::NotEqual),
+            '<' if next_is('=') => TokenKind::Operator(OrderedOperator::SmallerOrEqual),
+            '<' => TokenKind::Operator(OrderedOperator::Smaller),
+            '>' if next_is('=') => TokenKind::Operator(OrderedOperator::GreaterOrEqual),
+            '>' => TokenKind::Operator(OrderedOperator::Greater),
+            // The symbols used by the filters pane
+            '≠' => TokenKind::Operator(OrderedOperator::NotEqual),
+            '≤' => TokenKind::Operator(OrderedOperator::SmallerOrEqual),
+            '≥' => TokenKind::Operator(OrderedOperator::GreaterOrEqual),
+            '"' => {
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, c)) => value.push(c),
+                        None => return Err(QueryError::new("Unterminated string", start..query.len())),
+                    }
+                }
+                TokenKind::Quoted(value)
+            }
+            c if is_word_char(c) => {
+                let mut value = c.to_string();
+                while let Some((_, c)) = chars.next_if(|&(_, c)| is_word_char(c)) {
+                    value.push(c);
+                }
+                TokenKind::Word(value)
+            }
+            c => return Err(QueryError::new("Unexpected character", start..start + c.len_utf8())),
+        };
+        let end = chars.peek().map(|&(index, _)| index).unwrap_or(query.len());
+        tokens.push(Token { kind, span: start..end });
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn next_or_error(&mut self, expected: &str) -> Result<Token, QueryError> {
+        self.next().ok_or_else(|| QueryError::new(format!("Expected {}", expected), self.end..self.end))
+    }
+
+    fn next_is_keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token { kind: TokenKind::Word(word), .. }) if word.eq_ignore_ascii_case(keyword) => {
+                self.position += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect(&mut self, kind: TokenKind, expected: &str) -> Result<(), QueryError> {
+        let token = self.next_or_error(expected)?;
+        if token.kind == kind {
+            Ok(())
+        } else {
+            Err(QueryError::new(format!("Expected {}", expected), token.span))
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Filter, QueryError> {
+        let mut filters = vec![self.parse_and()?];
+        while self.next_is_keyword("or") {
+            filters.push(self.parse_and()?);
+        }
+        Ok(if filters.len() == 1 { filters.remove(0) } else { OrFilter { filters }.into() })
+    }
+
+    fn parse_and(&mut self) -> Result<Filter, QueryError> {
+        let mut filters = vec![self.parse_not()?];
+        while self.next_is_keyword("and") {
+            filters.push(self.parse_not()?);
+        }
+        Ok(if filters.len() == 1 { filters.remove(0) } else { AndFilter { filters }.into() })
+    }
+
+    fn parse_not(&mut self) -> Result<Filter, QueryError> {
+        if self.next_is_keyword("not") {
+            return Ok(negate(self.parse_not()?));
+        }
+        let token = self.next_or_error("a filter")?;
+        match token.kind {
+            TokenKind::OpenParen => {
+                let filter = self.parse_or()?;
+                self.expect(TokenKind::CloseParen, "`)`")?;
+                Ok(filter)
+            }
+            TokenKind::Word(field) => self.parse_predicate(&field, token.span),
+            _ => Err(QueryError::new("Expected a filter", token.span)),
+        }
+    }
+
+    fn parse_predicate(&mut self, field: &str, field_span: Range<usize>) -> Result<Filter, QueryError> {
+        let field = field.to_lowercase();
+        let property = match field.as_str() {
+            "suicide" => Some(Property::Suicide),
+            "posthumous" => Some(Property::Posthumous),
+            "during_round" => Some(Property::DuringRound),
+            "sentry" => Some(Property::DiedToSentry),
+            _ => None,
+        };
+        if let Some(property) = property {
+            return Ok(PropertyFilter {
+                op: PropertyOperator::IsPresent,
+                property,
+            }
+            .into());
+        }
+
+        match field.as_str() {
+            "killer.team" | "victim.team" | "assister.team" | "killer.class" | "victim.class" | "assister.class" | "weapon" | "killer" | "victim" | "assister" => {
+                let (negated, values) = self.parse_values()?;
+                // an empty `in ()` would give an empty `or` that can't be written back as a query
+                let is_list = matches!(field.as_str(), "killer.class" | "victim.class" | "assister.class" | "weapon");
+                if values.is_empty() && !is_list {
+                    return Err(QueryError::new(format!("`{}` needs at least one value", field), field_span));
+                }
+                let filter = match field.as_str() {
+                    "killer.team" => any_of(values.iter().map(|value| Ok(KillerTeamFilter { team: parse_team(value)? }.into())))?,
+                    "victim.team" => any_of(values.iter().map(|value| Ok(VictimTeamFilter { team: parse_team(value)? }.into())))?,
+                    "assister.team" => any_of(values.iter().map(|value| Ok(AssisterTeamFilter { team: parse_team(value)? }.into())))?,
+                    "killer.class" => KillerClassFilter { classes: parse_classes(&values)? }.into(),
+                    "victim.class" => VictimClassFilter { classes: parse_classes(&values)? }.into(),
+                    "assister.class" => AssisterClassFilter { classes: parse_classes(&values)? }.into(),
+                    "weapon" => parse_weapons(values),
+                    "killer" => any_of(values.iter().map(|value| Ok(KillerPlayerFilter { steam_id: parse_steam_id(value)? }.into())))?,
+                    "victim" => any_of(values.iter().map(|value| Ok(VictimPlayerFilter { steam_id: parse_steam_id(value)? }.into())))?,
+                    _ => any_of(values.iter().map(|value| Ok(AssisterPlayerFilter { steam_id: parse_steam_id(value)? }.into())))?,
+                };
+                Ok(if negated { negate(filter) } else { filter })
+            }
+            "killer.z" | "victim.z" | "distance2d" | "distance3d" | "round" | "match_time" | "round_time" | "time_alive" => {
+                let token = self.next_or_error("a comparison operator")?;
+                let op = match token.kind {
+                    TokenKind::Operator(op) => op,
+                    _ => return Err(QueryError::new("Expected a comparison operator (=, !=, <, <=, > or >=)", token.span)),
+                };
+                let value = self.parse_value()?;
+                Ok(match field.as_str() {
+                    "killer.z" => KillerElevationFilter { op, z: parse_number(&value)? }.into(),
+                    "victim.z" => VictimElevationFilter { op, z: parse_number(&value)? }.into(),
+                    "distance2d" => Distance2DFilter {
+                        op,
+                        distance: parse_number(&value)?,
+                    }
+                    .into(),
+                    "distance3d" => Distance3DFilter {
+                        op,
+                        distance: parse_number(&value)?,
+                    }
+                    .into(),
+                    "round" => RoundFilter {
+                        op,
+                        round: value.text.parse().map_err(|_| QueryError::new("Expected a round number", value.span.clone()))?,
+                    }
+                    .into(),
+                    "match_time" => MatchTimeFilter {
+                        op,
+                        seconds: parse_number(&value)?,
+                    }
+                    .into(),
+                    "round_time" => RoundTimeFilter {
+                        op,
+                        seconds: parse_number(&value)?,
+                    }
+                    .into(),
+                    _ => TimeAliveFilter {
+                        op,
+                        seconds: parse_number(&value)?,
+                    }
+                    .into(),
+                })
+            }
+            _ => Err(QueryError::new(format!("Unknown filter `{}`", field), field_span)),
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<Value, QueryError> {
+        let token = self.next_or_error("a value")?;
+        let (text, quoted) = match token.kind {
+            TokenKind::Word(text) => (text, false),
+            TokenKind::Quoted(text) => (text, true),
+            _ => return Err(QueryError::new("Expected a value", token.span)),
+        };
+        Ok(Value { text, span: token.span, quoted })
+    }
+
+    // `= value`, `!= value` or `in (value, ...)`, returns whether the values are negated
+    fn parse_values(&mut self) -> Result<(bool, Values), QueryError> {
+        let token = self.next_or_error("`=`, `!=` or `in`")?;
+        match token.kind {
+            TokenKind::Operator(OrderedOperator::Equal) => Ok((false, vec![self.parse_value()?])),
+            TokenKind::Operator(OrderedOperator::NotEqual) => Ok((true, vec![self.parse_value()?])),
+            TokenKind::Word(word) if word.eq_ignore_ascii_case("in") => {
+                self.expect(TokenKind::OpenParen, "`(`")?;
+                let mut values = Vec::new();
+                if self.peek().map(|token| &token.kind) == Some(&TokenKind::CloseParen) {
+                    self.position += 1;
+                    return Ok((false, values));
+                }
+                loop {
+                    values.push(self.parse_value()?);
+                    let token = self.next_or_error("`,` or `)`")?;
+                    match token.kind {
+                        TokenKind::Comma => {}
+                        TokenKind::CloseParen => return Ok((false, values)),
+                        _ => return Err(QueryError::new("Expected `,` or `)`", token.span)),
+                    }
+                }
+            }
+            _ => Err(QueryError::new("Expected `=`, `!=` or `in`", token.span)),
+        }
+    }
+}
+
+fn negate(filter: Filter) -> Filter {
+    match filter {
+        Filter::PropertyFilter(PropertyFilter { op, property }) => PropertyFilter {
+            op: match op {
+                PropertyOperator::IsPresent => PropertyOperator::IsNotPresent,
+                PropertyOperator::IsNotPresent => PropertyOperator::IsPresent,
+            },
+            property,
+        }
+        .into(),
+        Filter::NotFilter(NotFilter { filter }) => *filter,
+        filter => NotFilter { filter: Box::new(filter) }.into(),
+    }
+}
+
+fn any_of(filters: impl Iterator<Item = Result<Filter, QueryError>>) -> Result<Filter, QueryError> {
+    let mut filters = filters.collect::<Result<Vec<_>, _>>()?;
+    Ok(if filters.len() == 1 { filters.remove(0) } else { OrFilter { filters }.into() })
+}
+
+fn parse_number(value: &Value) -> Result<f32, QueryError> {
+    value.text.parse().map_err(|_| QueryError::new("Expected a number", value.span.clone()))
+}
+
+fn parse_team(value: &Value) -> Result<Team, QueryError> {
+    match value.text.to_lowercase().as_str() {
+        "red" => Ok(Team::Red),
+        "blu" | "blue" => Ok(Team::Blu),
+        "spectator" | "spec" => Ok(Team::Spectator),
+        "other" => Ok(Team::Other),
+        _ => Err(QueryError::new("Unknown team, expected red or blu", value.span.clone())),
+    }
+}
+
+fn parse_classes(values: &[Value]) -> Result<[bool; 10], QueryError> {
+    let mut classes = [false; 10];
+    for value in values {
+        let class = class_index(&value.text).ok_or_else(|| QueryError::new("Unknown class", value.span.clone()))?;
+        classes[class] = true;
+    }
+    Ok(classes)
+}
+
+// Index of the class in the arrays of the class filters
+pub fn class_index(name: &str) -> Option<usize> {
+    match name.to_lowercase().as_str() {
+        "demo" => Some(4),
+        "engi" | "engy" => Some(9),
+        name => CLASS_NAMES.iter().position(|class| *class == name),
+    }
+}
+
+fn parse_weapons(values: Values) -> Filter {
+    let mut filters = Vec::new();
+    let mut kill_icons = Vec::new();
+    for value in values {
+        match weapon_group(&value.text).filter(|_| !value.quoted) {
+            Some(group) => filters.push(
+                WeaponFilter {
+                    weapon: WeaponSelection::Group(group),
+                }
+                .into(),
+            ),
+            None => kill_icons.push(value.text),
+        }
+    }
+    if !kill_icons.is_empty() || filters.is_empty() {
+        filters.push(
+            WeaponFilter {
+                weapon: WeaponSelection::KillIcons(kill_icons),
+            }
+            .into(),
+        );
+    }
+    if filters.len() == 1 {
+        filters.remove(0)
+    } else {
+        OrFilter { filters }.into()
+    }
+}
+
+fn parse_steam_id(value: &Value) -> Result<String, QueryError> {
+    normalize_steam_id(&value.text).ok_or_else(|| QueryError::new("Invalid SteamID", value.span.clone()))
+}
+
+// Demos use the [U:1:123] format, convert the other common ones so IDs can be copied from anywhere
+pub fn normalize_steam_id(value: &str) -> Option<String> {
+    if value.starts_with("[U:") {
+        Some(value.to_string())
+    } else if let Some(rest) = value.strip_prefix("STEAM_") {
+        let mut parts = rest.split(':').skip(1).map(|part| part.parse::<u64>());
+        match (parts.next(), parts.next()) {
+            (Some(Ok(y)), Some(Ok(z))) => Some(format!("[U:1:{}]", z * 2 + y)),
+            _ => None,
+        }
+    } else {
+        let steam_id64: u64 = value.parse().ok()?;
+        let account_id = steam_id64.checked_sub(76561197960265728)?;
+        Some(format!("[U:1:{}]", account_id))
+    }
+}
+
+pub fn parse_filter(query: &str) -> Result<Filter, QueryError> {
+    let mut parser = Parser {
+        tokens: tokenize(query)?,
+        position: 0,
+        end: query.len(),
+    };
+    let filter = parser.parse_or()?;
+    match parser.peek() {
+        Some(token) => Err(QueryError::new("Expected `and`, `or` or the end of the query", token.span.clone())),
+        None => Ok(filter),
+    }
+}
+
+// The top level `and` is split into separate filters, the way the filters pane and the job files store them
+pub fn parse_filters(query: &str) -> Result<Vec<Filter>, QueryError> {
+    if query.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    Ok(match parse_filter(query)? {
+        Filter::AndFilter(AndFilter { filters }) => filters,
+        filter => vec![filter],
+    })
+}
+
+fn weapon_group_name(group: WeaponGroup) -> &'static str {
+    match group {
+        WeaponGroup::RocketLaunchers => "rocket_launchers",
+        WeaponGroup::Melee => "melee",
+        WeaponGroup::Hitscan => "hitscan",
+        WeaponGroup::Projectile => "projectile",
+        WeaponGroup::Sentry => "sentry",
+    }
+}
+
+fn weapon_group(name: &str) -> Option<WeaponGroup> {
+    WeaponGroup::ALL.iter().copied().find(|group| weapon_group_name(*group).eq_ignore_ascii_case(name))
+}
+
+fn team_name(team: Team) -> &'static str {
+    match team {
+        Team::Red => "red",
+        Team::Blu => "blu",
+        Team::Spectator => "spectator",
+        Team::Other => "other",
+    }
+}
+
+fn operator_text(op: OrderedOperator) -> &'static str {
+    match op {
+        OrderedOperator::Equal => "=",
+        OrderedOperator::NotEqual => "!=",
+        OrderedOperator::Greater => ">",
+        OrderedOperator::Smaller => "<",
+        OrderedOperator::GreaterOrEqual => ">=",
+        OrderedOperator::SmallerOrEqual => "<=",
+    }
+}
+
+fn format_value(value: &str) -> String {
+    if !value.is_empty() && value.chars().all(is_word_char) {
+        value.to_string()
+    } else {
+        format!("\"{}\"", value)
+    }
+}
+
+// Kill icons named like a weapon group are quoted, so they aren't read back as the group
+fn format_kill_icon(icon: &str) -> String {
+    if weapon_group(icon).is_some() {
+        format!("\"{}\"", icon)
+    } else {
+        format_value(icon)
+    }
+}
+
+fn format_values<'a>(field: &str, values: impl IntoIterator<Item = &'a str>) -> String {
+    format_list(field, values.into_iter().map(format_value).collect())
+}
+
+// The values are already formatted
+fn format_list(field: &str, values: Vec<String>) -> String {
+    if values.len() == 1 {
+        format!("{} = {}", field, values[0])
+    } else {
+        format!("{} in ({})", field, values.join(", "))
+    }
+}
+
+fn format_classes(field: &str, classes: &[bool; 10]) -> String {
+    format_values(field, CLASS_NAMES.iter().zip(classes).filter(|(_, selected)| **selected).map(|(name, _)| *name))
+}
+
+fn format_property(property: Property) -> &'static str {
+    match property {
+        Property::Suicide => "suicide",
+        Property::Posthumous => "posthumous",
+        Property::DuringRound => "during_round",
+        Property::DiedToSentry => "sentry",
+    }
+}
+
+// The field and the formatted values of the filters `field in (...)` is parsed into
+fn list_values(filter: &Filter) -> Option<(&'static str, Vec<String>)> {
+    Some(match filter {
+        Filter::KillerTeamFilter(KillerTeamFilter { team }) => ("killer.team", vec![team_name(*team).to_string()]),
+        Filter::VictimTeamFilter(VictimTeamFilter { team }) => ("victim.team", vec![team_name(*team).to_string()]),
+        Filter::AssisterTeamFilter(AssisterTeamFilter { team }) => ("assister.team", vec![team_name(*team).to_string()]),
+        Filter::KillerPlayerFilter(KillerPlayerFilter { steam_id }) => ("killer", vec![format_value(steam_id)]),
+        Filter::VictimPlayerFilter(VictimPlayerFilter { steam_id }) => ("victim", vec![format_value(steam_id)]),
+        Filter::AssisterPlayerFilter(AssisterPlayerFilter { steam_id }) => ("assister", vec![format_value(steam_id)]),
+        Filter::WeaponFilter(WeaponFilter { weapon }) => match weapon {
+            WeaponSelection::Group(group) => ("weapon", vec![weapon_group_name(*group).to_string()]),
+            WeaponSelection::KillIcons(kill_icons) => ("weapon", kill_icons.iter().map(|icon| format_kill_icon(icon)).collect()),
+        },
+        _ => return None,
+    })
+}
+
+// An `or` of the same field as `field in (...)`, when the parser reads it back as the same filters
+fn format_in(filters: &[Filter]) -> Option<String> {
+    if filters.len() < 2 {
+        return None;
+    }
+    let mut field = None;
+    let mut values = Vec::new();
+    for (index, filter) in filters.iter().enumerate() {
+        // the weapons are parsed into groups followed by a single list of kill icons
+        if let Filter::WeaponFilter(WeaponFilter {
+            weapon: WeaponSelection::KillIcons(kill_icons),
+        }) = filter
+        {
+            if index + 1 != filters.len() || kill_icons.is_empty() {
+                return None;
+            }
+        }
+        let (filter_field, filter_values) = list_values(filter)?;
+        if field.is_some() && field != Some(filter_field) {
+            return None;
+        }
+        field = Some(filter_field);
+        values.extend(filter_values);
+    }
+    Some(format!("{} in ({})", field?, values.join(", ")))
+}
+
+// The `or` filters that are written as a chain of `or`, rather than with `in (...)`
+fn is_or_chain(filter: &Filter) -> bool {
+    matches!(filter, Filter::OrFilter(OrFilter { filters }) if format_in(filters).is_none())
+}
+
+fn format_operands(filters: &[Filter], separator: &str, needs_parentheses: fn(&Filter) -> bool) -> String {
+    let operands: Vec<_> = filters
+        .iter()
+        .map(|filter| {
+            if needs_parentheses(filter) {
+                format!("({})", format_filter(filter))
+            } else {
+                format_filter(filter)
+            }
+        })
+        .collect();
+    operands.join(separator)
+}
+
+pub fn format_filter(filter: &Filter) -> String {
+    match filter {
+        Filter::KillerTeamFilter(KillerTeamFilter { team }) => format!("killer.team = {}", team_name(*team)),
+        Filter::VictimTeamFilter(VictimTeamFilter { team }) => format!("victim.team = {}", team_name(*team)),
+        Filter::KillerClassFilter(KillerClassFilter { classes }) => format_classes("killer.class", classes),
+        Filter::VictimClassFilter(VictimClassFilter { classes }) => format_classes("victim.class", classes),
+        Filter::AssisterTeamFilter(AssisterTeamFilter { team }) => format!("assister.team = {}", team_name(*team)),
+        Filter::AssisterClassFilter(AssisterClassFilter { classes }) => format_classes("assister.class", classes),
+        Filter::KillerElevationFilter(KillerElevationFilter { op, z }) => format!("killer.z {} {}", operator_text(*op), z),
+        Filter::VictimElevationFilter(VictimElevationFilter { op, z }) => format!("victim.z {} {}", operator_text(*op), z),
+        Filter::Distance2DFilter(Distance2DFilter { op, distance }) => format!("distance2d {} {}", operator_text(*op), distance),
+        Filter::Distance3DFilter(Distance3DFilter { op, distance }) => format!("distance3d {} {}", operator_text(*op), distance),
+        Filter::RoundFilter(RoundFilter { op, round }) => format!("round {} {}", operator_text(*op), round),
+        Filter::PropertyFilter(PropertyFilter { op, property }) => match op {
+            PropertyOperator::IsPresent => format_property(*property).to_string(),
+            PropertyOperator::IsNotPresent => format!("not {}", format_property(*property)),
+        },
+        Filter::WeaponFilter(WeaponFilter { weapon }) => match weapon {
+            WeaponSelection::Group(group) => format!("weapon = {}", weapon_group_name(*group)),
+            WeaponSelection::KillIcons(kill_icons) => format_list("weapon", kill_icons.iter().map(|icon| format_kill_icon(icon)).collect()),
+        },
+        Filter::KillerPlayerFilter(KillerPlayerFilter { steam_id }) => format!("killer = {}", format_value(steam_id)),
+        Filter::VictimPlayerFilter(VictimPlayerFilter { steam_id }) => format!("victim = {}", format_value(steam_id)),
+        Filter::AssisterPlayerFilter(AssisterPlayerFilter { steam_id }) => format!("assister = {}", format_value(steam_id)),
+        Filter::MatchTimeFilter(MatchTimeFilter { op, seconds }) => format!("match_time {} {}", operator_text(*op), seconds),
+        Filter::RoundTimeFilter(RoundTimeFilter { op, seconds }) => format!("round_time {} {}", operator_text(*op), seconds),
+        Filter::TimeAliveFilter(TimeAliveFilter { op, seconds }) => format!("time_alive {} {}", operator_text(*op), seconds),
+        // `and` binds tighter than `or`, the parentheses around an `and` in an `or` are only there to make it easier to read,
+        // the other ones keep the nested groups when the query is parsed again
+        Filter::AndFilter(AndFilter { filters }) => format_operands(filters, " and ", |filter| is_or_chain(filter) || matches!(filter, Filter::AndFilter(_))),
+        Filter::OrFilter(OrFilter { filters }) => match format_in(filters) {
+            Some(list) => list,
+            None => format_operands(filters, " or ", |filter| is_or_chain(filter) || matches!(filter, Filter::AndFilter(_))),
+        },
+        Filter::NotFilter(NotFilter { filter }) => match filter.as_ref() {
+            filter if is_or_chain(filter) || matches!(filter, Filter::AndFilter(_)) => format!("not ({})", format_filter(filter)),
+            filter => format!("not {}", format_filter(filter)),
+        },
+    }
+}
+
+pub fn format_filters(filters: &[Filter]) -> String {
+    if let [filter] = filters {
+        return format_filter(filter);
+    }
+    format_operands(filters, " and ", |filter| is_or_chain(filter) || matches!(filter, Filter::AndFilter(_)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The filters don't implement PartialEq, their serialization is compared instead
+    fn round_trip(query: &str) -> String {
+        let filters = parse_filters(query).unwrap();
+        let formatted = format_filters(&filters);
+        let parsed_again = parse_filters(&formatted).unwrap();
+        assert_eq!(
+            serde_json::to_value(&parsed_again).unwrap(),
+            serde_json::to_value(&filters).unwrap(),
+            "{} doesn't round-trip",
+            query
+        );
+        assert_eq!(format_filters(&parsed_again), formatted);
+        formatted
+    }
+
+    fn format_weapon(weapon: WeaponSelection) -> String {
+        format_filter(&WeaponFilter { weapon }.into())
+    }
+
+    fn error_span(query: &str) -> Range<usize> {
+        parse_filters(query).unwrap_err().span
+    }
+
+    #[test]
+    fn predicates() {
+        assert_eq!(round_trip("killer.team = red"), "killer.team = red");
+        assert_eq!(round_trip("victim.class in (scout, SOLDIER)"), "victim.class in (scout, soldier)");
+        assert_eq!(round_trip("victim.class == medic"), "victim.class = medic");
+        assert_eq!(round_trip("distance2d >= 512"), "distance2d >= 512");
+        assert_eq!(round_trip("round ≤ 2"), "round <= 2");
+        assert_eq!(round_trip("weapon = melee"), "weapon = melee");
+        assert_eq!(
+            round_trip("weapon in (tf_projectile_rocket, \"odd icon\")"),
+            "weapon in (tf_projectile_rocket, \"odd icon\")"
+        );
+        assert_eq!(round_trip("not suicide and during_round"), "not suicide and during_round");
+    }
+
+    #[test]
+    fn not_equal_becomes_not() {
+        assert_eq!(round_trip("killer.team != red"), "not killer.team = red");
+        assert_eq!(round_trip("victim.class != spy"), "not victim.class = spy");
+    }
+
+    #[test]
+    fn nested_groups() {
+        assert_eq!(round_trip("round = 1 or round = 2 and suicide"), "round = 1 or (round = 2 and suicide)");
+        assert_eq!(round_trip("(round = 1 or round = 2) and suicide"), "(round = 1 or round = 2) and suicide");
+        assert_eq!(round_trip("not (suicide or posthumous) and round > 1"), "not (suicide or posthumous) and round > 1");
+        assert_eq!(round_trip("not not sentry"), "sentry");
+        assert_eq!(
+            round_trip("((killer.class = scout and distance3d < 300) or (killer.class = sniper and not distance3d < 1000)) and not suicide"),
+            "((killer.class = scout and distance3d < 300) or (killer.class = sniper and not distance3d < 1000)) and not suicide"
+        );
+        // the top level `and` gives separate filters
+        assert_eq!(parse_filters("suicide and round = 1 and sentry").unwrap().len(), 3);
+        assert_eq!(parse_filters("suicide or round = 1").unwrap().len(), 1);
+        assert!(parse_filters("  ").unwrap().is_empty());
+    }
+
+    #[test]
+    fn lists() {
+        assert_eq!(round_trip("killer in ([U:1:1], [U:1:2], [U:1:3])"), "killer in ([U:1:1], [U:1:2], [U:1:3])");
+        assert_eq!(round_trip("victim.team in (red, blu)"), "victim.team in (red, blu)");
+        assert_eq!(round_trip("assister in ([U:1:1])"), "assister = [U:1:1]");
+        assert_eq!(round_trip("not killer in ([U:1:1], [U:1:2])"), "not killer in ([U:1:1], [U:1:2])");
+        assert_eq!(
+            round_trip("killer != [U:1:1] and killer.team in (red, blu)"),
+            "not killer = [U:1:1] and killer.team in (red, blu)"
+        );
+        assert_eq!(round_trip("suicide or killer in ([U:1:1], [U:1:2])"), "suicide or killer in ([U:1:1], [U:1:2])");
+        // an `or` of different fields stays a chain
+        assert_eq!(round_trip("killer = [U:1:1] or victim = [U:1:1]"), "killer = [U:1:1] or victim = [U:1:1]");
+        // the nested groups are kept
+        assert_eq!(round_trip("(round = 1 or round = 2) or suicide"), "(round = 1 or round = 2) or suicide");
+        assert_eq!(round_trip("(round = 1 and round = 2) and suicide"), "(round = 1 and round = 2) and suicide");
+        assert!(parse_filters("killer in ()").is_err());
+    }
+
+    #[test]
+    fn weapons() {
+        assert_eq!(round_trip("weapon in (melee, hitscan)"), "weapon in (melee, hitscan)");
+        assert_eq!(
+            round_trip("weapon in (melee, tf_projectile_rocket, scattergun)"),
+            "weapon in (melee, tf_projectile_rocket, scattergun)"
+        );
+        assert_eq!(round_trip("weapon in (scattergun, melee)"), "weapon in (melee, scattergun)");
+        assert_eq!(round_trip("weapon = \"melee\""), "weapon = \"melee\"");
+        assert_eq!(round_trip("weapon in (\"melee\", melee)"), "weapon in (melee, \"melee\")");
+        assert_eq!(round_trip("weapon in ()"), "weapon in ()");
+        // kill icons are never written as groups
+        assert_eq!(format_weapon(WeaponSelection::KillIcons(vec!["melee".into()])), "weapon = \"melee\"");
+        assert_eq!(
+            format_weapon(WeaponSelection::KillIcons(vec!["Sentry".into(), "knife".into()])),
+            "weapon in (\"Sentry\", knife)"
+        );
+        assert_eq!(format_weapon(WeaponSelection::Group(WeaponGroup::Melee)), "weapon = melee");
+        let kill_icons = parse_filter("weapon = \"melee\"").unwrap();
+        assert!(matches!(kill_icons, Filter::WeaponFilter(WeaponFilter { weapon: WeaponSelection::KillIcons(icons) }) if icons == ["melee"]));
+        // an `or` that `in (...)` can't give back stays a chain
+        let or_filter = OrFilter {
+            filters: vec![
+                WeaponFilter {
+                    weapon: WeaponSelection::KillIcons(vec!["knife".into()]),
+                }
+                .into(),
+                WeaponFilter {
+                    weapon: WeaponSelection::Group(WeaponGroup::Melee),
+                }
+                .into(),
+            ],
+        };
+        let formatted = format_filter(&or_filter.into());
+        assert_eq!(formatted, "weapon = knife or weapon = melee");
+        assert_eq!(round_trip(&formatted), formatted);
+    }
+
+    #[test]
+    fn steam_ids() {
+        assert_eq!(round_trip("killer = [U:1:123]"), "killer = [U:1:123]");
+        assert_eq!(round_trip("victim = STEAM_0:1:61"), "victim = [U:1:123]");
+        assert_eq!(round_trip("assister = 76561197960265851"), "assister = [U:1:123]");
+        assert_eq!(round_trip("killer in (STEAM_1:0:5, 76561197960265738)"), "killer in ([U:1:10], [U:1:10])");
+        assert!(parse_filters("killer = STEAM_0:1").is_err());
+        assert!(parse_filters("killer = 123").is_err());
+    }
+
+    #[test]
+    fn error_spans() {
+        assert_eq!(error_span("killer.team = green"), 14..19);
+        assert_eq!(error_span("victim.class in (scout, cook)"), 24..28);
+        assert_eq!(error_span("killer = STEAM_0:1"), 9..18);
+        assert_eq!(error_span("round >"), 7..7);
+        assert_eq!(error_span("round > two"), 8..11);
+        assert_eq!(error_span("suicide and (round = 1"), 22..22);
+        assert_eq!(error_span("suicide round = 1"), 8..13);
+        assert_eq!(error_span("killer.team ~ red"), 12..13);
+        assert_eq!(error_span("weapon = \"melee"), 9..15);
+        assert_eq!(error_span("health > 5"), 0..6);
+    }
+
+    #[test]
+    fn error_descriptions() {
+        let query = "killer.team = green";
+        let error = parse_filter(query).unwrap_err();
+        assert_eq!(error.describe(query), "Unknown team, expected red or blu at \"green\" (column 15)");
+        assert_eq!(error.pretty(query), "Unknown team, expected red or blu\n  killer.team = green\n                ^^^^^");
+        assert!(parse_filter("round >").unwrap_err().describe("round >").ends_with("at the end of the query"));
+    }
+}
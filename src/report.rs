@@ -0,0 +1,75 @@
+use crate::{
+    export::DeathRecord,
+    gradient::HeatmapGradient,
+    heatmap::{HeatMapGenerator, HeatmapType},
+    heatmap_analyser::Death,
+    svg::{escape, hex_color},
+};
+use image::{codecs::png::PngEncoder, ColorType, ImageEncoder, RgbImage};
+use serde::Serialize;
+use std::error::Error;
+
+const TEMPLATE: &str = include_str!("report.html");
+const GRADIENT_STOPS: usize = 9;
+
+#[derive(Serialize)]
+struct ReportDeath {
+    #[serde(flatten)]
+    record: DeathRecord,
+    // Already in image pixels so that the page doesn't need to know about the camera
+    killer_screen: Option<(f32, f32)>,
+    victim_screen: Option<(f32, f32)>,
+}
+
+#[derive(Serialize)]
+struct ReportColors {
+    killer: String,
+    victim: String,
+    line: Vec<(f32, String)>,
+}
+
+// A single html file with the level overview, the deaths and a script to browse them, it doesn't load anything from elsewhere
+pub fn render_report<'a>(
+    heatmap_generator: &HeatMapGenerator,
+    gradient: &HeatmapGradient,
+    overview: &RgbImage,
+    title: &str,
+    summary: &str,
+    deaths: impl IntoIterator<Item = (&'a Death, DeathRecord)>,
+) -> Result<String, Box<dyn Error>> {
+    let (width, height) = overview.dimensions();
+    let mut png = Vec::new();
+    PngEncoder::new(&mut png).write_image(overview.as_raw(), width, height, ColorType::Rgb8)?;
+
+    let line_gradient = gradient.line_gradient();
+    let colors = ReportColors {
+        killer: hex_color(line_gradient.get(0.0)),
+        victim: hex_color(line_gradient.get(1.0)),
+        line: (0..GRADIENT_STOPS)
+            .map(|index| {
+                let offset = index as f32 / (GRADIENT_STOPS - 1) as f32;
+                (offset, hex_color(line_gradient.get(offset)))
+            })
+            .collect(),
+    };
+    let deaths: Vec<_> = deaths
+        .into_iter()
+        .map(|(death, record)| ReportDeath {
+            record,
+            killer_screen: heatmap_generator.screen_position(HeatmapType::KillerPosition, death),
+            victim_screen: heatmap_generator.screen_position(HeatmapType::VictimPosition, death),
+        })
+        .collect();
+
+    Ok(TEMPLATE
+        .replace("{{TITLE}}", &escape(title))
+        .replace("{{SUMMARY}}", &escape(summary))
+        .replace("{{OVERVIEW}}", &base64::encode(&png))
+        .replace("{{COLORS}}", &script_json(&colors)?)
+        .replace("{{DEATHS}}", &script_json(&deaths)?))
+}
+
+// JSON that can't end the <script> element it's embedded in, < is only ever found in strings
+fn script_json(value: &impl Serialize) -> Result<String, serde_json::Error> {
+    Ok(serde_json::to_string(value)?.replace('<', "\\u003c"))
+}
@@ -0,0 +1,132 @@
+use crate::{
+    export::DeathRecord,
+    gradient::HeatmapGradient,
+    heatmap::{HeatMapGenerator, HeatmapType},
+    heatmap_analyser::Death,
+};
+use image::{codecs::png::PngEncoder, ColorType, ImageEncoder, RgbImage};
+use palette::LinSrgba;
+use std::{error::Error, fmt::Write};
+
+const POINT_RADIUS: f32 = 4.0;
+const LINE_WIDTH: f32 = 1.5;
+// Samples of the line gradient, SVG only interpolates linearly between its stops
+const GRADIENT_STOPS: usize = 9;
+
+// Vector version of the lines and of the victim / killer positions, the level overview is embedded as a png.
+// Every line or point has a tooltip describing its death.
+pub fn render_svg<'a>(
+    heatmap_generator: &HeatMapGenerator,
+    heatmap_type: HeatmapType,
+    gradient: &HeatmapGradient,
+    overview: &RgbImage,
+    deaths: impl IntoIterator<Item = (&'a Death, DeathRecord)>,
+) -> Result<String, Box<dyn Error>> {
+    let (width, height) = overview.dimensions();
+    let mut png = Vec::new();
+    PngEncoder::new(&mut png).write_image(overview.as_raw(), width, height, ColorType::Rgb8)?;
+
+    let line_gradient = gradient.line_gradient();
+    let mut svg = String::new();
+    writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" width="{0}" height="{1}" viewBox="0 0 {0} {1}">"#,
+        width, height
+    )?;
+    writeln!(svg, "<defs>")?;
+    writeln!(svg, r#"<linearGradient id="line-gradient">"#)?;
+    for index in 0..GRADIENT_STOPS {
+        let offset = index as f32 / (GRADIENT_STOPS - 1) as f32;
+        writeln!(svg, r#"<stop offset="{}" stop-color="{}"/>"#, offset, hex_color(line_gradient.get(offset)))?;
+    }
+    writeln!(svg, "</linearGradient>")?;
+    writeln!(
+        svg,
+        r#"<marker id="arrowhead" viewBox="0 0 10 10" refX="10" refY="5" markerWidth="4" markerHeight="4" orient="auto"><path d="M0,0 L10,5 L0,10 z" fill="{}"/></marker>"#,
+        hex_color(line_gradient.get(1.0))
+    )?;
+    writeln!(svg, "</defs>")?;
+    let data = format!("data:image/png;base64,{}", base64::encode(&png));
+    writeln!(svg, r#"<image width="{}" height="{}" href="{2}" xlink:href="{2}"/>"#, width, height, data)?;
+
+    for (index, (death, record)) in deaths.into_iter().enumerate() {
+        match heatmap_type {
+            HeatmapType::Lines => {
+                let killer_coords = heatmap_generator.screen_position(HeatmapType::KillerPosition, death);
+                let victim_coords = heatmap_generator.screen_position(HeatmapType::VictimPosition, death);
+                if let (Some((x1, y1)), Some((x2, y2))) = (killer_coords, victim_coords) {
+                    // the gradient has to follow each line, with a bounding box based one it would be stretched across the line's box instead
+                    writeln!(
+                        svg,
+                        r##"<linearGradient id="line-{}" xlink:href="#line-gradient" href="#line-gradient" gradientUnits="userSpaceOnUse" x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}"/>"##,
+                        index, x1, y1, x2, y2
+                    )?;
+                    writeln!(
+                        svg,
+                        r##"<line x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" stroke="url(#line-{})" stroke-width="{}" marker-end="url(#arrowhead)"><title>{}</title></line>"##,
+                        x1,
+                        y1,
+                        x2,
+                        y2,
+                        index,
+                        LINE_WIDTH,
+                        escape(&describe(&record))
+                    )?;
+                }
+            }
+            HeatmapType::VictimPosition | HeatmapType::KillerPosition | HeatmapType::AssisterPosition => {
+                // same colors as the ends of the lines, the assisters in between
+                let color = match heatmap_type {
+                    HeatmapType::VictimPosition => line_gradient.get(1.0),
+                    HeatmapType::KillerPosition => line_gradient.get(0.0),
+                    _ => line_gradient.get(0.5),
+                };
+                if let Some((x, y)) = heatmap_generator.screen_position(heatmap_type, death) {
+                    writeln!(
+                        svg,
+                        r##"<circle cx="{:.1}" cy="{:.1}" r="{}" fill="{}" stroke="#000000" stroke-width="0.5"><title>{}</title></circle>"##,
+                        x,
+                        y,
+                        POINT_RADIUS,
+                        hex_color(color),
+                        escape(&describe(&record))
+                    )?;
+                }
+            }
+            HeatmapType::KillDeath | HeatmapType::Occupancy | HeatmapType::Path => return Err(format!("{} heatmaps can't be rendered as SVG", heatmap_type).into()),
+        }
+    }
+    writeln!(svg, "</svg>")?;
+    Ok(svg)
+}
+
+fn describe(record: &DeathRecord) -> String {
+    let kill = if record.suicide {
+        format!("{} killed themselves", record.victim_name)
+    } else {
+        format!("{} killed {}", record.killer_name, record.victim_name)
+    };
+    format!(
+        "{} with {}\nTick {} ({}), round {}\n{}",
+        kill,
+        record.weapon,
+        record.demo_tick,
+        format_time(record.demo_time),
+        record.round,
+        record.demo
+    )
+}
+
+fn format_time(seconds: f32) -> String {
+    let seconds = seconds.max(0.0) as u32;
+    format!("{}:{:02}", seconds / 60, seconds % 60)
+}
+
+pub(crate) fn hex_color(color: LinSrgba) -> String {
+    let channel = |value: f32| (value.clamp(0.0, 1.0) * 255.0) as u8;
+    format!("#{:02x}{:02x}{:02x}", channel(color.red), channel(color.green), channel(color.blue))
+}
+
+pub(crate) fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
@@ -1,7 +1,8 @@
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use std::fmt::Display;
 
-#[derive(Debug, Copy, Clone, PartialEq, Serialize)]
-pub(crate) enum Weapon {
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize)]
+pub enum Weapon {
     Unknown,
     AirStrike,
     AliBabasWeeBooties,
@@ -169,6 +170,7 @@ pub(crate) enum Weapon {
     ScottishResistance,
     SecondBanana,
     SelfAwareBeautyMark,
+    SentryGun,
     Shahanshah,
     SharpDresser,
     SharpenedVolcanoFragment,
@@ -214,7 +216,7 @@ pub(crate) enum Weapon {
 
 // https://wiki.alliedmods.net/Team_fortress_2_item_definition_indexes#Weapons
 
-pub(crate) fn index_to_weapon(index: i32) -> Weapon {
+pub fn index_to_weapon(index: i32) -> Weapon {
     match index {
         13 => Weapon::Scattergun,
         200 => Weapon::Scattergun, // Renamed/Strange
@@ -757,3 +759,429 @@ pub(crate) fn index_to_weapon(index: i32) -> Weapon {
         _ => Weapon::Unknown,
     }
 }
+
+// Kill icons as they appear in the player_death event, see https://wiki.teamfortress.com/wiki/Kill_icons
+pub fn kill_icon_to_weapon(kill_icon: &str) -> Weapon {
+    match kill_icon {
+        // Scout
+        "scattergun" => Weapon::Scattergun,
+        "force_a_nature" => Weapon::ForceANature,
+        "shortstop" => Weapon::Shortstop,
+        "soda_popper" => Weapon::SodaPopper,
+        "pep_brawlerblaster" => Weapon::BabyFaceBlaster,
+        "back_scatter" => Weapon::BackScatter,
+        "pistol_scout" | "pistol" => Weapon::Pistol,
+        "maxgun" => Weapon::Lugermorph,
+        "the_winger" => Weapon::Winger,
+        "pep_pistol" => Weapon::PrettyBoysPocketPistol,
+        "guillotine" => Weapon::FlyingGuillotine,
+        "bat" => Weapon::Bat,
+        "sandman" | "ball" => Weapon::Sandman,
+        "holy_mackerel" => Weapon::HolyMackerel,
+        "unarmed_combat" => Weapon::UnarmedCombat,
+        "batsaber" => Weapon::BatSaber,
+        "candy_cane" => Weapon::CandyCane,
+        "boston_basher" => Weapon::BostonBasher,
+        "scout_sword" => Weapon::ThreeRuneBlade,
+        "warfan" => Weapon::FanOWar,
+        "atomizer" => Weapon::Atomizer,
+        "lava_bat" => Weapon::SunOnAStick,
+        "wrap_assassin" => Weapon::WrapAssassin,
+
+        // Soldier
+        "tf_projectile_rocket" | "rocketlauncher" => Weapon::RocketLauncher,
+        "rocketlauncher_directhit" => Weapon::DirectHit,
+        "blackbox" => Weapon::BlackBox,
+        "liberty_launcher" => Weapon::LibertyLauncher,
+        "cow_mangler" => Weapon::CowMangler5000,
+        "quake_rl" => Weapon::Original,
+        "dumpster_device" => Weapon::BeggarsBazooka,
+        "airstrike" => Weapon::AirStrike,
+        "shotgun_soldier" | "shotgun_pyro" | "shotgun_hwg" | "shotgun_primary" => Weapon::Shotgun,
+        "righteous_bison" => Weapon::RighteousBison,
+        "panic_attack" => Weapon::PanicAttack,
+        "reserve_shooter" => Weapon::ReserveShooter,
+        "shovel" => Weapon::Shovel,
+        "pickaxe" | "unique_pickaxe" => Weapon::Equalizer,
+        "unique_pickaxe_escape" => Weapon::EscapePlan,
+        "disciplinary_action" => Weapon::DisciplinaryAction,
+        "market_gardener" => Weapon::MarketGardener,
+        "paintrain" => Weapon::PainTrain,
+        "mantreads" => Weapon::Mantreads,
+        "demokatana" => Weapon::HalfZatoichi,
+
+        // Pyro
+        "flamethrower" => Weapon::FlameThrower,
+        "backburner" => Weapon::Backburner,
+        "degreaser" => Weapon::Degreaser,
+        "phlogistinator" => Weapon::Phlogistinator,
+        "rainblower" => Weapon::Rainblower,
+        "nostromo_napalmer" => Weapon::NostromoNapalmer,
+        "dragons_fury" | "dragons_fury_bonus" => Weapon::DragonsFury,
+        "flaregun" => Weapon::FlareGun,
+        "detonator" => Weapon::Detonator,
+        "scorch_shot" => Weapon::ScorchShot,
+        "manmelter" => Weapon::Manmelter,
+        "fireaxe" => Weapon::FireAxe,
+        "axtinguisher" => Weapon::Axtinguisher,
+        "powerjack" => Weapon::Powerjack,
+        "sledgehammer" => Weapon::Homewrecker,
+        "back_scratcher" => Weapon::BackScratcher,
+        "lava_axe" => Weapon::SharpenedVolcanoFragment,
+        "the_maul" => Weapon::Maul,
+        "thirddegree" => Weapon::ThirdDegree,
+        "annihilator" => Weapon::NeonAnnihilator,
+        "hot_hand" => Weapon::HotHand,
+        "lollichop" => Weapon::Lollichop,
+
+        // Demoman
+        "tf_projectile_pipe" => Weapon::GrenadeLauncher,
+        "tf_projectile_pipe_remote" => Weapon::StickybombLauncher,
+        "loch_n_load" => Weapon::LochNLoad,
+        "loose_cannon" | "loose_cannon_impact" | "loose_cannon_explosion" | "loose_cannon_reflect" => Weapon::LooseCannon,
+        "iron_bomber" => Weapon::IronBomber,
+        "quickiebomb_launcher" => Weapon::QuickiebombLauncher,
+        "sticky_resistance" => Weapon::ScottishResistance,
+        "bottle" => Weapon::Bottle,
+        "sword" => Weapon::Eyelander,
+        "headtaker" => Weapon::HorselessHeadlessHorsemannsHeadtaker,
+        "nessieclub" => Weapon::NessiesNineIron,
+        "claidheamohmor" => Weapon::ClaidheamhMor,
+        "battleaxe" => Weapon::ScotsmansSkullcutter,
+        "persian_persuader" => Weapon::PersianPersuader,
+        "ullapool_caber" | "ullapool_caber_explosion" => Weapon::UllapoolCaber,
+        "scotland_shard" => Weapon::ScottishHandshake,
+        "demoshield" => Weapon::CharginTarge,
+        "splendid_screen" => Weapon::SplendidScreen,
+        "tide_turner" => Weapon::TideTurner,
+
+        // Heavy
+        "minigun" => Weapon::Minigun,
+        "natascha" => Weapon::Natascha,
+        "iron_curtain" => Weapon::IronCurtain,
+        "brass_beast" => Weapon::BrassBeast,
+        "tomislav" => Weapon::Tomislav,
+        "long_heatmaker" => Weapon::HuoLongHeater,
+        "deflector" => Weapon::Deflector,
+        "family_business" => Weapon::FamilyBusiness,
+        "fists" => Weapon::Fists,
+        "gloves" => Weapon::KillingGlovesOfBoxing,
+        "gloves_running_urgently" => Weapon::GlovesOfRunningUrgently,
+        "warrior_spirit" => Weapon::WarriorsSpirit,
+        "steel_fists" => Weapon::FistsOfSteel,
+        "eviction_notice" => Weapon::EvictionNotice,
+        "holiday_punch" => Weapon::HolidayPunch,
+        "bread_bite" => Weapon::BreadBite,
+        "apocofists" => Weapon::ApocoFists,
+
+        // Engineer
+        "frontier_justice" => Weapon::FrontierJustice,
+        "widowmaker" => Weapon::Widowmaker,
+        "pomson" => Weapon::Pomson6000,
+        "rescue_ranger" | "rescue_ranger_reflect" => Weapon::RescueRanger,
+        "wrench" => Weapon::Wrench,
+        "robot_arm" | "robot_arm_combo_kill" | "robot_arm_blender_kill" => Weapon::Gunslinger,
+        "southern_hospitality" => Weapon::SouthernHospitality,
+        "eureka_effect" => Weapon::EurekaEffect,
+        "wrench_jag" => Weapon::Jag,
+        "wrench_golden" => Weapon::GoldenWrench,
+        "obj_sentrygun" | "obj_sentrygun2" | "obj_sentrygun3" | "obj_minisentry" => Weapon::SentryGun,
+        "wrangler_kill" => Weapon::Wrangler,
+
+        // Medic
+        "syringegun_medic" => Weapon::SyringeGun,
+        "blutsauger" => Weapon::Blutsauger,
+        "proto_syringe" => Weapon::Overdose,
+        "crusaders_crossbow" => Weapon::CrusadersCrossbow,
+        "bonesaw" => Weapon::Bonesaw,
+        "ubersaw" => Weapon::Ubersaw,
+        "battleneedle" => Weapon::VitaSaw,
+        "amputator" => Weapon::Amputator,
+        "solemn_vow" => Weapon::SolemnVow,
+
+        // Sniper
+        "sniperrifle" => Weapon::SniperRifle,
+        "sydney_sleeper" => Weapon::SydneySleeper,
+        "bazaar_bargain" => Weapon::BazaarBargain,
+        "machina" | "player_penetration" => Weapon::Machina,
+        "pro_rifle" => Weapon::HitmansHeatmaker,
+        "the_classic" => Weapon::Classic,
+        "awper_hand" => Weapon::AWPerHand,
+        "shooting_star" => Weapon::ShootingStar,
+        "tf_projectile_arrow" | "huntsman" | "compound_bow" | "huntsman_flyingburn" => Weapon::Huntsman,
+        "smg" => Weapon::SMG,
+        "pro_smg" => Weapon::CleanersCarbine,
+        "club" => Weapon::Kukri,
+        "tribalkukri" => Weapon::TribalmansShiv,
+        "bushwacka" => Weapon::Bushwacka,
+        "shahanshah" => Weapon::Shahanshah,
+
+        // Spy
+        "revolver" => Weapon::Revolver,
+        "ambassador" => Weapon::Ambassador,
+        "letranger" => Weapon::LEtranger,
+        "enforcer" => Weapon::Enforcer,
+        "diamondback" => Weapon::Diamondback,
+        "samrevolver" => Weapon::BigKill,
+        "knife" => Weapon::Knife,
+        "eternal_reward" => Weapon::YourEternalReward,
+        "kunai" => Weapon::ConniversKunai,
+        "big_earner" => Weapon::BigEarner,
+        "voodoo_pin" => Weapon::WangaPrick,
+        "sharp_dresser" => Weapon::SharpDresser,
+        "spy_cicle" => Weapon::Spycicle,
+        "black_rose" => Weapon::BlackRose,
+
+        // All class
+        "saxxy" => Weapon::Saxxy,
+        "fryingpan" | "frying_pan" => Weapon::FryingPan,
+        "golden_fryingpan" => Weapon::GoldenFryingPan,
+        "necro_smasher" => Weapon::NecroSmasher,
+        "freedom_staff" => Weapon::FreedomStaff,
+        "bat_outta_hell" => Weapon::BatOuttaHell,
+        "memory_maker" => Weapon::MemoryMaker,
+        "ham_shank" => Weapon::HamShank,
+        "prinny_machete" => Weapon::PrinnyMachete,
+        "crossing_guard" => Weapon::CrossingGuard,
+
+        _ => Weapon::Unknown,
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum WeaponKind {
+    Hitscan,
+    Projectile,
+    Melee,
+    // Flames, explosions on death, stomps, buildings, ...
+    Other,
+}
+
+impl Weapon {
+    pub fn is_rocket_launcher(self) -> bool {
+        matches!(
+            self,
+            Weapon::RocketLauncher
+                | Weapon::DirectHit
+                | Weapon::BlackBox
+                | Weapon::RocketJumper
+                | Weapon::LibertyLauncher
+                | Weapon::CowMangler5000
+                | Weapon::Original
+                | Weapon::BeggarsBazooka
+                | Weapon::AirStrike
+        )
+    }
+
+    pub fn kind(self) -> WeaponKind {
+        match self {
+            Weapon::Ambassador
+            | Weapon::AWPerHand
+            | Weapon::BabyFaceBlaster
+            | Weapon::BackScatter
+            | Weapon::BazaarBargain
+            | Weapon::BigKill
+            | Weapon::BrassBeast
+            | Weapon::Classic
+            | Weapon::CleanersCarbine
+            | Weapon::Deflector
+            | Weapon::Diamondback
+            | Weapon::Enforcer
+            | Weapon::FamilyBusiness
+            | Weapon::ForceANature
+            | Weapon::FrontierJustice
+            | Weapon::HitmansHeatmaker
+            | Weapon::HuoLongHeater
+            | Weapon::IronCurtain
+            | Weapon::LEtranger
+            | Weapon::Lugermorph
+            | Weapon::Machina
+            | Weapon::Minigun
+            | Weapon::Natascha
+            | Weapon::PanicAttack
+            | Weapon::Pistol
+            | Weapon::PrettyBoysPocketPistol
+            | Weapon::ReserveShooter
+            | Weapon::Revolver
+            | Weapon::Scattergun
+            | Weapon::ShootingStar
+            | Weapon::Shortstop
+            | Weapon::Shotgun
+            | Weapon::SMG
+            | Weapon::SniperRifle
+            | Weapon::SodaPopper
+            | Weapon::SydneySleeper
+            | Weapon::Tomislav
+            | Weapon::Widowmaker
+            | Weapon::Winger => WeaponKind::Hitscan,
+            Weapon::AirStrike
+            | Weapon::BeggarsBazooka
+            | Weapon::BlackBox
+            | Weapon::Blutsauger
+            | Weapon::CowMangler5000
+            | Weapon::CrusadersCrossbow
+            | Weapon::Detonator
+            | Weapon::DirectHit
+            | Weapon::DragonsFury
+            | Weapon::FlareGun
+            | Weapon::FlyingGuillotine
+            | Weapon::FortifiedCompound
+            | Weapon::GrenadeLauncher
+            | Weapon::Huntsman
+            | Weapon::IronBomber
+            | Weapon::LibertyLauncher
+            | Weapon::LochNLoad
+            | Weapon::LooseCannon
+            | Weapon::Manmelter
+            | Weapon::Original
+            | Weapon::Overdose
+            | Weapon::Pomson6000
+            | Weapon::QuickiebombLauncher
+            | Weapon::RescueRanger
+            | Weapon::RighteousBison
+            | Weapon::RocketJumper
+            | Weapon::RocketLauncher
+            | Weapon::ScorchShot
+            | Weapon::ScottishResistance
+            | Weapon::StickybombLauncher
+            | Weapon::StickyJumper
+            | Weapon::SyringeGun => WeaponKind::Projectile,
+            Weapon::Amputator
+            | Weapon::ApocoFists
+            | Weapon::Atomizer
+            | Weapon::Axtinguisher
+            | Weapon::BackScratcher
+            | Weapon::Bat
+            | Weapon::BatOuttaHell
+            | Weapon::BatSaber
+            | Weapon::BigEarner
+            | Weapon::BlackRose
+            | Weapon::Bonesaw
+            | Weapon::BostonBasher
+            | Weapon::Bottle
+            | Weapon::BreadBite
+            | Weapon::Bushwacka
+            | Weapon::CandyCane
+            | Weapon::ClaidheamhMor
+            | Weapon::ConniversKunai
+            | Weapon::ConscientiousObjector
+            | Weapon::CrossingGuard
+            | Weapon::DisciplinaryAction
+            | Weapon::Equalizer
+            | Weapon::EscapePlan
+            | Weapon::EurekaEffect
+            | Weapon::EvictionNotice
+            | Weapon::Eyelander
+            | Weapon::FanOWar
+            | Weapon::FireAxe
+            | Weapon::Fists
+            | Weapon::FistsOfSteel
+            | Weapon::FreedomStaff
+            | Weapon::FryingPan
+            | Weapon::GlovesOfRunningUrgently
+            | Weapon::GoldenFryingPan
+            | Weapon::GoldenWrench
+            | Weapon::Gunslinger
+            | Weapon::HalfZatoichi
+            | Weapon::HamShank
+            | Weapon::HolidayPunch
+            | Weapon::HolyMackerel
+            | Weapon::Homewrecker
+            | Weapon::HorselessHeadlessHorsemannsHeadtaker
+            | Weapon::HotHand
+            | Weapon::Jag
+            | Weapon::KillingGlovesOfBoxing
+            | Weapon::Knife
+            | Weapon::Kukri
+            | Weapon::Lollichop
+            | Weapon::MarketGardener
+            | Weapon::Maul
+            | Weapon::MemoryMaker
+            | Weapon::NecroSmasher
+            | Weapon::NeonAnnihilator
+            | Weapon::NessiesNineIron
+            | Weapon::PainTrain
+            | Weapon::PersianPersuader
+            | Weapon::PostalPummeler
+            | Weapon::Powerjack
+            | Weapon::PrinnyMachete
+            | Weapon::Sandman
+            | Weapon::Saxxy
+            | Weapon::ScotsmansSkullcutter
+            | Weapon::ScottishHandshake
+            | Weapon::Shahanshah
+            | Weapon::SharpDresser
+            | Weapon::SharpenedVolcanoFragment
+            | Weapon::Shovel
+            | Weapon::SolemnVow
+            | Weapon::SouthernHospitality
+            | Weapon::Spycicle
+            | Weapon::SunOnAStick
+            | Weapon::ThirdDegree
+            | Weapon::ThreeRuneBlade
+            | Weapon::TribalmansShiv
+            | Weapon::Ubersaw
+            | Weapon::UllapoolCaber
+            | Weapon::UnarmedCombat
+            | Weapon::VitaSaw
+            | Weapon::WangaPrick
+            | Weapon::WarriorsSpirit
+            | Weapon::WrapAssassin
+            | Weapon::Wrench
+            | Weapon::YourEternalReward => WeaponKind::Melee,
+            _ => WeaponKind::Other,
+        }
+    }
+}
+
+// Some weapons kill with more than one kind of attack, so the kind depends on the kill icon and not just the weapon
+pub fn kill_icon_kind(kill_icon: &str) -> WeaponKind {
+    match kill_icon {
+        "ball" | "guillotine" | "loose_cannon_impact" => WeaponKind::Projectile,
+        "ullapool_caber_explosion" | "loose_cannon_explosion" => WeaponKind::Other,
+        _ if kill_icon.starts_with("deflect_") || kill_icon.ends_with("_reflect") => WeaponKind::Projectile,
+        _ => kill_icon_to_weapon(kill_icon).kind(),
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum WeaponGroup {
+    RocketLaunchers,
+    Melee,
+    Hitscan,
+    Projectile,
+    Sentry,
+}
+
+impl WeaponGroup {
+    pub const ALL: [WeaponGroup; 5] = [
+        WeaponGroup::RocketLaunchers,
+        WeaponGroup::Melee,
+        WeaponGroup::Hitscan,
+        WeaponGroup::Projectile,
+        WeaponGroup::Sentry,
+    ];
+
+    pub fn contains(&self, kill_icon: &str) -> bool {
+        match self {
+            WeaponGroup::RocketLaunchers => kill_icon_to_weapon(kill_icon).is_rocket_launcher(),
+            WeaponGroup::Melee => kill_icon_kind(kill_icon) == WeaponKind::Melee,
+            WeaponGroup::Hitscan => kill_icon_kind(kill_icon) == WeaponKind::Hitscan,
+            WeaponGroup::Projectile => kill_icon_kind(kill_icon) == WeaponKind::Projectile,
+            // Kills made while wrangling the sentry get their own kill icon
+            WeaponGroup::Sentry => matches!(kill_icon_to_weapon(kill_icon), Weapon::SentryGun | Weapon::Wrangler),
+        }
+    }
+}
+
+impl Display for WeaponGroup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WeaponGroup::RocketLaunchers => write!(f, "Rocket launchers"),
+            WeaponGroup::Melee => write!(f, "Melee"),
+            WeaponGroup::Hitscan => write!(f, "Hitscan"),
+            WeaponGroup::Projectile => write!(f, "Projectile"),
+            WeaponGroup::Sentry => write!(f, "Sentry"),
+        }
+    }
+}